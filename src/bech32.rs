@@ -0,0 +1,250 @@
+use std::fmt;
+
+const CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+// The constant the polymod of a valid string must come out to
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
+const MAX_LENGTH: usize = 90;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Variant {
+    // BIP173, for version 0 witness programs
+    Bech32,
+    // BIP350, for version 1 and up
+    Bech32m,
+}
+
+impl Variant {
+    fn constant(self) -> u32 {
+        match self {
+            Variant::Bech32 => BECH32_CONST,
+            Variant::Bech32m => BECH32M_CONST,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Bech32Error {
+    MixedCase,
+    InvalidCharacter(char),
+    InvalidLength(usize),
+    MissingSeparator,
+    BadChecksum,
+    InvalidProgram,
+}
+
+impl fmt::Display for Bech32Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bech32Error::MixedCase => write!(f, "Bech32 string mixes upper and lower case"),
+            Bech32Error::InvalidCharacter(c) => write!(f, "Invalid bech32 character {:?}", c),
+            Bech32Error::InvalidLength(len) => write!(f, "Bech32 string of {} characters is too long or short", len),
+            Bech32Error::MissingSeparator => write!(f, "Bech32 string has no separator"),
+            Bech32Error::BadChecksum => write!(f, "Bech32 checksum does not match"),
+            Bech32Error::InvalidProgram => write!(f, "Invalid witness version or program"),
+        }
+    }
+}
+
+// BCH checksum over GF(32): each value shifts the 30-bit state left by 5,
+// and the bits shifted out select which generators to xor in
+fn polymod(values: &[u8]) -> u32 {
+    let mut chk = 1u32;
+    for &value in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+// High bits of each character, a zero, then the low bits
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut result: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    result.push(0);
+    result.extend(hrp.bytes().map(|c| c & 31));
+    result
+}
+
+// data is already in 5-bit groups
+pub fn encode(hrp: &str, data: &[u8], variant: Variant) -> String {
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(data);
+    values.extend_from_slice(&[0; 6]);
+    let checksum = polymod(&values) ^ variant.constant();
+    let mut result = format!("{}1", hrp);
+    for &value in data {
+        result.push(CHARSET[value as usize] as char);
+    }
+    for i in 0..6 {
+        result.push(CHARSET[((checksum >> (5 * (5 - i))) & 31) as usize] as char);
+    }
+    result
+}
+
+// The human readable part, the 5-bit data without the checksum, and which
+// checksum it was
+pub fn decode(s: &str) -> Result<(String, Vec<u8>, Variant), Bech32Error> {
+    if s.len() > MAX_LENGTH {
+        return Err(Bech32Error::InvalidLength(s.len()));
+    }
+    if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(Bech32Error::MixedCase);
+    }
+    if let Some(c) = s.chars().find(|c| !('!'..='~').contains(c)) {
+        return Err(Bech32Error::InvalidCharacter(c));
+    }
+    let s = s.to_ascii_lowercase();
+    let separator = s.rfind('1').ok_or(Bech32Error::MissingSeparator)?;
+    if separator == 0 || separator + 7 > s.len() {
+        return Err(Bech32Error::InvalidLength(s.len()));
+    }
+    let (hrp, rest) = (&s[..separator], &s[separator + 1..]);
+    let mut data = Vec::with_capacity(rest.len());
+    for c in rest.chars() {
+        let value = CHARSET.iter().position(|&x| x as char == c).ok_or(Bech32Error::InvalidCharacter(c))?;
+        data.push(value as u8);
+    }
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(&data);
+    let variant = match polymod(&values) {
+        BECH32_CONST => Variant::Bech32,
+        BECH32M_CONST => Variant::Bech32m,
+        _ => return Err(Bech32Error::BadChecksum),
+    };
+    data.truncate(data.len() - 6);
+    Ok((hrp.to_string(), data, variant))
+}
+
+// Regroups bits, most significant first. Without padding, leftover bits
+// must be fewer than from_bits and all zero.
+fn convert_bits(data: &[u8], from_bits: u32, to_bits: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut result = Vec::new();
+    let max = (1u32 << to_bits) - 1;
+    for &value in data {
+        if (value as u32) >> from_bits != 0 {
+            return None;
+        }
+        acc = (acc << from_bits) | value as u32;
+        bits += from_bits;
+        while bits >= to_bits {
+            bits -= to_bits;
+            result.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            result.push(((acc << (to_bits - bits)) & max) as u8);
+        }
+    } else if bits >= from_bits || (acc << (to_bits - bits)) & max != 0 {
+        return None;
+    }
+    Some(result)
+}
+
+fn segwit_hrp(testnet: bool) -> &'static str {
+    if testnet {
+        "tb"
+    } else {
+        "bc"
+    }
+}
+
+// Version 0 programs use bech32, later versions bech32m
+pub fn encode_segwit_address(version: u8, program: &[u8], testnet: bool) -> String {
+    let variant = if version == 0 { Variant::Bech32 } else { Variant::Bech32m };
+    let mut data = vec![version];
+    data.extend(convert_bits(program, 8, 5, true).expect("bytes always regroup into 5 bits"));
+    encode(segwit_hrp(testnet), &data, variant)
+}
+
+// Whether the address is for testnet, its witness version and program
+pub fn decode_segwit_address(address: &str) -> Result<(bool, u8, Vec<u8>), Bech32Error> {
+    let (hrp, data, variant) = decode(address)?;
+    let testnet = match hrp.as_str() {
+        "bc" => false,
+        "tb" => true,
+        _ => return Err(Bech32Error::InvalidProgram),
+    };
+    let [version, rest @ ..] = data.as_slice() else {
+        return Err(Bech32Error::InvalidProgram);
+    };
+    let program = convert_bits(rest, 5, 8, false).ok_or(Bech32Error::InvalidProgram)?;
+    let valid = match version {
+        0 => variant == Variant::Bech32 && (program.len() == 20 || program.len() == 32),
+        1..=16 => variant == Variant::Bech32m && (2..=40).contains(&program.len()),
+        _ => false,
+    };
+    if !valid {
+        return Err(Bech32Error::InvalidProgram);
+    }
+    Ok((testnet, *version, program))
+}
+
+// p2wsh pays to the sha256 of the witness script
+pub fn p2wsh_address(script_hash: &[u8; 32], testnet: bool) -> String {
+    encode_segwit_address(0, script_hash, testnet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{decode_hex, encode_hex};
+
+    // Checksum vectors from BIP173 and BIP350
+    #[test]
+    fn checksum_vectors() {
+        for valid in [
+            "A12UEL5L",
+            "a12uel5l",
+            "an83characterlonghumanreadablepartthatcontainsthenumber1andtheexcludedcharactersbio1tt5tgs",
+            "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw",
+            "split1checkupstagehandshakeupstreamerranterredcaperred2y9e3w",
+        ] {
+            assert_eq!(decode(valid).unwrap().2, Variant::Bech32, "{}", valid);
+        }
+        for valid in [
+            "A1LQFN3A",
+            "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx",
+            "split1checkupstagehandshakeupstreamerranterredcaperredlc445v",
+        ] {
+            assert_eq!(decode(valid).unwrap().2, Variant::Bech32m, "{}", valid);
+        }
+        for invalid in ["pzry9x0s0muk", "1pzry9x0s0muk", "x1b4n0q5v", "li1dgmt3", "A1G7SGD8", "10a06t8", "1qzzfhee", "a12UEL5L"] {
+            assert!(decode(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn segwit_addresses() {
+        let (testnet, version, program) = decode_segwit_address("BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4").unwrap();
+        assert!(!testnet);
+        assert_eq!(version, 0);
+        assert_eq!(encode_hex(&program), "751e76e8199196d454941c45d1b3a323f1433bd6");
+        assert_eq!(encode_segwit_address(0, &program, false), "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4");
+        let script_hash = decode_hex("1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262").unwrap();
+        assert_eq!(
+            p2wsh_address(&script_hash.try_into().unwrap(), true),
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"
+        );
+        let taproot = "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y";
+        assert_eq!(decode_segwit_address(taproot).unwrap().1, 1);
+        assert_eq!(decode_segwit_address("bc1zw508d6qejxtdg4y5r3zarvaryvaxxpcs").unwrap().1, 2);
+    }
+
+    #[test]
+    fn segwit_address_variant_must_match_version() {
+        // Version 1 with a bech32 checksum, version 0 with bech32m
+        assert!(decode_segwit_address("bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh").is_err());
+        assert!(decode_segwit_address("tb1q0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq24jc47").is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt;
+
+use crate::ecc::{PrivateKey, S256Point, G, N};
+use crate::hashes::{hash160, hmac_sha512};
+use crate::u256::U256;
+use crate::util::{decode_base58_checksum, encode_base58_checksum, Base58Error};
+
+// Child numbers from here up are hardened
+pub const HARDENED: u32 = 0x8000_0000;
+
+const XPRV_VERSION: [u8; 4] = [0x04, 0x88, 0xad, 0xe4];
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TPRV_VERSION: [u8; 4] = [0x04, 0x35, 0x83, 0x94];
+const TPUB_VERSION: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+
+// Version, depth, parent fingerprint, child number, chain code and key
+const EXTENDED_KEY_SIZE: usize = 4 + 1 + 4 + 4 + 32 + 33;
+
+#[derive(Debug)]
+pub enum Bip32Error {
+    InvalidSeedLength(usize),
+    IndexOutOfRange(u32),
+    HardenedFromPublic,
+    InvalidKey,
+    InvalidExtendedKey(&'static str),
+    Base58(Base58Error),
+}
+
+impl fmt::Display for Bip32Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bip32Error::InvalidSeedLength(len) => write!(f, "Seed of {} bytes is not between 16 and 64 bytes", len),
+            Bip32Error::IndexOutOfRange(index) => write!(f, "Child index {} does not fit in 31 bits", index),
+            Bip32Error::HardenedFromPublic => write!(f, "Hardened children need the private key"),
+            Bip32Error::InvalidKey => write!(f, "Derived key is invalid, skip to the next index"),
+            Bip32Error::InvalidExtendedKey(reason) => write!(f, "Invalid extended key: {}", reason),
+            Bip32Error::Base58(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<Base58Error> for Bip32Error {
+    fn from(err: Base58Error) -> Bip32Error {
+        Bip32Error::Base58(err)
+    }
+}
+
+#[derive(Clone)]
+pub struct ExtendedPrivateKey {
+    pub testnet: bool,
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: u32,
+    pub chain_code: [u8; 32],
+    pub private_key: PrivateKey,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExtendedPublicKey {
+    pub testnet: bool,
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: u32,
+    pub chain_code: [u8; 32],
+    pub public_key: S256Point,
+}
+
+impl ExtendedPrivateKey {
+    // The master key m
+    pub fn from_seed(seed: &[u8], testnet: bool) -> Result<ExtendedPrivateKey, Bip32Error> {
+        if !(16..=64).contains(&seed.len()) {
+            return Err(Bip32Error::InvalidSeedLength(seed.len()));
+        }
+        let (secret, chain_code) = split_hmac(b"Bitcoin seed", seed);
+        if secret.is_zero() || secret >= N {
+            return Err(Bip32Error::InvalidKey);
+        }
+        Ok(ExtendedPrivateKey {
+            testnet,
+            depth: 0,
+            parent_fingerprint: [0; 4],
+            child_number: 0,
+            chain_code,
+            private_key: PrivateKey::new(secret),
+        })
+    }
+
+    // index is below 2^31, hardened adds HARDENED to it
+    pub fn derive_child(&self, index: u32, hardened: bool) -> Result<ExtendedPrivateKey, Bip32Error> {
+        let child_number = child_number(index, hardened)?;
+        let mut data = Vec::with_capacity(37);
+        if hardened {
+            data.push(0x00);
+            data.extend_from_slice(&self.private_key.secret().to_be_bytes());
+        } else {
+            data.extend_from_slice(&self.private_key.point.sec(true));
+        }
+        data.extend_from_slice(&child_number.to_be_bytes());
+        let (tweak, chain_code) = split_hmac(&self.chain_code, &data);
+        if tweak >= N {
+            return Err(Bip32Error::InvalidKey);
+        }
+        let secret = tweak.add_mod(self.private_key.secret(), N);
+        if secret.is_zero() {
+            return Err(Bip32Error::InvalidKey);
+        }
+        Ok(ExtendedPrivateKey {
+            testnet: self.testnet,
+            depth: self.depth.checked_add(1).ok_or(Bip32Error::InvalidKey)?,
+            parent_fingerprint: fingerprint(&self.private_key.point),
+            child_number,
+            chain_code,
+            private_key: PrivateKey::new(secret),
+        })
+    }
+
+    pub fn extended_public_key(&self) -> ExtendedPublicKey {
+        ExtendedPublicKey {
+            testnet: self.testnet,
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+            public_key: self.private_key.point,
+        }
+    }
+
+    pub fn fingerprint(&self) -> [u8; 4] {
+        fingerprint(&self.private_key.point)
+    }
+
+    pub fn to_xprv(&self) -> String {
+        let version = if self.testnet { TPRV_VERSION } else { XPRV_VERSION };
+        let mut key = vec![0x00];
+        key.extend_from_slice(&self.private_key.secret().to_be_bytes());
+        serialize(version, self.depth, self.parent_fingerprint, self.child_number, &self.chain_code, &key)
+    }
+
+    pub fn to_xpub(&self) -> String {
+        self.extended_public_key().to_xpub()
+    }
+
+    pub fn parse(xprv: &str) -> Result<ExtendedPrivateKey, Bip32Error> {
+        let (version, depth, parent_fingerprint, child_number, chain_code, key) = deserialize(xprv)?;
+        let testnet = match version {
+            XPRV_VERSION => false,
+            TPRV_VERSION => true,
+            _ => return Err(Bip32Error::InvalidExtendedKey("not a private key version")),
+        };
+        let [0x00, secret @ ..] = key else {
+            return Err(Bip32Error::InvalidExtendedKey("private key must start with a zero byte"));
+        };
+        let secret = U256::from_be_bytes(secret);
+        if secret.is_zero() || secret >= N {
+            return Err(Bip32Error::InvalidKey);
+        }
+        Ok(ExtendedPrivateKey {
+            testnet,
+            depth,
+            parent_fingerprint,
+            child_number,
+            chain_code,
+            private_key: PrivateKey::new(secret),
+        })
+    }
+}
+
+impl fmt::Debug for ExtendedPrivateKey {
+    // Leave the secret out of logs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedPrivateKey")
+            .field("testnet", &self.testnet)
+            .field("depth", &self.depth)
+            .field("parent_fingerprint", &self.parent_fingerprint)
+            .field("child_number", &self.child_number)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ExtendedPublicKey {
+    // Only non-hardened children can be derived without the private key
+    pub fn derive_child(&self, index: u32) -> Result<ExtendedPublicKey, Bip32Error> {
+        if index >= HARDENED {
+            return Err(Bip32Error::HardenedFromPublic);
+        }
+        let child_number = index;
+        let mut data = self.public_key.sec(true);
+        data.extend_from_slice(&child_number.to_be_bytes());
+        let (tweak, chain_code) = split_hmac(&self.chain_code, &data);
+        if tweak >= N {
+            return Err(Bip32Error::InvalidKey);
+        }
+        let public_key = G * tweak + self.public_key;
+        if public_key.is_infinity() {
+            return Err(Bip32Error::InvalidKey);
+        }
+        Ok(ExtendedPublicKey {
+            testnet: self.testnet,
+            depth: self.depth.checked_add(1).ok_or(Bip32Error::InvalidKey)?,
+            parent_fingerprint: fingerprint(&self.public_key),
+            child_number,
+            chain_code,
+            public_key,
+        })
+    }
+
+    pub fn fingerprint(&self) -> [u8; 4] {
+        fingerprint(&self.public_key)
+    }
+
+    pub fn to_xpub(&self) -> String {
+        let version = if self.testnet { TPUB_VERSION } else { XPUB_VERSION };
+        let key = self.public_key.sec(true);
+        serialize(version, self.depth, self.parent_fingerprint, self.child_number, &self.chain_code, &key)
+    }
+
+    pub fn parse(xpub: &str) -> Result<ExtendedPublicKey, Bip32Error> {
+        let (version, depth, parent_fingerprint, child_number, chain_code, key) = deserialize(xpub)?;
+        let testnet = match version {
+            XPUB_VERSION => false,
+            TPUB_VERSION => true,
+            _ => return Err(Bip32Error::InvalidExtendedKey("not a public key version")),
+        };
+        if !matches!(key[0], 0x02 | 0x03) {
+            return Err(Bip32Error::InvalidExtendedKey("public key must be compressed"));
+        }
+        let public_key = S256Point::parse(&key).map_err(|_| Bip32Error::InvalidKey)?;
+        Ok(ExtendedPublicKey { testnet, depth, parent_fingerprint, child_number, chain_code, public_key })
+    }
+}
+
+fn child_number(index: u32, hardened: bool) -> Result<u32, Bip32Error> {
+    if index >= HARDENED {
+        return Err(Bip32Error::IndexOutOfRange(index));
+    }
+    Ok(if hardened { index | HARDENED } else { index })
+}
+
+// HMAC-SHA512 split into the key material (left) and chain code (right)
+fn split_hmac(key: &[u8], data: &[u8]) -> (U256, [u8; 32]) {
+    let hmac = hmac_sha512(key, data);
+    let mut left = [0u8; 32];
+    let mut chain_code = [0u8; 32];
+    left.copy_from_slice(&hmac[..32]);
+    chain_code.copy_from_slice(&hmac[32..]);
+    (U256::from_be_bytes(left), chain_code)
+}
+
+// First four bytes of the hash160 of the compressed SEC
+fn fingerprint(point: &S256Point) -> [u8; 4] {
+    let h160 = hash160(&point.sec(true));
+    [h160[0], h160[1], h160[2], h160[3]]
+}
+
+fn serialize(
+    version: [u8; 4],
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+    chain_code: &[u8; 32],
+    key: &[u8],
+) -> String {
+    let mut payload = Vec::with_capacity(EXTENDED_KEY_SIZE);
+    payload.extend_from_slice(&version);
+    payload.push(depth);
+    payload.extend_from_slice(&parent_fingerprint);
+    payload.extend_from_slice(&child_number.to_be_bytes());
+    payload.extend_from_slice(chain_code);
+    payload.extend_from_slice(key);
+    encode_base58_checksum(&payload)
+}
+
+type ExtendedKeyFields = ([u8; 4], u8, [u8; 4], u32, [u8; 32], [u8; 33]);
+
+fn deserialize(s: &str) -> Result<ExtendedKeyFields, Bip32Error> {
+    let payload = decode_base58_checksum(s)?;
+    if payload.len() != EXTENDED_KEY_SIZE {
+        return Err(Bip32Error::InvalidExtendedKey("wrong length"));
+    }
+    let mut version = [0u8; 4];
+    let mut parent_fingerprint = [0u8; 4];
+    let mut child_number = [0u8; 4];
+    let mut chain_code = [0u8; 32];
+    let mut key = [0u8; 33];
+    version.copy_from_slice(&payload[..4]);
+    let depth = payload[4];
+    parent_fingerprint.copy_from_slice(&payload[5..9]);
+    child_number.copy_from_slice(&payload[9..13]);
+    chain_code.copy_from_slice(&payload[13..45]);
+    key.copy_from_slice(&payload[45..]);
+    let child_number = u32::from_be_bytes(child_number);
+    if depth == 0 && (parent_fingerprint != [0; 4] || child_number != 0) {
+        return Err(Bip32Error::InvalidExtendedKey("master key with a parent"));
+    }
+    Ok((version, depth, parent_fingerprint, child_number, chain_code, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::decode_hex;
+
+    // BIP32 test vector 1: m/0'/1
+    #[test]
+    fn derivation_vector() {
+        let master = ExtendedPrivateKey::from_seed(&decode_hex("000102030405060708090a0b0c0d0e0f").unwrap(), false).unwrap();
+        assert_eq!(master.to_xpub(), "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8");
+        assert_eq!(master.to_xprv(), "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi");
+        let hardened = master.derive_child(0, true).unwrap();
+        assert_eq!(hardened.to_xpub(), "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw");
+        assert_eq!(hardened.to_xprv(), "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7");
+        let child = hardened.derive_child(1, false).unwrap();
+        assert_eq!(child.to_xpub(), "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ");
+        assert_eq!(child.to_xprv(), "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs");
+        // Public derivation reaches the same non-hardened child
+        let xpub = ExtendedPublicKey::parse(&hardened.to_xpub()).unwrap();
+        assert_eq!(xpub.derive_child(1).unwrap(), child.extended_public_key());
+        assert!(matches!(xpub.derive_child(HARDENED), Err(Bip32Error::HardenedFromPublic)));
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let xprv = "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs";
+        assert_eq!(ExtendedPrivateKey::parse(xprv).unwrap().to_xprv(), xprv);
+        assert!(ExtendedPublicKey::parse(xprv).is_err());
+    }
+}
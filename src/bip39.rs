@@ -0,0 +1,105 @@
+use std::fmt;
+
+use crate::hashes::{pbkdf2_hmac_sha512, sha256};
+
+const ENGLISH: &str = include_str!("wordlists/english.txt");
+
+const PBKDF2_ROUNDS: u32 = 2048;
+
+#[derive(Debug)]
+pub enum Bip39Error {
+    InvalidWordCount(usize),
+    UnknownWord(String),
+    BadChecksum,
+}
+
+impl fmt::Display for Bip39Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bip39Error::InvalidWordCount(count) => write!(f, "Mnemonic of {} words is not 12, 15, 18, 21 or 24", count),
+            Bip39Error::UnknownWord(word) => write!(f, "{:?} is not in the wordlist", word),
+            Bip39Error::BadChecksum => write!(f, "Mnemonic checksum does not match"),
+        }
+    }
+}
+
+// The 2048 words, in order, so a word's index is its 11-bit value
+pub fn wordlist() -> Vec<&'static str> {
+    ENGLISH.lines().collect()
+}
+
+// Seeds are derived from the text alone, so any mnemonic works here and
+// validate_mnemonic is a separate check. Both strings are expected to be
+// NFKD normalized already, which ASCII always is.
+pub fn mnemonic_to_seed(mnemonic: &str, passphrase: &str) -> [u8; 64] {
+    let salt = format!("mnemonic{}", passphrase);
+    pbkdf2_hmac_sha512(mnemonic.as_bytes(), salt.as_bytes(), PBKDF2_ROUNDS)
+}
+
+// Every word is in the wordlist and the last word's low bits match the
+// first bits of the sha256 of the entropy, one checksum bit per 32
+// bits of entropy
+pub fn validate_mnemonic(mnemonic: &str) -> Result<(), Bip39Error> {
+    let words: Vec<&str> = mnemonic.split_whitespace().collect();
+    if !matches!(words.len(), 12 | 15 | 18 | 21 | 24) {
+        return Err(Bip39Error::InvalidWordCount(words.len()));
+    }
+    let wordlist = wordlist();
+    let mut bits = Vec::with_capacity(words.len() * 11);
+    for word in &words {
+        let index = wordlist
+            .binary_search(word)
+            .map_err(|_| Bip39Error::UnknownWord(word.to_string()))?;
+        bits.extend((0..11).rev().map(|i| (index >> i) & 1 == 1));
+    }
+    let checksum_len = bits.len() / 33;
+    let (entropy_bits, checksum) = bits.split_at(bits.len() - checksum_len);
+    let entropy: Vec<u8> = entropy_bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0u8, |acc, &bit| (acc << 1) | bit as u8))
+        .collect();
+    let hash = sha256(&entropy);
+    let expected = (0..checksum_len).map(|i| (hash[i / 8] >> (7 - i % 8)) & 1 == 1);
+    if !expected.eq(checksum.iter().copied()) {
+        return Err(Bip39Error::BadChecksum);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::encode_hex;
+
+    // From the BIP39 reference vectors, all with passphrase TREZOR
+    #[test]
+    fn seed_vectors() {
+        for (mnemonic, seed) in [
+            (
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            ),
+            (
+                "legal winner thank year wave sausage worth useful legal winner thank yellow",
+                "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+            ),
+            (
+                "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+                "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e1613912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad",
+            ),
+        ] {
+            validate_mnemonic(mnemonic).unwrap();
+            assert_eq!(encode_hex(&mnemonic_to_seed(mnemonic, "TREZOR")), seed);
+        }
+    }
+
+    #[test]
+    fn invalid_mnemonics() {
+        let bad_checksum = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
+        assert!(matches!(validate_mnemonic(bad_checksum), Err(Bip39Error::BadChecksum)));
+        assert!(matches!(validate_mnemonic("abandon"), Err(Bip39Error::InvalidWordCount(1))));
+        let unknown = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon bitcoin";
+        assert!(matches!(validate_mnemonic(unknown), Err(Bip39Error::UnknownWord(_))));
+        assert_eq!(wordlist().len(), 2048);
+    }
+}
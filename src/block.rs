@@ -0,0 +1,808 @@
+use std::fmt;
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::ParseError;
+use crate::hashes::hash256;
+use crate::merkle::{merkle_root, MerkleProof, MerkleTree};
+use crate::tx::Tx;
+use crate::u256::U256;
+use crate::util::{encode_hex, encode_varint, read_varint};
+
+pub const TWO_WEEKS: u32 = 60 * 60 * 24 * 14;
+
+// Headers per difficulty epoch
+pub const RETARGET_INTERVAL: usize = 2016;
+
+// On testnet a block more than 20 minutes after its parent may use the
+// max target
+const TESTNET_MIN_DIFFICULTY_SPACING: u32 = 20 * 60;
+
+// A header's timestamp must be above the median of this many before it
+const MEDIAN_TIME_SPAN: usize = 11;
+
+// and at most this far ahead of the local clock
+const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+
+pub const HALVING_INTERVAL: u64 = 210_000;
+const INITIAL_SUBSIDY: u64 = 50 * 100_000_000;
+
+// Heights from which BIP34 requires the coinbase to start with the height
+const BIP34_HEIGHT: u64 = 227_931;
+const TESTNET_BIP34_HEIGHT: u64 = 21_111;
+
+// The 80-byte genesis headers. Both share a merkle root and differ only
+// in timestamp and nonce.
+pub const GENESIS_BLOCK: [u8; 80] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x3b, 0xa3, 0xed, 0xfd, 0x7a, 0x7b, 0x12, 0xb2, 0x7a, 0xc7, 0x2c, 0x3e,
+    0x67, 0x76, 0x8f, 0x61, 0x7f, 0xc8, 0x1b, 0xc3, 0x88, 0x8a, 0x51, 0x32, 0x3a, 0x9f, 0xb8, 0xaa,
+    0x4b, 0x1e, 0x5e, 0x4a, 0x29, 0xab, 0x5f, 0x49, 0xff, 0xff, 0x00, 0x1d, 0x1d, 0xac, 0x2b, 0x7c,
+];
+
+pub const TESTNET_GENESIS_BLOCK: [u8; 80] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x3b, 0xa3, 0xed, 0xfd, 0x7a, 0x7b, 0x12, 0xb2, 0x7a, 0xc7, 0x2c, 0x3e,
+    0x67, 0x76, 0x8f, 0x61, 0x7f, 0xc8, 0x1b, 0xc3, 0x88, 0x8a, 0x51, 0x32, 0x3a, 0x9f, 0xb8, 0xaa,
+    0x4b, 0x1e, 0x5e, 0x4a, 0xda, 0xe5, 0x49, 0x4d, 0xff, 0xff, 0x00, 0x1d, 0x1a, 0xa4, 0xae, 0x18,
+];
+
+// BIP9 versions have 001 in the top three bits, leaving bits 0 to 28 for
+// deployments to signal on
+const BIP9_TOP_BITS: u32 = 0b001;
+const BIP9_DEPLOYMENT_BITS: u8 = 29;
+
+// prev_block and merkle_root are kept in display (big-endian) order,
+// the wire format stores them reversed
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub prev_block: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub timestamp: u32,
+    pub bits: [u8; 4],
+    pub nonce: [u8; 4],
+}
+
+impl BlockHeader {
+    pub fn parse<R: Read>(reader: &mut R) -> Result<BlockHeader, ParseError> {
+        let mut buf = [0u8; 80];
+        reader.read_exact(&mut buf)?;
+
+        let mut prev_block = [0u8; 32];
+        prev_block.copy_from_slice(&buf[4..36]);
+        prev_block.reverse();
+        let mut merkle_root = [0u8; 32];
+        merkle_root.copy_from_slice(&buf[36..68]);
+        merkle_root.reverse();
+
+        Ok(BlockHeader {
+            version: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            prev_block,
+            merkle_root,
+            timestamp: u32::from_le_bytes([buf[68], buf[69], buf[70], buf[71]]),
+            bits: [buf[72], buf[73], buf[74], buf[75]],
+            nonce: [buf[76], buf[77], buf[78], buf[79]],
+        })
+    }
+
+    pub fn serialize(&self) -> [u8; 80] {
+        let mut result = [0u8; 80];
+        result[0..4].copy_from_slice(&self.version.to_le_bytes());
+        let mut prev_block = self.prev_block;
+        prev_block.reverse();
+        result[4..36].copy_from_slice(&prev_block);
+        let mut merkle_root = self.merkle_root;
+        merkle_root.reverse();
+        result[36..68].copy_from_slice(&merkle_root);
+        result[68..72].copy_from_slice(&self.timestamp.to_le_bytes());
+        result[72..76].copy_from_slice(&self.bits);
+        result[76..80].copy_from_slice(&self.nonce);
+        result
+    }
+
+    // Block hash in display order
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hash = hash256(&self.serialize());
+        hash.reverse();
+        hash
+    }
+
+    pub fn id(&self) -> String {
+        encode_hex(&self.hash())
+    }
+
+    pub fn target(&self) -> U256 {
+        bits_to_target(self.bits)
+    }
+
+    // The bits for the epoch after the one running from first to last.
+    // A last timestamp before the first counts as no time at all.
+    pub fn new_epoch_bits(first: &BlockHeader, last: &BlockHeader) -> [u8; 4] {
+        calculate_new_bits(last.bits, last.timestamp.saturating_sub(first.timestamp) as u64)
+    }
+
+    pub fn difficulty(&self) -> f64 {
+        difficulty(self.bits)
+    }
+
+    pub fn bip9(&self) -> bool {
+        self.version >> 29 == BIP9_TOP_BITS
+    }
+
+    // Segwit lock-in via BIP91
+    pub fn bip91(&self) -> bool {
+        self.signals_bit(4) == Some(true)
+    }
+
+    pub fn bip141(&self) -> bool {
+        self.signals_bit(1) == Some(true)
+    }
+
+    // None when the version isn't BIP9 or there is no such deployment bit
+    pub fn signals_bit(&self, bit: u8) -> Option<bool> {
+        if !self.bip9() || bit >= BIP9_DEPLOYMENT_BITS {
+            return None;
+        }
+        Some((self.version >> bit) & 1 == 1)
+    }
+
+    // Expected number of hashes to find a header meeting this target,
+    // 2^256 / (target + 1). No hash is below a zero target, so such a
+    // header counts for no work rather than overflowing.
+    pub fn work(&self) -> U256 {
+        let target = self.target();
+        if target.is_zero() {
+            return U256::ZERO;
+        }
+        (U256::MAX - target) / (target + U256::ONE) + U256::ONE
+    }
+
+    // The hash read as a little-endian number must be below the target
+    pub fn check_pow(&self) -> bool {
+        U256::from_hash(hash256(&self.serialize())).less_than(&self.target())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChainError {
+    BadLinkage,
+    WrongBits { expected: [u8; 4], actual: [u8; 4] },
+    InsufficientPow,
+    TimeTooOld { median_time_past: u32 },
+    TimeTooNew { now: u64 },
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::BadLinkage => write!(f, "Header does not build on the chain tip"),
+            ChainError::WrongBits { expected, actual } => {
+                write!(f, "Header has bits {:02x?}, expected {:02x?}", actual, expected)
+            }
+            ChainError::InsufficientPow => write!(f, "Header hash does not meet its target"),
+            ChainError::TimeTooOld { median_time_past } => {
+                write!(f, "Header timestamp is not after the median time past {}", median_time_past)
+            }
+            ChainError::TimeTooNew { now } => write!(f, "Header timestamp is over two hours after {}", now),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BlockError {
+    MissingCoinbase,
+    WrongHeight { expected: u64, actual: Option<u64> },
+    ExcessiveCoinbase { allowed: u64, actual: u64 },
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::MissingCoinbase => write!(f, "Block does not start with a coinbase"),
+            BlockError::WrongHeight { expected, actual: Some(actual) } => {
+                write!(f, "Coinbase gives height {}, expected {}", actual, expected)
+            }
+            BlockError::WrongHeight { expected, actual: None } => {
+                write!(f, "Coinbase gives no height, expected {}", expected)
+            }
+            BlockError::ExcessiveCoinbase { allowed, actual } => {
+                write!(f, "Coinbase pays out {} satoshis, at most {} allowed", actual, allowed)
+            }
+        }
+    }
+}
+
+// A header followed by its transactions, the first being the coinbase
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub txs: Vec<Tx>,
+}
+
+impl Block {
+    pub fn parse<R: Read>(reader: &mut R, testnet: bool) -> Result<Block, ParseError> {
+        let header = BlockHeader::parse(reader)?;
+        let count = read_varint(reader)?;
+        // Each transaction takes at least 60 bytes, so don't trust the count
+        // for the allocation
+        let mut txs = Vec::with_capacity(count.min(4096) as usize);
+        for _ in 0..count {
+            txs.push(Tx::parse(reader, testnet)?);
+        }
+        Ok(Block { header, txs })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.header.serialize().to_vec();
+        result.extend(encode_varint(self.txs.len() as u64));
+        for tx in &self.txs {
+            result.extend(tx.serialize());
+        }
+        result
+    }
+
+    // The txids in display order
+    pub fn tx_hashes(&self) -> Vec<[u8; 32]> {
+        self.txs.iter().map(Tx::hash).collect()
+    }
+
+    // Hashing works on the wire order, and so does the root it gives
+    fn wire_tx_hashes(&self) -> Vec<[u8; 32]> {
+        self.tx_hashes()
+            .into_iter()
+            .map(|mut hash| {
+                hash.reverse();
+                hash
+            })
+            .collect()
+    }
+
+    pub fn validate_merkle_root(&self) -> bool {
+        let Ok(mut root) = merkle_root(&self.wire_tx_hashes()) else {
+            return false;
+        };
+        root.reverse();
+        root == self.header.merkle_root
+    }
+
+    // Proof that the transaction with this txid (display order) is in the
+    // block. Like the rest of the merkle code the proof is in wire order, so
+    // it verifies against the reversed txid and header merkle root.
+    pub fn merkle_proof(&self, txid: &[u8; 32]) -> Option<MerkleProof> {
+        let index = self.tx_hashes().iter().position(|hash| hash == txid)?;
+        MerkleTree::from_leaves(&self.wire_tx_hashes()).proof_for(index)
+    }
+
+    // The coinbase may claim the subsidy for the block's height plus the
+    // fees of the other transactions, which the caller works out since
+    // that takes their previous outputs. Once BIP34 is active it must also
+    // commit to that height.
+    pub fn validate_coinbase(&self, height: u64, total_fees: u64) -> Result<(), BlockError> {
+        let coinbase = self.txs.first().filter(|tx| tx.is_coinbase()).ok_or(BlockError::MissingCoinbase)?;
+        let bip34_height = if coinbase.testnet { TESTNET_BIP34_HEIGHT } else { BIP34_HEIGHT };
+        if height >= bip34_height && coinbase.coinbase_height() != Some(height) {
+            return Err(BlockError::WrongHeight { expected: height, actual: coinbase.coinbase_height() });
+        }
+        let allowed = block_subsidy(height).saturating_add(total_fees);
+        // An overflowing sum is over any allowance
+        let actual = coinbase
+            .tx_outs
+            .iter()
+            .try_fold(0u64, |total, tx_out| total.checked_add(tx_out.amount))
+            .unwrap_or(u64::MAX);
+        if actual > allowed {
+            return Err(BlockError::ExcessiveCoinbase { allowed, actual });
+        }
+        Ok(())
+    }
+}
+
+// 50 BTC halved every 210,000 blocks. Shifting by 64 or more would
+// overflow, and the subsidy is long gone by then anyway.
+pub fn block_subsidy(height: u64) -> u64 {
+    let halvings = height / HALVING_INTERVAL;
+    if halvings >= 64 {
+        return 0;
+    }
+    INITIAL_SUBSIDY >> halvings
+}
+
+// Headers checked one by one on top of a genesis header. All of them are
+// kept, since retargeting looks back an epoch.
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    headers: Vec<BlockHeader>,
+    work: U256,
+    testnet: bool,
+}
+
+impl HeaderChain {
+    pub fn new(testnet: bool) -> HeaderChain {
+        let genesis = if testnet { TESTNET_GENESIS_BLOCK } else { GENESIS_BLOCK };
+        let genesis = BlockHeader::parse(&mut &genesis[..]).expect("80 bytes");
+        HeaderChain { headers: vec![genesis], work: genesis.work(), testnet }
+    }
+
+    pub fn tip(&self) -> &BlockHeader {
+        self.headers.last().expect("the chain starts at genesis")
+    }
+
+    // Genesis is height 0
+    pub fn height(&self) -> u64 {
+        self.headers.len() as u64 - 1
+    }
+
+    // Total work of every header, genesis included
+    pub fn work(&self) -> U256 {
+        self.work
+    }
+
+    // Time-based locktimes are judged against this rather than the tip's
+    // own timestamp, which miners have some leeway over
+    pub fn mtp(&self) -> u32 {
+        median_time_past(&self.headers)
+    }
+
+    pub fn add(&mut self, header: BlockHeader) -> Result<(), ChainError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        self.add_at(header, now)
+    }
+
+    // add with the current time in seconds given, rather than read from
+    // the system clock
+    pub fn add_at(&mut self, header: BlockHeader, now: u64) -> Result<(), ChainError> {
+        if header.prev_block != self.tip().hash() {
+            return Err(ChainError::BadLinkage);
+        }
+        let median_time_past = self.mtp();
+        if header.timestamp <= median_time_past {
+            return Err(ChainError::TimeTooOld { median_time_past });
+        }
+        if header.timestamp as u64 > now.saturating_add(MAX_FUTURE_BLOCK_TIME) {
+            return Err(ChainError::TimeTooNew { now });
+        }
+        let expected = self.next_bits(&header);
+        if header.bits != expected {
+            return Err(ChainError::WrongBits { expected, actual: header.bits });
+        }
+        if !header.check_pow() {
+            return Err(ChainError::InsufficientPow);
+        }
+        self.work = self.work + header.work();
+        self.headers.push(header);
+        Ok(())
+    }
+
+    // Retargets on epoch boundaries, otherwise carries the tip's bits.
+    // Testnet also lets a late header use the max target, and blocks that
+    // did so are skipped when looking back for the real bits.
+    fn next_bits(&self, header: &BlockHeader) -> [u8; 4] {
+        let height = self.headers.len();
+        let tip = self.tip();
+        if height.is_multiple_of(RETARGET_INTERVAL) {
+            return BlockHeader::new_epoch_bits(&self.headers[height - RETARGET_INTERVAL], tip);
+        }
+        if !self.testnet {
+            return tip.bits;
+        }
+        let max_bits = target_to_bits(max_target());
+        if header.timestamp > tip.timestamp.saturating_add(TESTNET_MIN_DIFFICULTY_SPACING) {
+            return max_bits;
+        }
+        self.headers
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(i, previous)| i.is_multiple_of(RETARGET_INTERVAL) || previous.bits != max_bits)
+            .map(|(_, previous)| previous.bits)
+            .expect("genesis is an epoch start")
+    }
+}
+
+// Median timestamp of the last 11 headers, or of all of them when there
+// are fewer. Zero for no headers.
+pub fn median_time_past(headers: &[BlockHeader]) -> u32 {
+    let start = headers.len().saturating_sub(MEDIAN_TIME_SPAN);
+    let mut timestamps: Vec<u32> = headers[start..].iter().map(|header| header.timestamp).collect();
+    timestamps.sort_unstable();
+    timestamps.get(timestamps.len() / 2).copied().unwrap_or(0)
+}
+
+// Share of the headers signalling bit, say over a 2016-block window.
+// Headers without a BIP9 version count as not signalling, and there is no
+// share of no headers.
+pub fn signalling_fraction<'a, I: IntoIterator<Item = &'a BlockHeader>>(headers: I, bit: u8) -> Option<f64> {
+    let (mut signalling, mut total) = (0usize, 0usize);
+    for header in headers {
+        total += 1;
+        if header.signals_bit(bit) == Some(true) {
+            signalling += 1;
+        }
+    }
+    (total > 0).then(|| signalling as f64 / total as f64)
+}
+
+// Decode the compact bits: coefficient * 256^(exponent - 3).
+// Bit 23 of the coefficient is a sign bit, so negative or overflowing
+// encodings can never be met and decode to a zero target.
+pub fn bits_to_target(bits: [u8; 4]) -> U256 {
+    let exponent = bits[3] as u32;
+    let coefficient = u32::from_le_bytes([bits[0], bits[1], bits[2], 0]);
+    if coefficient & 0x0080_0000 != 0 {
+        return U256::ZERO;
+    }
+    let coefficient = U256::from_u64(coefficient as u64);
+    if exponent <= 3 {
+        return coefficient >> (8 * (3 - exponent));
+    }
+    if coefficient.bits() + 8 * (exponent - 3) > 256 {
+        return U256::ZERO;
+    }
+    coefficient << (8 * (exponent - 3))
+}
+
+pub fn target_to_bits(target: U256) -> [u8; 4] {
+    let bytes = target.to_be_bytes();
+    let raw = match bytes.iter().position(|&b| b != 0) {
+        Some(start) => &bytes[start..],
+        None => return [0, 0, 0, 0],
+    };
+    // A leading byte above 0x7f would set the sign bit, so shift the
+    // coefficient down a byte and bump the exponent instead
+    let (exponent, coefficient) = if raw[0] > 0x7f {
+        (raw.len() + 1, [0, raw[0], raw.get(1).copied().unwrap_or(0)])
+    } else {
+        (raw.len(), [raw[0], raw.get(1).copied().unwrap_or(0), raw.get(2).copied().unwrap_or(0)])
+    };
+    [coefficient[2], coefficient[1], coefficient[0], exponent as u8]
+}
+
+// The easiest allowed target, 0xffff * 256^(0x1d - 3)
+fn max_target() -> U256 {
+    U256::from_u64(0xffff) << (8 * (0x1d - 3))
+}
+
+pub fn difficulty(bits: [u8; 4]) -> f64 {
+    max_target().to_f64() / bits_to_target(bits).to_f64()
+}
+
+// Retarget after a 2016-block epoch that took time_differential seconds.
+// That is clamped to a quarter and four times the two-week goal before
+// scaling the previous target, and the result never gets easier than the
+// max target.
+pub fn calculate_new_bits(previous_bits: [u8; 4], time_differential: u64) -> [u8; 4] {
+    let time_differential = time_differential.clamp(TWO_WEEKS as u64 / 4, TWO_WEEKS as u64 * 4);
+    let new_target = bits_to_target(previous_bits)
+        .checked_mul(U256::from_u64(time_differential))
+        .map(|scaled| scaled / U256::from_u64(TWO_WEEKS as u64))
+        .unwrap_or(U256::MAX);
+    target_to_bits(new_target.min(max_target()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::decode_hex;
+
+    const HEADER_HEX: &str = "020000208ec39428b17323fa0ddec8e887b4a7c53b8c0a0a220cfd0000000000000000005b0750fce0a889502d40508d39576821155e9c9e3f5c3157f961db38fd8b25be1e77a759e93c0118a4ffd71d";
+
+    #[test]
+    fn parse_and_check_pow() {
+        let raw = decode_hex(HEADER_HEX).unwrap();
+        let block = BlockHeader::parse(&mut &raw[..]).unwrap();
+        assert_eq!(block.version, 0x20000002);
+        assert_eq!(encode_hex(&block.hash()), "0000000000000000007e9e4c586439b0cdbe13b1370bdd9435d76a644d047523");
+        assert_eq!(block.serialize().to_vec(), raw);
+        assert_eq!(format!("{:x}", block.target()), "0000000000000000013ce9000000000000000000000000000000000000000000");
+        assert!(block.check_pow());
+        assert!(BlockHeader::parse(&mut &raw[..79]).is_err());
+    }
+
+    #[test]
+    fn genesis_pow() {
+        let genesis = BlockHeader::parse(&mut &GENESIS_BLOCK[..]).unwrap();
+        assert_eq!(encode_hex(&genesis.hash()), "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+        assert!(genesis.check_pow());
+    }
+
+    #[test]
+    fn bits_target_round_trip() {
+        let bits = [0xe9, 0x3c, 0x01, 0x18];
+        assert_eq!(target_to_bits(bits_to_target(bits)), bits);
+        assert!((difficulty(bits) - 888171856257.3206).abs() < 0.01);
+        assert_eq!(difficulty([0xff, 0xff, 0x00, 0x1d]), 1.0);
+        assert_eq!(target_to_bits(U256::from_u64(0x12)), [0, 0, 0x12, 1]);
+        assert_eq!(bits_to_target([0, 0, 0x12, 1]), U256::from_u64(0x12));
+        assert_eq!(target_to_bits(U256::from_u64(0x1234)), [0, 0x34, 0x12, 2]);
+    }
+
+    #[test]
+    fn bits_sign_bit() {
+        // A coefficient with its top bit set is shifted down a byte
+        let target = U256::from_u64(0x80) << 200;
+        assert_eq!(target_to_bits(target), [0x00, 0x80, 0x00, 27]);
+        assert_eq!(bits_to_target([0x00, 0x80, 0x00, 27]), target);
+        assert_eq!(bits_to_target([0, 0, 0x80, 0x04]), U256::ZERO);
+    }
+
+    // The first and last headers of an epoch, from chapter 9
+    const EPOCH_FIRST_HEX: &str = "000000203471101bbda3fe307664b3283a9ef0e97d9a38a7eacd8800000000000000000010c8aba8479bbaa5e0848152fd3c2289ca50e1c3e58c9a4faaafbdf5803c5448ddb845597e8b0118e43a81d3";
+    const EPOCH_LAST_HEX: &str = "02000020f1472d9db4b563c35f97c428ac903f23b7fc055d1cfc26000000000000000000b3f449fcbe1bc4cfbcb8283a0d2c037f961a3fdf2b8bedc144973735eea707e1264258597e8b0118e5f00474";
+
+    fn header(hex: &str) -> BlockHeader {
+        BlockHeader::parse(&mut &decode_hex(hex).unwrap()[..]).unwrap()
+    }
+
+    #[test]
+    fn retarget() {
+        let (first, last) = (header(EPOCH_FIRST_HEX), header(EPOCH_LAST_HEX));
+        assert_eq!(BlockHeader::new_epoch_bits(&first, &last), [0x30, 0x8d, 0x01, 0x18]);
+        assert_eq!(calculate_new_bits([0x7e, 0x8b, 0x01, 0x18], 1214793), [0x30, 0x8d, 0x01, 0x18]);
+        // Difficulty never moves more than a factor of four
+        assert_eq!(calculate_new_bits(last.bits, TWO_WEEKS as u64 / 4), calculate_new_bits(last.bits, 1));
+        assert_eq!(calculate_new_bits(last.bits, TWO_WEEKS as u64 * 4), calculate_new_bits(last.bits, u64::MAX));
+        assert_eq!(calculate_new_bits([0xff, 0xff, 0x00, 0x1d], TWO_WEEKS as u64 * 4), [0xff, 0xff, 0x00, 0x1d]);
+    }
+
+    #[test]
+    fn retarget_clamps() {
+        let (first, last) = (header(EPOCH_FIRST_HEX), header(EPOCH_LAST_HEX));
+        let fast = BlockHeader { timestamp: first.timestamp + 10, ..last };
+        assert_eq!(bits_to_target(BlockHeader::new_epoch_bits(&first, &fast)), last.target() / U256::from_u64(4));
+        let slow = BlockHeader { timestamp: first.timestamp + TWO_WEEKS * 10, ..last };
+        assert_eq!(bits_to_target(BlockHeader::new_epoch_bits(&first, &slow)), last.target() * U256::from_u64(4));
+    }
+
+    #[test]
+    fn header_fields() {
+        let block = header(HEADER_HEX);
+        assert_eq!(encode_hex(&block.prev_block), "000000000000000000fd0c220a0a8c3bc5a7b487e8c8de0dfa2373b12894c38e");
+        assert_eq!(encode_hex(&block.merkle_root), "be258bfd38db61f957315c3f9e9c5e15216857398d50402d5089a8e0fc50075b");
+        assert_eq!(block.timestamp, 0x59a7771e);
+        assert_eq!(block.bits, [0xe9, 0x3c, 0x01, 0x18]);
+        assert_eq!(block.nonce, [0xa4, 0xff, 0xd7, 0x1d]);
+    }
+
+    #[test]
+    fn id_and_pow() {
+        assert_eq!(header(HEADER_HEX).id(), "0000000000000000007e9e4c586439b0cdbe13b1370bdd9435d76a644d047523");
+        // The same header with two nonces, one above its target
+        let insufficient = "04000000fbedbbf0cfdaf278c094f187f2eb987c86a199da22bbb20400000000000000007b7697b29129648fa08b4bcd13c9d5e60abb973a1efac9c8d573c71c807c56c3d6213557faa80518c3737ec0";
+        let valid = "04000000fbedbbf0cfdaf278c094f187f2eb987c86a199da22bbb20400000000000000007b7697b29129648fa08b4bcd13c9d5e60abb973a1efac9c8d573c71c807c56c3d6213557faa80518c3737ec1";
+        assert!(!header(insufficient).check_pow());
+        assert!(header(valid).check_pow());
+    }
+
+    #[test]
+    fn difficulty_of_header() {
+        assert!((header(HEADER_HEX).difficulty() - 888171856257.3206).abs() < 1.0);
+        assert_eq!(BlockHeader::parse(&mut &GENESIS_BLOCK[..]).unwrap().difficulty(), 1.0);
+        for bits in [[0xe9, 0x3c, 0x01, 0x18], [0xff, 0xff, 0x00, 0x1d], [0x54, 0xd8, 0x01, 0x18], [0x00, 0x80, 0x00, 0x04]] {
+            assert_eq!(target_to_bits(bits_to_target(bits)), bits);
+        }
+        assert_eq!(target_to_bits(U256::from_u64(0x80)), [0x00, 0x80, 0x00, 0x02]);
+    }
+
+    #[test]
+    fn retarget_from_bits_and_elapsed_time() {
+        let bits = [0x7e, 0x8b, 0x01, 0x18];
+        // An epoch right on schedule keeps its target
+        assert_eq!(calculate_new_bits(bits, TWO_WEEKS as u64), bits);
+        let doubled = calculate_new_bits(bits, TWO_WEEKS as u64 * 2);
+        assert_eq!(bits_to_target(doubled), bits_to_target(bits) * U256::from_u64(2));
+        // A last timestamp before the first counts as no time taken
+        let (first, last) = (header(EPOCH_FIRST_HEX), header(EPOCH_LAST_HEX));
+        let backwards = BlockHeader { timestamp: first.timestamp - 1, ..last };
+        assert_eq!(BlockHeader::new_epoch_bits(&first, &backwards), calculate_new_bits(last.bits, 0));
+    }
+
+    #[test]
+    fn version_bit_signalling() {
+        let segwit = header(HEADER_HEX);
+        let version_4 = header("0400000039fa821848781f027a2e6dfabbf6bda920d9ae61b63400030000000000000000ecae536a304042e3154be0e3e9a8220e5568c3433a9ab49ac4cbb74f8df8e8b0cc2acf569fb9061806652c27");
+        let segsig = header("1200002028856ec5bca29cf76980d368b0a163a0bb81fc192951270100000000000000003288f32a2831833c31a25401c52093eb545d28157e200a64b21b3ae8f21c507401877b5935470118144dbfd1");
+        let none = header("0000002066f09203c1cf5ef1531f24ed21b1915ae9abeb691f0d2e0100000000000000003de0976428ce56125351bae62c5b8b8c79d8297c702ea05d60feabb4ed188b59c36fa759e93c0118b74b2618");
+        assert!(segwit.bip9());
+        assert!(segwit.bip141());
+        assert_eq!(segwit.signals_bit(29), None);
+        assert!(!version_4.bip9());
+        assert_eq!(version_4.signals_bit(1), None);
+        assert!(segsig.bip91());
+        assert!(!none.bip91());
+        assert!(!none.bip141());
+        assert_eq!(signalling_fraction([&segwit, &version_4, &segsig, &none], 1), Some(0.5));
+        assert_eq!(signalling_fraction([], 1), None);
+    }
+
+    // The whole genesis block: its header and the one coinbase
+    const GENESIS_HEX: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c0101000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+
+    // Mainnet block 170, with the first transaction between two people
+    const BLOCK170_HEX: &str = "0100000055bd840a78798ad0da853f68974f3d183e2bd1db6a842c1feecf222a00000000ff104ccb05421ab93e63f8c3ce5c2c2e9dbb37de2764b3a3175c8166562cac7d51b96a49ffff001d283e9e700201000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0102ffffffff0100f2052a01000000434104d46c4968bde02899d2aa0963367c7a6ce34eec332b32e42e5f3407e052d64ac625da6f0718e7b302140434bd725706957c092db53805b821a85b23a7ac61725bac000000000100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000";
+
+    #[test]
+    fn block_merkle_root() {
+        let raw = decode_hex(GENESIS_HEX).unwrap();
+        let genesis = Block::parse(&mut &raw[..], false).unwrap();
+        assert_eq!(genesis.txs.len(), 1);
+        assert_eq!(genesis.serialize(), raw);
+        assert!(genesis.validate_merkle_root());
+        assert_eq!(genesis.header.id(), "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+        let mut corrupt = raw.clone();
+        let len = corrupt.len();
+        corrupt[len - 10] ^= 1;
+        assert!(!Block::parse(&mut &corrupt[..], false).unwrap().validate_merkle_root());
+        let raw = decode_hex(BLOCK170_HEX).unwrap();
+        let block = Block::parse(&mut &raw[..], false).unwrap();
+        assert_eq!(block.header.id(), "00000000d1145790a8694403d4063f323d499e655c83426834d4ce2f8dd4a2ee");
+        assert_eq!(block.txs.len(), 2);
+        assert_eq!(block.serialize(), raw);
+        assert!(block.validate_merkle_root());
+        // Its coinbase predates BIP34 and starts with the bits, not a height
+        assert_eq!(block.txs[0].coinbase_height(), Some(486604799));
+        assert_eq!(block.txs[1].id(), "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16");
+        let mut corrupt = raw.clone();
+        let len = corrupt.len();
+        corrupt[len - 10] ^= 1;
+        assert!(!Block::parse(&mut &corrupt[..], false).unwrap().validate_merkle_root());
+        assert!(!Block { header: genesis.header, txs: vec![] }.validate_merkle_root());
+    }
+
+    // Mainnet blocks 1 and 2
+    const BLOCK1_HEX: &str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299";
+    const BLOCK2_HEX: &str = "010000004860eb18bf1b1620e37e9490fc8a427514416fd75159ab86688e9a8300000000d5fdcc541e25de1c7a5addedf24858b8bb665c9f36ef744ee42c316022c90f9bb0bc6649ffff001d08d2bd61";
+
+    // Mainnet headers 1 to 8
+    const MAINNET_HEADERS: [&str; 8] = [
+        BLOCK1_HEX,
+        BLOCK2_HEX,
+        "01000000bddd99ccfda39da1b108ce1a5d70038d0a967bacb68b6b63065f626a0000000044f672226090d85db9a9f2fbfe5f0f9609b387af7be5b7fbb7a1767c831c9e995dbe6649ffff001d05e0ed6d",
+        "010000004944469562ae1c2c74d9a535e00b6f3e40ffbad4f2fda3895501b582000000007a06ea98cd40ba2e3288262b28638cec5337c1456aaf5eedc8e9e5a20f062bdf8cc16649ffff001d2bfee0a9",
+        "0100000085144a84488ea88d221c8bd6c059da090e88f8a2c99690ee55dbba4e00000000e11c48fecdd9e72510ca84f023370c9a38bf91ac5cae88019bee94d24528526344c36649ffff001d1d03e477",
+        "01000000fc33f596f822a0a1951ffdbf2a897b095636ad871707bf5d3162729b00000000379dfb96a5ea8c81700ea4ac6b97ae9a9312b2d4301a29580e924ee6761a2520adc46649ffff001d189c4c97",
+        "010000008d778fdc15a2d3fb76b7122a3b5582bea4f21f5a0c693537e7a03130000000003f674005103b42f984169c7d008370967e91920a6a5d64fd51282f75bc73a68af1c66649ffff001d39a59c86",
+        "010000004494c8cf4154bdcc0720cd4a59d9c9b285e4b146d45f061d2b6c967100000000e3855ed886605b6d4a99d5fa2ef2e9b0b164e63df3c4136bebf2d0dac0f1f7a667c86649ffff001d1c4b5666",
+    ];
+
+    #[test]
+    fn header_chain() {
+        let (block1, block2) = (header(BLOCK1_HEX), header(BLOCK2_HEX));
+        let mut chain = HeaderChain::new(false);
+        assert_eq!(chain.tip().id(), "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+        assert_eq!(chain.work(), U256::from_u64(0x100010001));
+        assert_eq!(chain.add(block2), Err(ChainError::BadLinkage));
+        let wrong_bits = BlockHeader { bits: [0xff, 0xff, 0x00, 0x1c], ..block1 };
+        assert!(matches!(chain.add(wrong_bits), Err(ChainError::WrongBits { .. })));
+        let no_pow = BlockHeader { nonce: [0; 4], ..block1 };
+        assert_eq!(chain.add(no_pow), Err(ChainError::InsufficientPow));
+        chain.add(block1).unwrap();
+        chain.add(block2).unwrap();
+        assert_eq!(chain.height(), 2);
+        assert_eq!(chain.work(), U256::from_u64(3 * 0x100010001));
+        let testnet = HeaderChain::new(true);
+        assert_eq!(testnet.tip().id(), "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943");
+    }
+
+    #[test]
+    fn mainnet_headers_from_genesis() {
+        let mut chain = HeaderChain::new(false);
+        for hex in MAINNET_HEADERS {
+            chain.add(header(hex)).unwrap();
+        }
+        assert_eq!(chain.height(), 8);
+        assert_eq!(chain.tip().id(), "00000000408c48f847aa786c2268fc3e6ec2af68e8468a34a28c61b7f1de0dc6");
+        assert_eq!(chain.work(), U256::from_u64(9 * 0x100010001));
+    }
+
+    #[test]
+    fn retarget_at_epoch_boundary() {
+        // A header every five minutes, pushed straight onto the chain as
+        // only the header at height 2016 is under test
+        let mut chain = HeaderChain::new(false);
+        while chain.headers.len() < RETARGET_INTERVAL {
+            let tip = *chain.tip();
+            chain.headers.push(BlockHeader { prev_block: tip.hash(), timestamp: tip.timestamp + 300, ..tip });
+        }
+        let tip = *chain.tip();
+        let next = BlockHeader { prev_block: tip.hash(), timestamp: tip.timestamp + 300, ..tip };
+        // The epoch took half of two weeks, so the target halves
+        let expected = [0x3f, 0xef, 0x7f, 0x1c];
+        let now = next.timestamp as u64;
+        assert_eq!(chain.add_at(next, now), Err(ChainError::WrongBits { expected, actual: [0xff, 0xff, 0x00, 0x1d] }));
+        let retargeted = BlockHeader { bits: expected, ..next };
+        assert_eq!(chain.add_at(retargeted, now), Err(ChainError::InsufficientPow));
+        // One header earlier the old bits still apply
+        chain.headers.pop();
+        let tip = *chain.tip();
+        let early = BlockHeader { prev_block: tip.hash(), timestamp: tip.timestamp + 300, bits: expected, ..tip };
+        let wrong_bits = ChainError::WrongBits { expected: [0xff, 0xff, 0x00, 0x1d], actual: expected };
+        assert_eq!(chain.add_at(early, now), Err(wrong_bits));
+    }
+
+    #[test]
+    fn work_of_zero_target() {
+        let zero_target = BlockHeader { bits: [0, 0, 0, 0], ..header(HEADER_HEX) };
+        assert_eq!(zero_target.work(), U256::ZERO);
+        let max_target = BlockHeader { bits: target_to_bits(U256::MAX), ..header(HEADER_HEX) };
+        assert_eq!(max_target.work(), U256::ONE);
+    }
+
+    #[test]
+    fn subsidy_and_coinbase() {
+        assert_eq!(block_subsidy(0), 5_000_000_000);
+        assert_eq!(block_subsidy(209_999), 5_000_000_000);
+        assert_eq!(block_subsidy(210_000), 2_500_000_000);
+        assert_eq!(block_subsidy(420_000), 1_250_000_000);
+        assert_eq!(block_subsidy(6_929_999), 1);
+        assert_eq!(block_subsidy(6_930_000), 0);
+        assert_eq!(block_subsidy(64 * 210_000), 0);
+        assert_eq!(block_subsidy(u64::MAX), 0);
+        let block = Block::parse(&mut &decode_hex(GENESIS_HEX).unwrap()[..], false).unwrap();
+        assert_eq!(block.validate_coinbase(0, 0), Ok(()));
+        let excessive = BlockError::ExcessiveCoinbase { allowed: 2_500_000_000, actual: 5_000_000_000 };
+        assert_eq!(block.validate_coinbase(210_000, 0), Err(excessive));
+        assert_eq!(block.validate_coinbase(210_000, 2_500_000_000), Ok(()));
+        // Past BIP34 the coinbase must start with the height
+        assert!(matches!(block.validate_coinbase(300_000, 0), Err(BlockError::WrongHeight { expected: 300_000, .. })));
+        let empty = Block { txs: vec![], ..block };
+        assert_eq!(empty.validate_coinbase(0, 0), Err(BlockError::MissingCoinbase));
+    }
+
+    #[test]
+    fn median_time_past_rules() {
+        let (block1, block2) = (header(BLOCK1_HEX), header(BLOCK2_HEX));
+        let mut chain = HeaderChain::new(false);
+        assert_eq!(chain.mtp(), 1231006505);
+        let old = BlockHeader { timestamp: 1231006505, ..block1 };
+        assert_eq!(chain.add_at(old, 2_000_000_000), Err(ChainError::TimeTooOld { median_time_past: 1231006505 }));
+        // More than two hours ahead of the clock
+        let now = block1.timestamp as u64 - 7201;
+        assert_eq!(chain.add_at(block1, now), Err(ChainError::TimeTooNew { now }));
+        chain.add_at(block1, now + 1).unwrap();
+        assert_eq!(chain.mtp(), block1.timestamp);
+        chain.add(block2).unwrap();
+        assert_eq!(chain.mtp(), block1.timestamp);
+        let headers: Vec<BlockHeader> = [5, 1, 9, 3, 7, 2, 8, 4, 6, 10, 11, 100, 0]
+            .into_iter()
+            .map(|timestamp| BlockHeader { timestamp, ..block1 })
+            .collect();
+        // Only the last eleven count
+        assert_eq!(median_time_past(&headers), 7);
+        assert_eq!(median_time_past(&headers[..2]), 5);
+        assert_eq!(median_time_past(&[]), 0);
+    }
+
+    #[test]
+    fn merkle_proofs() {
+        let genesis = Block::parse(&mut &decode_hex(GENESIS_HEX).unwrap()[..], false).unwrap();
+        assert!(genesis.merkle_proof(&genesis.tx_hashes()[0]).unwrap().steps.is_empty());
+        let block = Block::parse(&mut &decode_hex(BLOCK170_HEX).unwrap()[..], false).unwrap();
+        let mut root = block.header.merkle_root;
+        root.reverse();
+        for txid in block.tx_hashes() {
+            let mut leaf = txid;
+            leaf.reverse();
+            let proof = block.merkle_proof(&txid).unwrap();
+            assert_eq!(proof.steps.len(), 1);
+            assert!(proof.verify(&leaf, &root));
+            assert!(!proof.verify(&leaf, &genesis.header.merkle_root));
+        }
+        assert!(block.merkle_proof(&[1; 32]).is_none());
+    }
+
+    #[test]
+    fn hash_below_target() {
+        let block = header(HEADER_HEX);
+        let proof = U256::from_hash(hash256(&block.serialize()));
+        assert_eq!(proof, U256::from_be_bytes(block.hash()));
+        assert!(proof.less_than(&block.target()));
+        assert!(!block.target().less_than(&proof));
+        // The same hash read in display order is far above it
+        let too_large = U256::from_hash(block.hash());
+        assert!(!too_large.less_than(&block.target()));
+        assert!(!proof.less_than(&proof));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::hashes::murmur3_32;
+use crate::network::GenericMessage;
+use crate::util::{bit_field_to_bytes, encode_varint};
+
+const BIP37_CONSTANT: u32 = 0xfba4c795;
+
+// BIP37 filter; bit_field holds one 0/1 entry per bit, size is in bytes
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BloomFilter {
+    pub size: u32,
+    pub bit_field: Vec<u8>,
+    pub function_count: u32,
+    pub tweak: u32,
+}
+
+impl BloomFilter {
+    pub fn new(size: u32, function_count: u32, tweak: u32) -> BloomFilter {
+        BloomFilter { size, bit_field: vec![0; size as usize * 8], function_count, tweak }
+    }
+
+    fn bit_indices<'a>(&'a self, item: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        (0..self.function_count).map(move |i| {
+            let seed = i.wrapping_mul(BIP37_CONSTANT).wrapping_add(self.tweak);
+            murmur3_32(item, seed) as usize % self.bit_field.len()
+        })
+    }
+
+    pub fn add(&mut self, item: &[u8]) {
+        if self.bit_field.is_empty() {
+            return;
+        }
+        let indices: Vec<usize> = self.bit_indices(item).collect();
+        for bit in indices {
+            self.bit_field[bit] = 1;
+        }
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        !self.bit_field.is_empty() && self.bit_indices(item).all(|bit| self.bit_field[bit] == 1)
+    }
+
+    pub fn filter_bytes(&self) -> Vec<u8> {
+        bit_field_to_bytes(&self.bit_field)
+    }
+
+    // Sends the filter to a peer. flag says whether the peer should add
+    // outputs that match to the filter itself: 0 never, 1 always, 2 only
+    // for p2pk and bare multisig.
+    pub fn filterload(&self, flag: u8) -> GenericMessage {
+        let mut payload = encode_varint(self.size as u64);
+        payload.extend(self.filter_bytes());
+        payload.extend_from_slice(&self.function_count.to_le_bytes());
+        payload.extend_from_slice(&self.tweak.to_le_bytes());
+        payload.push(flag);
+        GenericMessage::new(b"filterload", payload)
+    }
+
+    // Adds the item here and gives the message adding it to a peer's copy
+    pub fn filteradd(&mut self, item: &[u8]) -> GenericMessage {
+        self.add(item);
+        let mut payload = encode_varint(item.len() as u64);
+        payload.extend_from_slice(item);
+        GenericMessage::new(b"filteradd", payload)
+    }
+
+    // Empties the filter here. The peer drops its filter altogether and goes
+    // back to relaying everything.
+    pub fn filterclear(&mut self) -> GenericMessage {
+        self.bit_field.fill(0);
+        GenericMessage::new(b"filterclear", Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Message;
+    use crate::util::encode_hex;
+
+    #[test]
+    fn add_and_contains() {
+        // The filter from chapter 12
+        let mut bloom = BloomFilter::new(10, 5, 99);
+        bloom.add(b"Hello World");
+        assert_eq!(encode_hex(&bloom.filter_bytes()), "0000000a080000000140");
+        bloom.add(b"Goodbye!");
+        assert_eq!(encode_hex(&bloom.filter_bytes()), "4000600a080000010940");
+        assert!(bloom.contains(b"Hello World"));
+        assert!(bloom.contains(b"Goodbye!"));
+        assert!(!bloom.contains(b"nope"));
+    }
+
+    #[test]
+    fn empty_filter() {
+        let mut bloom = BloomFilter::new(0, 5, 99);
+        bloom.add(b"Hello World");
+        assert!(!bloom.contains(b"Hello World"));
+    }
+
+    #[test]
+    fn filter_messages() {
+        let mut bloom = BloomFilter::new(10, 5, 99);
+        bloom.add(b"Hello World");
+        bloom.add(b"Goodbye!");
+        let load = bloom.filterload(1);
+        assert_eq!(load.command(), b"filterload");
+        assert_eq!(encode_hex(&load.serialize()), "0a4000600a080000010940050000006300000001");
+        let add = bloom.filteradd(b"abc");
+        assert_eq!(add.command(), b"filteradd");
+        assert_eq!(add.serialize(), vec![3, b'a', b'b', b'c']);
+        assert!(bloom.contains(b"abc"));
+        // Clearing the peer's filter clears ours to match
+        let clear = bloom.filterclear();
+        assert_eq!(clear.command(), b"filterclear");
+        assert!(clear.serialize().is_empty());
+        assert!(!bloom.contains(b"abc"));
+    }
+}
@@ -0,0 +1,479 @@
+use std::fmt;
+
+use crate::coin_selection::Selection;
+use crate::ecc::PrivateKey;
+use crate::script::{address_to_script_pubkey, Script, ScriptError};
+use crate::tx::{
+    OutPoint, SighashType, Tx, TxError, TxFetcher, TxIn, TxOut, SEQUENCE_FINAL, SEQUENCE_LOCKTIME, SEQUENCE_RBF,
+};
+
+pub const DEFAULT_DUST_THRESHOLD: u64 = 546;
+
+// Extra bytes a signed p2pkh input adds over an empty script_sig: a
+// worst-case 72-byte DER signature plus sighash byte and a compressed SEC,
+// each behind a one-byte push
+const P2PKH_SCRIPT_SIG_SIZE: usize = 1 + 73 + 1 + 33;
+// The same signature and SEC as a two-item witness
+const P2WPKH_WITNESS_SIZE: usize = 1 + 1 + 73 + 1 + 33;
+// A p2sh-p2wpkh script_sig pushes the 22-byte witness program
+const P2SH_P2WPKH_SCRIPT_SIG_SIZE: usize = 1 + 22;
+
+#[derive(Debug)]
+pub enum BuilderError {
+    InvalidAddress(String),
+    WrongNetwork(String),
+    InsufficientFunds { available: u64, required: u64 },
+    Dust { index: usize, amount: u64 },
+    AmountOverflow,
+    Script(ScriptError),
+    Tx(TxError),
+}
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuilderError::InvalidAddress(address) => write!(f, "Invalid address {}", address),
+            BuilderError::WrongNetwork(address) => write!(f, "Address {} is for the other network", address),
+            BuilderError::InsufficientFunds { available, required } => {
+                write!(f, "Inputs ({}) do not cover outputs and fee ({})", available, required)
+            }
+            BuilderError::Dust { index, amount } => {
+                write!(f, "Output {} of {} is below the dust threshold", index, amount)
+            }
+            BuilderError::AmountOverflow => write!(f, "Amounts overflow a u64"),
+            BuilderError::Script(err) => write!(f, "{}", err),
+            BuilderError::Tx(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<ScriptError> for BuilderError {
+    fn from(err: ScriptError) -> BuilderError {
+        BuilderError::Script(err)
+    }
+}
+
+impl From<TxError> for BuilderError {
+    fn from(err: TxError) -> BuilderError {
+        BuilderError::Tx(err)
+    }
+}
+
+// Where an output pays to, resolved into a script_pubkey at build time.
+// Data is an OP_RETURN payload.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Destination {
+    Address(String),
+    Script(Script),
+    Data(Vec<u8>),
+}
+
+impl From<&str> for Destination {
+    fn from(address: &str) -> Destination {
+        Destination::Address(address.to_string())
+    }
+}
+
+impl From<String> for Destination {
+    fn from(address: String) -> Destination {
+        Destination::Address(address)
+    }
+}
+
+impl From<Script> for Destination {
+    fn from(script_pubkey: Script) -> Destination {
+        Destination::Script(script_pubkey)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TxBuilder {
+    testnet: bool,
+    inputs: Vec<(OutPoint, TxOut)>,
+    outputs: Vec<(Destination, u64)>,
+    locktime: u32,
+    rbf: bool,
+    fee_rate: f64,
+    change_address: Option<String>,
+    dust_threshold: u64,
+}
+
+impl TxBuilder {
+    pub fn new(testnet: bool) -> TxBuilder {
+        TxBuilder {
+            testnet,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            locktime: 0,
+            rbf: false,
+            fee_rate: 0.0,
+            change_address: None,
+            dust_threshold: DEFAULT_DUST_THRESHOLD,
+        }
+    }
+
+    pub fn add_input(&mut self, outpoint: OutPoint, prevout: TxOut) -> &mut TxBuilder {
+        self.inputs.push((outpoint, prevout));
+        self
+    }
+
+    // add_input with the output being spent looked up through the fetcher
+    pub fn add_input_from_fetcher(
+        &mut self,
+        outpoint: OutPoint,
+        fetcher: &mut dyn TxFetcher,
+    ) -> Result<&mut TxBuilder, BuilderError> {
+        let prevout = outpoint.fetch_output(fetcher, self.testnet).map_err(TxError::from)?;
+        Ok(self.add_input(outpoint, prevout))
+    }
+
+    pub fn add_output<D: Into<Destination>>(&mut self, destination: D, amount: u64) -> &mut TxBuilder {
+        self.outputs.push((destination.into(), amount));
+        self
+    }
+
+    // A zero-value OP_RETURN output carrying data, at most 80 bytes
+    pub fn add_data_output(&mut self, data: &[u8]) -> &mut TxBuilder {
+        self.outputs.push((Destination::Data(data.to_vec()), 0));
+        self
+    }
+
+    pub fn set_locktime(&mut self, locktime: u32) -> &mut TxBuilder {
+        self.locktime = locktime;
+        self
+    }
+
+    // Signal BIP125 replaceability on every input
+    pub fn rbf(&mut self, rbf: bool) -> &mut TxBuilder {
+        self.rbf = rbf;
+        self
+    }
+
+    pub fn fee_rate(&mut self, sat_per_vbyte: f64) -> &mut TxBuilder {
+        self.fee_rate = sat_per_vbyte;
+        self
+    }
+
+    pub fn change_address(&mut self, address: &str) -> &mut TxBuilder {
+        self.change_address = Some(address.to_string());
+        self
+    }
+
+    pub fn dust_threshold(&mut self, amount: u64) -> &mut TxBuilder {
+        self.dust_threshold = amount;
+        self
+    }
+
+    // Spends the selected coins. Fee and change are still worked out by
+    // build() for the outputs actually added.
+    pub fn add_selection(&mut self, selection: &Selection) -> &mut TxBuilder {
+        self.inputs.extend(selection.inputs.iter().cloned());
+        self
+    }
+
+    // The outputs being spent, aligned with the inputs of the built Tx
+    pub fn prevouts(&self) -> Vec<TxOut> {
+        self.inputs.iter().map(|(_, prevout)| prevout.clone()).collect()
+    }
+
+    // Whatever is left after outputs and fee goes to the change address,
+    // unless it would be dust, in which case it is left to the fee
+    pub fn build(&self) -> Result<Tx, BuilderError> {
+        // A non-final sequence is needed for the locktime to be enforced
+        let sequence = if self.rbf {
+            SEQUENCE_RBF
+        } else if self.locktime != 0 {
+            SEQUENCE_LOCKTIME
+        } else {
+            SEQUENCE_FINAL
+        };
+        let tx_ins = self
+            .inputs
+            .iter()
+            .map(|(outpoint, _)| TxIn {
+                prev_tx: outpoint.tx_id,
+                prev_index: outpoint.index,
+                script_sig: Script::new(vec![]),
+                sequence,
+                witness: Vec::new(),
+            })
+            .collect();
+        let mut tx_outs = Vec::new();
+        for (index, (destination, amount)) in self.outputs.iter().enumerate() {
+            let script_pubkey = match destination {
+                Destination::Address(address) => address_script(address, self.testnet)?,
+                Destination::Script(script_pubkey) => script_pubkey.clone(),
+                Destination::Data(data) => Script::op_return(data, false)?,
+            };
+            // Unspendable outputs never need to be worth spending
+            if *amount < self.dust_threshold && !script_pubkey.is_op_return() {
+                return Err(BuilderError::Dust { index, amount: *amount });
+            }
+            tx_outs.push(TxOut { amount: *amount, script_pubkey });
+        }
+        let mut tx = Tx { version: 1, tx_ins, tx_outs, locktime: self.locktime, testnet: self.testnet };
+
+        let available = checked_sum(self.inputs.iter().map(|(_, prevout)| prevout.amount))?;
+        let spent = checked_sum(self.outputs.iter().map(|(_, amount)| *amount))?;
+        let required = spent.checked_add(self.fee(&tx)).ok_or(BuilderError::AmountOverflow)?;
+        if available < required {
+            return Err(BuilderError::InsufficientFunds { available, required });
+        }
+        if let Some(change_address) = &self.change_address {
+            let script_pubkey = address_script(change_address, self.testnet)?;
+            tx.tx_outs.push(TxOut { amount: 0, script_pubkey });
+            let change = spent
+                .checked_add(self.fee(&tx))
+                .and_then(|required| available.checked_sub(required));
+            match change {
+                Some(change) if change >= self.dust_threshold => tx.tx_outs.last_mut().unwrap().amount = change,
+                _ => {
+                    tx.tx_outs.pop();
+                }
+            }
+        }
+        Ok(tx)
+    }
+
+    // Builds the transaction and signs every input one of the keys can
+    // spend, the rest keep an empty script_sig
+    pub fn sign(&self, private_keys: &[PrivateKey]) -> Result<Tx, BuilderError> {
+        let mut tx = self.build()?;
+        let prevouts = self.prevouts();
+        for input_index in 0..tx.tx_ins.len() {
+            for private_key in private_keys {
+                match tx.sign_input(input_index, &prevouts, private_key, SighashType::All) {
+                    Ok(_) => break,
+                    Err(TxError::CannotSign(_)) => continue,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+        Ok(tx)
+    }
+
+    // Worst-case size in bytes of the built transaction once signed
+    pub fn predicted_size(&self) -> Result<usize, BuilderError> {
+        Ok(self.predicted_size_and_weight(&self.build()?).0)
+    }
+
+    pub fn predicted_vsize(&self) -> Result<usize, BuilderError> {
+        Ok(self.predicted_size_and_weight(&self.build()?).1.div_ceil(4))
+    }
+
+    fn predicted_size_and_weight(&self, tx: &Tx) -> (usize, usize) {
+        signed_size_and_weight(tx.serialize_legacy().len(), self.inputs.iter().map(|(_, prevout)| &prevout.script_pubkey))
+    }
+
+    fn fee(&self, tx: &Tx) -> u64 {
+        fee_for_weight(self.predicted_size_and_weight(tx).1, self.fee_rate)
+    }
+}
+
+// What signing adds to an input spending this script_pubkey: bytes of
+// script_sig, and of witness if it takes one. p2sh is taken to be
+// p2sh-p2wpkh, the only p2sh this builder can sign, anything else p2pkh.
+fn signed_input_size(script_pubkey: &Script) -> (usize, Option<usize>) {
+    if script_pubkey.is_p2wpkh_script_pubkey() {
+        (0, Some(P2WPKH_WITNESS_SIZE))
+    } else if script_pubkey.is_p2sh_script_pubkey() {
+        (P2SH_P2WPKH_SCRIPT_SIG_SIZE, Some(P2WPKH_WITNESS_SIZE))
+    } else {
+        (P2PKH_SCRIPT_SIG_SIZE, None)
+    }
+}
+
+// Worst-case size and weight once signed of a transaction that is
+// unsigned_size bytes with empty script_sigs, spending these script_pubkeys
+pub(crate) fn signed_size_and_weight<'a>(
+    unsigned_size: usize,
+    script_pubkeys: impl IntoIterator<Item = &'a Script>,
+) -> (usize, usize) {
+    let mut base_size = unsigned_size;
+    let mut witness_size = 0;
+    let mut segwit = false;
+    for script_pubkey in script_pubkeys {
+        let (script_sig_size, input_witness_size) = signed_input_size(script_pubkey);
+        base_size += script_sig_size;
+        segwit |= input_witness_size.is_some();
+        // An empty witness still takes its item count byte
+        witness_size += input_witness_size.unwrap_or(1);
+    }
+    if segwit {
+        // Marker and flag
+        witness_size += 2;
+    } else {
+        // No witness inputs, so no marker, flag or witness section
+        witness_size = 0;
+    }
+    (base_size + witness_size, base_size * 4 + witness_size)
+}
+
+// Fee rates are per virtual byte, a quarter of a weight unit rounded up
+pub(crate) fn fee_for_weight(weight: usize, sat_per_vbyte: f64) -> u64 {
+    (weight.div_ceil(4) as f64 * sat_per_vbyte).ceil() as u64
+}
+
+fn checked_sum<I: Iterator<Item = u64>>(amounts: I) -> Result<u64, BuilderError> {
+    let mut total = 0u64;
+    for amount in amounts {
+        total = total.checked_add(amount).ok_or(BuilderError::AmountOverflow)?;
+    }
+    Ok(total)
+}
+
+fn address_script(address: &str, testnet: bool) -> Result<Script, BuilderError> {
+    address_to_script_pubkey(address, testnet).map_err(|err| match err {
+        ScriptError::WrongNetwork(address) => BuilderError::WrongNetwork(address),
+        _ => BuilderError::InvalidAddress(address.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::OpCode;
+    use crate::script::{p2pkh_script, p2sh_script, Cmd};
+    use crate::tx::FetchError;
+    use crate::u256::U256;
+
+    const RECIPIENT: &str = "mzx5YhAH9kNHtcN481u6WkjeHjYtVeKVh2";
+
+    fn keys() -> [PrivateKey; 2] {
+        [PrivateKey::new(U256::from_u64(8675309)), PrivateKey::new(U256::from_u64(12345))]
+    }
+
+    // Two p2pkh inputs of 100000 and 50000 paying 120000, change going
+    // back to the first key
+    fn builder() -> TxBuilder {
+        let [key1, key2] = keys();
+        let script_pubkey = |key: &PrivateKey| p2pkh_script(&crate::hashes::hash160(&key.point.sec(true)));
+        let mut builder = TxBuilder::new(true);
+        builder
+            .add_input(OutPoint::new([1; 32], 0), TxOut { amount: 100000, script_pubkey: script_pubkey(&key1) })
+            .add_input(OutPoint::new([2; 32], 3), TxOut { amount: 50000, script_pubkey: script_pubkey(&key2) })
+            .add_output(RECIPIENT, 120000)
+            .fee_rate(1.0)
+            .change_address(&key1.point.address(true, true));
+        builder
+    }
+
+    struct MockFetcher(Tx);
+
+    impl TxFetcher for MockFetcher {
+        fn fetch(&mut self, tx_id: &str, _testnet: bool) -> Result<Tx, FetchError> {
+            if self.0.id() == tx_id {
+                Ok(self.0.clone())
+            } else {
+                Err(FetchError::NotCached(tx_id.to_string()))
+            }
+        }
+    }
+
+    #[test]
+    fn build_with_change() {
+        let tx = builder().build().unwrap();
+        assert_eq!(tx.tx_ins.len(), 2);
+        assert_eq!(tx.tx_outs.len(), 2);
+        assert_eq!(tx.tx_outs[0].amount, 120000);
+        // Version 4, two inputs of 32 + 4 + 1 + 108 + 4 with the worst-case
+        // p2pkh script_sig, two p2pkh outputs of 8 + 1 + 25, locktime 4 and
+        // one-byte counts: 376 vbytes, so 376 satoshis at 1 per vbyte and
+        // 150000 - 120000 - 376 in change
+        assert_eq!(builder().predicted_vsize().unwrap(), 376);
+        assert_eq!(tx.tx_outs[1].amount, 29624);
+    }
+
+    #[test]
+    fn sign_verifies() {
+        let builder = builder();
+        let signed = builder.sign(&keys()).unwrap();
+        assert!(signed.verify(&builder.prevouts()).unwrap());
+        assert!(signed.size() <= builder.predicted_size().unwrap());
+    }
+
+    #[test]
+    fn build_errors() {
+        let mut dust = builder();
+        dust.add_output(RECIPIENT, 100);
+        assert!(matches!(dust.build(), Err(BuilderError::Dust { index: 1, amount: 100 })));
+        let mut short = builder();
+        short.add_output(RECIPIENT, 30000);
+        assert!(matches!(short.build(), Err(BuilderError::InsufficientFunds { .. })));
+        let mut mainnet = builder();
+        mainnet.add_output("1F1Pn2y6pDb68E5nYJJeba4TLg2U7B6KF1", 1000);
+        assert!(matches!(mainnet.build(), Err(BuilderError::WrongNetwork(_))));
+    }
+
+    #[test]
+    fn predicted_size_bounds_signed_size() {
+        let keys = keys();
+        let key = &keys[0];
+        let h160 = crate::hashes::hash160(&key.point.sec(true));
+        let p2wpkh = Script::new(vec![Cmd::Op(OpCode::OP_0), Cmd::Data(h160.to_vec())]);
+        let p2sh_p2wpkh = p2sh_script(&crate::hashes::hash160(&p2wpkh.raw_serialize()));
+        let p2pkh = p2pkh_script(&h160);
+        for script_pubkeys in [
+            vec![p2wpkh.clone()],
+            vec![p2sh_p2wpkh.clone()],
+            vec![p2pkh.clone()],
+            vec![p2wpkh, p2pkh, p2sh_p2wpkh],
+        ] {
+            let mut builder = TxBuilder::new(true);
+            for (i, script_pubkey) in script_pubkeys.iter().enumerate() {
+                let prevout = TxOut { amount: 100000, script_pubkey: script_pubkey.clone() };
+                builder.add_input(OutPoint::new([i as u8 + 1; 32], 0), prevout);
+            }
+            builder.add_output(RECIPIENT, 50000).change_address(&key.point.address(true, true)).fee_rate(2.0);
+            let signed = builder.sign(&keys).unwrap();
+            assert!(signed.verify(&builder.prevouts()).unwrap());
+            // Signatures come out at most one byte shorter than the worst case
+            let predicted_size = builder.predicted_size().unwrap();
+            assert!(signed.size() <= predicted_size);
+            assert!(predicted_size - signed.size() <= 2 * script_pubkeys.len());
+            assert!(signed.vsize() <= builder.predicted_vsize().unwrap());
+        }
+    }
+
+    #[test]
+    fn data_output() {
+        let mut builder = builder();
+        builder.add_data_output(b"hi");
+        let tx = builder.build().unwrap();
+        assert_eq!(tx.tx_outs[1].amount, 0);
+        assert_eq!(tx.tx_outs[1].script_pubkey.op_return_data().unwrap(), b"hi");
+        builder.add_data_output(&[0; 81]);
+        assert!(matches!(builder.build(), Err(BuilderError::Script(ScriptError::DataTooLarge(81)))));
+    }
+
+    #[test]
+    fn input_from_fetcher() {
+        let key = PrivateKey::new(U256::from_u64(777));
+        let address = key.point.address(true, true);
+        let script_pubkey = address_to_script_pubkey(&address, true).unwrap();
+        let prev_tx = Tx {
+            version: 1,
+            tx_ins: vec![],
+            tx_outs: vec![
+                TxOut { amount: 1, script_pubkey: Script::default() },
+                TxOut { amount: 100000, script_pubkey },
+            ],
+            locktime: 0,
+            testnet: true,
+        };
+        let prev_tx_hash = prev_tx.hash();
+        let mut fetcher = MockFetcher(prev_tx);
+        let mut builder = TxBuilder::new(true);
+        builder
+            .add_input_from_fetcher(OutPoint::new(prev_tx_hash, 1), &mut fetcher)
+            .unwrap()
+            .add_output(RECIPIENT, 50000)
+            .add_output(address.as_str(), 40000);
+        let missing = TxBuilder::new(true).add_input_from_fetcher(OutPoint::new(prev_tx_hash, 5), &mut fetcher).is_err();
+        assert!(missing);
+        let mut tx = builder.build().unwrap();
+        assert_eq!(tx.tx_outs.len(), 2);
+        assert!(tx.sign_input_with_fetcher(0, &key, SighashType::All, &mut fetcher).unwrap());
+        assert!(tx.verify_with_fetcher(&mut fetcher).unwrap());
+    }
+}
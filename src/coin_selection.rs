@@ -0,0 +1,243 @@
+use std::cmp::Reverse;
+use std::fmt;
+
+use crate::builder::{fee_for_weight, signed_size_and_weight, DEFAULT_DUST_THRESHOLD};
+use crate::tx::{OutPoint, TxOut};
+
+// Size estimates for the transaction being funded: version, locktime and
+// one-byte input and output counts, an input with an empty script_sig,
+// and a p2pkh output, which stands in for both the payment and change
+const TX_OVERHEAD_SIZE: usize = 4 + 1 + 1 + 4;
+const EMPTY_INPUT_SIZE: usize = 32 + 4 + 1 + 4;
+const OUTPUT_SIZE: usize = 8 + 1 + 25;
+
+// Branch and bound gives up on an exact match after this many steps
+const BNB_MAX_TRIES: usize = 100_000;
+
+#[derive(Debug)]
+pub enum CoinSelectionError {
+    InsufficientFunds { available: u64, required: u64 },
+    AmountOverflow,
+}
+
+impl fmt::Display for CoinSelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoinSelectionError::InsufficientFunds { available, required } => {
+                write!(f, "Candidates ({}) do not cover target and fee ({})", available, required)
+            }
+            CoinSelectionError::AmountOverflow => write!(f, "Amounts overflow a u64"),
+        }
+    }
+}
+
+// Selected inputs add up to target + fee + change. A change of zero
+// means there is no change output, anything too small for one is left
+// to the fee.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Selection {
+    pub inputs: Vec<(OutPoint, TxOut)>,
+    pub fee: u64,
+    pub change: u64,
+}
+
+// Spends the biggest coins first until target and fee are covered
+pub fn largest_first(
+    candidates: &[(OutPoint, TxOut)],
+    target: u64,
+    fee_rate: f64,
+) -> Result<Selection, CoinSelectionError> {
+    let mut sorted: Vec<&(OutPoint, TxOut)> = candidates.iter().collect();
+    sorted.sort_by_key(|candidate| Reverse(candidate.1.amount));
+    let mut selected = Vec::new();
+    let mut available = 0u64;
+    for candidate in sorted {
+        selected.push(candidate.clone());
+        available = available.checked_add(candidate.1.amount).ok_or(CoinSelectionError::AmountOverflow)?;
+        let fee_with_change = fee_for_weight(weight(&selected, true), fee_rate);
+        let fee_without_change = fee_for_weight(weight(&selected, false), fee_rate);
+        match available.checked_sub(target) {
+            Some(excess) if excess >= fee_with_change + DEFAULT_DUST_THRESHOLD => {
+                return Ok(Selection { inputs: selected, fee: fee_with_change, change: excess - fee_with_change });
+            }
+            Some(excess) if excess >= fee_without_change => {
+                return Ok(Selection { inputs: selected, fee: excess, change: 0 });
+            }
+            _ => {}
+        }
+    }
+    let required = fee_for_weight(weight(&selected, false), fee_rate).saturating_add(target);
+    Err(CoinSelectionError::InsufficientFunds { available, required })
+}
+
+// Depth-first search for a set of coins that covers target and fee with
+// less left over than a change output would cost, so there is no change.
+// Falls back to largest_first when there is no such set.
+pub fn branch_and_bound(
+    candidates: &[(OutPoint, TxOut)],
+    target: u64,
+    fee_rate: f64,
+) -> Result<Selection, CoinSelectionError> {
+    // A coin's effective value is its amount less the fee to spend it
+    let mut pool: Vec<(i128, &(OutPoint, TxOut))> = candidates
+        .iter()
+        .map(|candidate| {
+            let input_weight = weight(std::slice::from_ref(candidate), false) - weight(&[], false);
+            (candidate.1.amount as i128 - fee_for_weight(input_weight, fee_rate) as i128, candidate)
+        })
+        .filter(|(effective_value, _)| *effective_value > 0)
+        .collect();
+    pool.sort_by_key(|(effective_value, _)| Reverse(*effective_value));
+    // Leftover below this is cheaper to give to the fee than to return
+    let cost_of_change = fee_for_weight(OUTPUT_SIZE * 4, fee_rate) + DEFAULT_DUST_THRESHOLD;
+    let mut search = Search {
+        pool: &pool,
+        target,
+        fee_rate,
+        lower: target as i128 + fee_for_weight(weight(&[], false), fee_rate) as i128,
+        cost_of_change,
+        selected: Vec::new(),
+        tries: 0,
+    };
+    let remaining = pool.iter().map(|(effective_value, _)| effective_value).sum();
+    match search.run(0, 0, remaining) {
+        Some(selection) => Ok(selection),
+        None => largest_first(candidates, target, fee_rate),
+    }
+}
+
+struct Search<'a> {
+    pool: &'a [(i128, &'a (OutPoint, TxOut))],
+    target: u64,
+    fee_rate: f64,
+    lower: i128,
+    cost_of_change: u64,
+    selected: Vec<(OutPoint, TxOut)>,
+    tries: usize,
+}
+
+impl Search<'_> {
+    // Tries including, then excluding, the coin at index, backtracking
+    // once the selection overshoots or can no longer reach the target
+    fn run(&mut self, index: usize, current: i128, remaining: i128) -> Option<Selection> {
+        self.tries += 1;
+        if self.tries > BNB_MAX_TRIES || current > self.lower + self.cost_of_change as i128 {
+            return None;
+        }
+        if current >= self.lower {
+            // Effective values only approximate the rounded fee, so check
+            // the selection as a whole. On overflow largest_first reports it.
+            let available = self
+                .selected
+                .iter()
+                .try_fold(0u64, |sum, (_, prevout)| sum.checked_add(prevout.amount))?;
+            let fee = fee_for_weight(weight(&self.selected, false), self.fee_rate);
+            return match available.checked_sub(self.target) {
+                Some(excess) if excess >= fee && excess - fee < self.cost_of_change => {
+                    Some(Selection { inputs: self.selected.clone(), fee: excess, change: 0 })
+                }
+                _ => None,
+            };
+        }
+        if index == self.pool.len() || current + remaining < self.lower {
+            return None;
+        }
+        let (effective_value, candidate) = self.pool[index];
+        self.selected.push(candidate.clone());
+        let found = self.run(index + 1, current + effective_value, remaining - effective_value);
+        self.selected.pop();
+        found.or_else(|| self.run(index + 1, current, remaining - effective_value))
+    }
+}
+
+// Weight of the signed transaction spending these coins, the same worst
+// case TxBuilder assumes
+fn weight(inputs: &[(OutPoint, TxOut)], change: bool) -> usize {
+    let outputs = if change { 2 } else { 1 };
+    let unsigned_size = TX_OVERHEAD_SIZE + outputs * OUTPUT_SIZE + inputs.len() * EMPTY_INPUT_SIZE;
+    signed_size_and_weight(unsigned_size, inputs.iter().map(|(_, prevout)| &prevout.script_pubkey)).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::TxBuilder;
+    use crate::ecc::PrivateKey;
+    use crate::op::OpCode;
+    use crate::script::{Cmd, Script};
+    use crate::u256::U256;
+
+    fn key() -> PrivateKey {
+        PrivateKey::new(U256::from_u64(8675309))
+    }
+
+    // p2wpkh coins of 100000, 50000, 30000, 20000 and 7000
+    fn candidates() -> Vec<(OutPoint, TxOut)> {
+        let h160 = crate::hashes::hash160(&key().point.sec(true));
+        let script_pubkey = Script::new(vec![Cmd::Op(OpCode::OP_0), Cmd::Data(h160.to_vec())]);
+        [100_000, 50_000, 30_000, 20_000, 7_000]
+            .into_iter()
+            .enumerate()
+            .map(|(i, amount)| (OutPoint::new([i as u8 + 1; 32], 0), TxOut { amount, script_pubkey: script_pubkey.clone() }))
+            .collect()
+    }
+
+    fn total(selection: &Selection) -> u64 {
+        selection.inputs.iter().map(|(_, prevout)| prevout.amount).sum()
+    }
+
+    #[test]
+    fn largest_first_adds_change() {
+        let selection = largest_first(&candidates(), 79_800, 1.0).unwrap();
+        assert_eq!(selection.inputs.len(), 1);
+        assert!(selection.change > 0);
+        assert_eq!(total(&selection), 79_800 + selection.fee + selection.change);
+    }
+
+    #[test]
+    fn branch_and_bound_finds_changeless_match() {
+        let key = key();
+        let selection = branch_and_bound(&candidates(), 79_800, 1.0).unwrap();
+        assert_eq!(selection.change, 0);
+        assert_eq!(total(&selection), 79_800 + selection.fee);
+        let mut builder = TxBuilder::new(true);
+        builder
+            .add_selection(&selection)
+            .add_output(key.point.address(true, true).as_str(), 79_800)
+            .change_address(&key.point.address(true, true))
+            .fee_rate(1.0);
+        let tx = builder.sign(&[key]).unwrap();
+        assert_eq!(tx.tx_outs.len(), 1);
+        assert!(tx.verify(&builder.prevouts()).unwrap());
+    }
+
+    #[test]
+    fn branch_and_bound_falls_back_to_change() {
+        let selection = branch_and_bound(&candidates(), 120_000, 2.0).unwrap();
+        assert!(selection.change > 0);
+        assert_eq!(total(&selection), 120_000 + selection.fee + selection.change);
+    }
+
+    #[test]
+    fn insufficient_funds_and_dust_change() {
+        let candidates = candidates();
+        assert!(matches!(
+            branch_and_bound(&candidates, 207_000, 1.0),
+            Err(CoinSelectionError::InsufficientFunds { available: 207_000, .. })
+        ));
+        assert!(matches!(largest_first(&candidates, 207_000, 1.0), Err(CoinSelectionError::InsufficientFunds { .. })));
+        // 400 over the target is too little for a change output
+        let selection = largest_first(&candidates[..1], 99_600, 1.0).unwrap();
+        assert_eq!((selection.change, selection.fee), (0, 400));
+    }
+
+    #[test]
+    fn amounts_overflowing_u64() {
+        let mut candidates = candidates();
+        candidates.truncate(2);
+        candidates[0].1.amount = u64::MAX - 1000;
+        candidates[1].1.amount = 1500;
+        assert!(matches!(branch_and_bound(&candidates, u64::MAX, 1.0), Err(CoinSelectionError::AmountOverflow)));
+        assert!(matches!(largest_first(&candidates, u64::MAX, 1.0), Err(CoinSelectionError::AmountOverflow)));
+    }
+}
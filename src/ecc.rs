@@ -0,0 +1,854 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::bech32::encode_segwit_address;
+use crate::error::ParseError;
+pub use crate::point::PointError;
+use crate::hashes::{hash160, hash256, hmac_sha256, sha256};
+use crate::u256::U256;
+use crate::util::{decode_base64, encode_base64, encode_varint, h160_to_p2pkh_address};
+
+// secp256k1: y^2 = x^3 + 7 over the prime P, with a generator of order N
+pub const P: U256 = U256::from_limbs([0xfffffffefffffc2f, 0xffffffffffffffff, 0xffffffffffffffff, 0xffffffffffffffff]);
+pub const N: U256 = U256::from_limbs([0xbfd25e8cd0364141, 0xbaaedce6af48a03b, 0xfffffffffffffffe, 0xffffffffffffffff]);
+
+// 2^256 - P, used to fold the high half of a product back into the field
+const P_FOLD: u64 = 0x1000003d1;
+
+pub const G: S256Point = S256Point {
+    coords: Some((
+        S256Field(U256::from_limbs([0x59f2815b16f81798, 0x029bfcdb2dce28d9, 0x55a06295ce870b07, 0x79be667ef9dcbbac])),
+        S256Field(U256::from_limbs([0x9c47d08ffb10d4b8, 0xfd17b448a6855419, 0x5da4fbfc0e1108a8, 0x483ada7726a3c465])),
+    )),
+};
+
+// An element of the secp256k1 base field, always reduced below P
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct S256Field(U256);
+
+impl S256Field {
+    pub fn new(num: U256) -> S256Field {
+        S256Field(num % P)
+    }
+
+    pub fn num(&self) -> U256 {
+        self.0
+    }
+
+    pub fn pow(self, exponent: U256) -> S256Field {
+        let mut result = S256Field(U256::ONE);
+        let mut base = self;
+        for i in 0..exponent.bits() {
+            if exponent.bit(i) {
+                result = result * base;
+            }
+            base = base * base;
+        }
+        result
+    }
+
+    pub fn inverse(self) -> S256Field {
+        self.pow(P - U256::from_u64(2))
+    }
+
+    // P % 4 == 3, so a square root is a^((P + 1) / 4)
+    pub fn sqrt(self) -> S256Field {
+        self.pow((P + U256::ONE) >> 2)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+}
+
+impl Add for S256Field {
+    type Output = S256Field;
+
+    fn add(self, other: S256Field) -> S256Field {
+        let (sum, overflow) = self.0.overflowing_add(other.0);
+        if overflow || sum >= P {
+            S256Field(sum.overflowing_sub(P).0)
+        } else {
+            S256Field(sum)
+        }
+    }
+}
+
+impl Sub for S256Field {
+    type Output = S256Field;
+
+    fn sub(self, other: S256Field) -> S256Field {
+        let (diff, borrow) = self.0.overflowing_sub(other.0);
+        if borrow {
+            S256Field(diff.overflowing_add(P).0)
+        } else {
+            S256Field(diff)
+        }
+    }
+}
+
+impl Mul for S256Field {
+    type Output = S256Field;
+
+    // high * 2^256 + low = high * P_FOLD + low (mod P), applied twice
+    fn mul(self, other: S256Field) -> S256Field {
+        let (low, high) = self.0.full_mul(other.0);
+        let (folded, top) = high.mul_u64(P_FOLD);
+        let (sum, carry) = low.overflowing_add(folded);
+        let top = U256::from_u64(top + carry as u64).mul_u64(P_FOLD).0;
+        let (mut sum, carry) = sum.overflowing_add(top);
+        if carry {
+            sum = sum + U256::from_u64(P_FOLD);
+        }
+        if sum >= P {
+            sum = sum - P;
+        }
+        S256Field(sum)
+    }
+}
+
+impl Div for S256Field {
+    type Output = S256Field;
+
+    fn div(self, other: S256Field) -> S256Field {
+        Mul::mul(self, other.inverse())
+    }
+}
+
+// A point on secp256k1, None being the point at infinity
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct S256Point {
+    coords: Option<(S256Field, S256Field)>,
+}
+
+impl S256Point {
+    pub fn new(x: S256Field, y: S256Field) -> Result<S256Point, PointError> {
+        if y * y != x * x * x + S256Field(U256::from_u64(7)) {
+            return Err(PointError::NotOnCurve);
+        }
+        Ok(S256Point { coords: Some((x, y)) })
+    }
+
+    pub fn infinity() -> S256Point {
+        S256Point { coords: None }
+    }
+
+    pub fn is_infinity(&self) -> bool {
+        self.coords.is_none()
+    }
+
+    pub fn x(&self) -> Option<S256Field> {
+        self.coords.map(|(x, _)| x)
+    }
+
+    pub fn y(&self) -> Option<S256Field> {
+        self.coords.map(|(_, y)| y)
+    }
+
+    // SEC1 public key encoding, compressed or uncompressed
+    pub fn sec(&self, compressed: bool) -> Vec<u8> {
+        let Some((x, y)) = self.coords else {
+            return vec![0x00];
+        };
+        if compressed {
+            let prefix = if y.num().bit(0) { 0x03 } else { 0x02 };
+            let mut result = vec![prefix];
+            result.extend_from_slice(&x.num().to_be_bytes());
+            result
+        } else {
+            let mut result = vec![0x04];
+            result.extend_from_slice(&x.num().to_be_bytes());
+            result.extend_from_slice(&y.num().to_be_bytes());
+            result
+        }
+    }
+
+    // p2pkh address of the SEC encoding
+    pub fn address(&self, compressed: bool, testnet: bool) -> String {
+        h160_to_p2pkh_address(&hash160(&self.sec(compressed)), testnet)
+    }
+
+    // p2wpkh address, which always commits to the compressed SEC
+    pub fn segwit_address(&self, testnet: bool) -> String {
+        encode_segwit_address(0, &hash160(&self.sec(true)), testnet)
+    }
+
+    pub fn parse(sec: &[u8]) -> Result<S256Point, ParseError> {
+        const INVALID_SEC: ParseError = ParseError::InvalidFormat("invalid SEC encoding");
+        let coordinate = |bytes: &[u8]| {
+            let num = U256::from_be_slice(bytes).ok_or(INVALID_SEC)?;
+            if num >= P {
+                return Err(INVALID_SEC);
+            }
+            Ok(S256Field(num))
+        };
+        let point = match sec {
+            [0x04, rest @ ..] if rest.len() == 64 => S256Point::new(coordinate(&rest[..32])?, coordinate(&rest[32..])?),
+            [prefix @ (0x02 | 0x03), rest @ ..] if rest.len() == 32 => {
+                let x = coordinate(rest)?;
+                let alpha = x * x * x + S256Field(U256::from_u64(7));
+                let beta = alpha.sqrt();
+                let y = if beta.num().bit(0) == (*prefix == 0x03) { beta } else { S256Field(P - beta.num()) };
+                S256Point::new(x, y)
+            }
+            _ => return Err(INVALID_SEC),
+        };
+        point.map_err(|_| ParseError::InvalidFormat("point is not on the curve"))
+    }
+
+    pub fn verify(&self, z: U256, sig: &Signature) -> bool {
+        if sig.r.is_zero() || sig.r >= N || sig.s.is_zero() || sig.s >= N {
+            return false;
+        }
+        let s_inv = sig.s.inv_mod(N);
+        let u = z.mul_mod(s_inv, N);
+        let v = sig.r.mul_mod(s_inv, N);
+        let total = S256Point::multi_scalar_mul(&[(u, G), (v, *self)]);
+        match total.x() {
+            Some(x) => x.num() % N == sig.r,
+            None => false,
+        }
+    }
+
+    // Straus: the scalars share a single run of doublings, each adding
+    // its point wherever it has a bit set
+    pub fn multi_scalar_mul(pairs: &[(U256, S256Point)]) -> S256Point {
+        let pairs: Vec<(U256, Jacobian)> =
+            pairs.iter().map(|(coefficient, point)| (coefficient.div_rem(N).1, point.to_jacobian())).collect();
+        let bits = pairs.iter().map(|(coefficient, _)| coefficient.bits()).max().unwrap_or(0);
+        let mut result = Jacobian::INFINITY;
+        for i in (0..bits).rev() {
+            result = result.double();
+            for (coefficient, point) in &pairs {
+                if coefficient.bit(i) {
+                    result = result.add(point);
+                }
+            }
+        }
+        result.to_affine()
+    }
+
+    // True exactly when every (pubkey, z, signature) verifies. Each chunk
+    // is first tried as one random linear combination of the verification
+    // equations and only checked signature by signature if that fails.
+    pub fn verify_batch(items: &[(S256Point, U256, Signature)]) -> bool {
+        items.chunks(BATCH_SIZE).all(|chunk| {
+            batch_holds(chunk) || chunk.iter().all(|(point, z, sig)| point.verify(*z, sig))
+        })
+    }
+
+    fn to_jacobian(self) -> Jacobian {
+        match self.coords {
+            Some((x, y)) => Jacobian { x, y, z: S256Field(U256::ONE) },
+            None => Jacobian::INFINITY,
+        }
+    }
+}
+
+impl Add for S256Point {
+    type Output = S256Point;
+
+    fn add(self, other: S256Point) -> S256Point {
+        self.to_jacobian().add(&other.to_jacobian()).to_affine()
+    }
+}
+
+impl Mul<U256> for S256Point {
+    type Output = S256Point;
+
+    fn mul(self, coefficient: U256) -> S256Point {
+        let coefficient = coefficient.div_rem(N).1;
+        let base = self.to_jacobian();
+        let mut result = Jacobian::INFINITY;
+        for i in (0..coefficient.bits()).rev() {
+            result = result.double();
+            if coefficient.bit(i) {
+                result = result.add(&base);
+            }
+        }
+        result.to_affine()
+    }
+}
+
+impl Mul<S256Point> for U256 {
+    type Output = S256Point;
+
+    fn mul(self, point: S256Point) -> S256Point {
+        point * self
+    }
+}
+
+// Jacobian coordinates (x / z^2, y / z^3) avoid an inversion per addition;
+// z == 0 is the point at infinity
+#[derive(Clone, Copy)]
+struct Jacobian {
+    x: S256Field,
+    y: S256Field,
+    z: S256Field,
+}
+
+impl Jacobian {
+    const INFINITY: Jacobian = Jacobian { x: S256Field(U256::ONE), y: S256Field(U256::ONE), z: S256Field(U256::ZERO) };
+
+    fn double(&self) -> Jacobian {
+        if self.z.is_zero() || self.y.is_zero() {
+            return Jacobian::INFINITY;
+        }
+        let y2 = self.y * self.y;
+        let s = S256Field(U256::from_u64(4)) * self.x * y2;
+        let m = S256Field(U256::from_u64(3)) * self.x * self.x;
+        let x = m * m - s - s;
+        let y = m * (s - x) - S256Field(U256::from_u64(8)) * y2 * y2;
+        let z = S256Field(U256::from_u64(2)) * self.y * self.z;
+        Jacobian { x, y, z }
+    }
+
+    fn add(&self, other: &Jacobian) -> Jacobian {
+        if self.z.is_zero() {
+            return *other;
+        }
+        if other.z.is_zero() {
+            return *self;
+        }
+        let z1z1 = self.z * self.z;
+        let z2z2 = other.z * other.z;
+        let u1 = self.x * z2z2;
+        let u2 = other.x * z1z1;
+        let s1 = self.y * z2z2 * other.z;
+        let s2 = other.y * z1z1 * self.z;
+        if u1 == u2 {
+            return if s1 == s2 { self.double() } else { Jacobian::INFINITY };
+        }
+        let h = u2 - u1;
+        let r = s2 - s1;
+        let h2 = h * h;
+        let h3 = h2 * h;
+        let u1h2 = u1 * h2;
+        let x = r * r - h3 - u1h2 - u1h2;
+        let y = r * (u1h2 - x) - s1 * h3;
+        let z = h * self.z * other.z;
+        Jacobian { x, y, z }
+    }
+
+    fn negate(&self) -> Jacobian {
+        Jacobian { y: S256Field::new(P - self.y.num()), ..*self }
+    }
+
+    // Same point as the affine (x, y) or its negation
+    fn matches_up_to_sign(&self, point: &S256Point) -> bool {
+        match point.coords {
+            None => self.z.is_zero(),
+            Some((x, y)) => {
+                let z2 = self.z * self.z;
+                let z3 = z2 * self.z;
+                let y = y * z3;
+                !self.z.is_zero() && self.x == x * z2 && (self.y == y || self.y == S256Field::new(P - y.num()))
+            }
+        }
+    }
+
+    fn to_affine(self) -> S256Point {
+        if self.z.is_zero() {
+            return S256Point::infinity();
+        }
+        let z_inv = self.z.inverse();
+        let z_inv2 = z_inv * z_inv;
+        S256Point { coords: Some((self.x * z_inv2, self.y * z_inv2 * z_inv)) }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Signature {
+    pub r: U256,
+    pub s: U256,
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Signature({:x},{:x})", self.r, self.s)
+    }
+}
+
+impl Signature {
+    pub fn new(r: U256, s: U256) -> Signature {
+        Signature { r, s }
+    }
+
+    pub fn is_low_s(&self) -> bool {
+        self.s <= N >> 1
+    }
+
+    // BIP62: (r, n - s) verifies just as well as (r, s), so only the low
+    // form is standard, the other is a malleated copy
+    pub fn normalize_s(&mut self) {
+        if !self.is_low_s() {
+            self.s = N - self.s;
+        }
+    }
+
+    // The recovery id picks R among the points with x = r or r + N (bit 1)
+    // by the parity of its y (bit 0), then pubkey = r^-1 (s R - z G)
+    pub fn recover_public_key(&self, z: U256, recovery_id: u8) -> Result<S256Point, PointError> {
+        if recovery_id > 3 {
+            return Err(PointError::InvalidRecoveryId(recovery_id));
+        }
+        if self.r.is_zero() || self.r >= N || self.s.is_zero() || self.s >= N {
+            return Err(PointError::NotRecoverable);
+        }
+        let x = if recovery_id & 2 == 0 {
+            self.r
+        } else {
+            match self.r.overflowing_add(N) {
+                (x, false) if x < P => x,
+                _ => return Err(PointError::NotRecoverable),
+            }
+        };
+        let x = S256Field(x);
+        let alpha = x * x * x + S256Field(U256::from_u64(7));
+        let beta = alpha.sqrt();
+        if beta * beta != alpha {
+            return Err(PointError::NotRecoverable);
+        }
+        let y = if beta.num().bit(0) == (recovery_id & 1 == 1) { beta } else { S256Field(P - beta.num()) };
+        let big_r = S256Point { coords: Some((x, y)) };
+        let r_inv = self.r.inv_mod(N);
+        let point = S256Point::multi_scalar_mul(&[
+            (self.s.mul_mod(r_inv, N), big_r),
+            (N - z.div_rem(N).1.mul_mod(r_inv, N), G),
+        ]);
+        if point.is_infinity() {
+            return Err(PointError::NotRecoverable);
+        }
+        Ok(point)
+    }
+
+    // Signed-message layout: a header of 27 + recovery id, plus 4 for a
+    // compressed key, then r and s as 32 bytes each
+    pub fn to_compact(&self, recovery_id: u8, compressed: bool) -> [u8; 65] {
+        let mut result = [0u8; 65];
+        result[0] = 27 + (recovery_id & 3) + if compressed { 4 } else { 0 };
+        result[1..33].copy_from_slice(&self.r.to_be_bytes());
+        result[33..].copy_from_slice(&self.s.to_be_bytes());
+        result
+    }
+
+    // The signature, its recovery id and whether the key is compressed
+    pub fn from_compact(compact: &[u8; 65]) -> Result<(Signature, u8, bool), ParseError> {
+        let header = compact[0];
+        if !(27..=34).contains(&header) {
+            return Err(ParseError::InvalidFormat("invalid compact signature header"));
+        }
+        let mut r = [0u8; 32];
+        r.copy_from_slice(&compact[1..33]);
+        let mut s = [0u8; 32];
+        s.copy_from_slice(&compact[33..]);
+        let sig = Signature { r: U256::from_be_bytes(r), s: U256::from_be_bytes(s) };
+        Ok((sig, (header - 27) & 3, header >= 31))
+    }
+
+    pub fn der(&self) -> Vec<u8> {
+        let mut result = der_integer(self.r);
+        result.extend(der_integer(self.s));
+        let mut der = vec![0x30, result.len() as u8];
+        der.extend(result);
+        der
+    }
+
+    pub fn parse(der: &[u8]) -> Result<Signature, ParseError> {
+        let [0x30, length, rest @ ..] = der else {
+            return Err(INVALID_DER);
+        };
+        if *length as usize != rest.len() {
+            return Err(INVALID_DER);
+        }
+        let (r, rest) = parse_der_integer(rest)?;
+        let (s, rest) = parse_der_integer(rest)?;
+        if !rest.is_empty() {
+            return Err(INVALID_DER);
+        }
+        Ok(Signature { r, s })
+    }
+}
+
+#[derive(Clone)]
+pub struct PrivateKey {
+    secret: U256,
+    pub point: S256Point,
+}
+
+impl PrivateKey {
+    pub fn new(secret: U256) -> PrivateKey {
+        PrivateKey { secret, point: G * secret }
+    }
+
+    pub fn secret(&self) -> U256 {
+        self.secret
+    }
+
+    pub fn sign(&self, z: U256) -> Signature {
+        self.sign_recoverable(z).0
+    }
+
+    // The signature along with the recovery id that gives back this key
+    pub fn sign_recoverable(&self, z: U256) -> (Signature, u8) {
+        let k = self.deterministic_k(z);
+        let (x, y) = (G * k).coords.expect("k is non-zero below N");
+        let r = x.num() % N;
+        let k_inv = k.inv_mod(N);
+        let s = z.add_mod(r.mul_mod(self.secret, N), N).mul_mod(k_inv, N);
+        let mut recovery_id = y.num().bit(0) as u8 | (((x.num() >= N) as u8) << 1);
+        let mut sig = Signature { r, s };
+        if !sig.is_low_s() {
+            // n - s signs with -k, whose R has the other y
+            recovery_id ^= 1;
+        }
+        sig.normalize_s();
+        (sig, recovery_id)
+    }
+
+    // signmessage: a compact signature over the prefixed message, in
+    // base64, for the compressed key
+    pub fn sign_message(&self, message: &[u8]) -> String {
+        let (sig, recovery_id) = self.sign_recoverable(message_digest(message));
+        encode_base64(&sig.to_compact(recovery_id, true))
+    }
+
+    // RFC 6979 nonce from HMAC-SHA256 over the secret and the digest, so
+    // signing the same message twice gives the same signature
+    fn deterministic_k(&self, z: U256) -> U256 {
+        let z = if z >= N { z - N } else { z };
+        let secret_bytes = self.secret.to_be_bytes();
+        let z_bytes = z.to_be_bytes();
+        let mut k = [0u8; 32];
+        let mut v = [1u8; 32];
+        for marker in [0x00, 0x01] {
+            let mut data = v.to_vec();
+            data.push(marker);
+            data.extend_from_slice(&secret_bytes);
+            data.extend_from_slice(&z_bytes);
+            k = hmac_sha256(&k, &data);
+            v = hmac_sha256(&k, &v);
+        }
+        loop {
+            v = hmac_sha256(&k, &v);
+            let candidate = U256::from_be_bytes(v);
+            if !candidate.is_zero() && candidate < N {
+                return candidate;
+            }
+            let mut data = v.to_vec();
+            data.push(0x00);
+            k = hmac_sha256(&k, &data);
+            v = hmac_sha256(&k, &v);
+        }
+    }
+}
+
+// Clones are separate copies of the secret, and each is wiped on its own drop
+#[cfg(feature = "zeroize")]
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.secret);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for PrivateKey {}
+
+#[cfg(feature = "zeroize")]
+const _: fn() = || {
+    fn assert_zeroize_on_drop<T: zeroize::ZeroizeOnDrop>() {}
+    assert_zeroize_on_drop::<PrivateKey>();
+};
+
+const MESSAGE_MAGIC: &[u8] = b"\x18Bitcoin Signed Message:\n";
+
+fn message_digest(message: &[u8]) -> U256 {
+    let mut data = MESSAGE_MAGIC.to_vec();
+    data.extend(encode_varint(message.len() as u64));
+    data.extend_from_slice(message);
+    U256::from_be_bytes(hash256(&data))
+}
+
+// Recovers the key from a base64 signmessage signature and checks it
+// against a p2pkh address on either network
+pub fn verify_message(address: &str, message: &[u8], signature: &str) -> bool {
+    let Ok(compact) = <[u8; 65]>::try_from(decode_base64(signature).unwrap_or_default()) else {
+        return false;
+    };
+    let Ok((sig, recovery_id, compressed)) = Signature::from_compact(&compact) else {
+        return false;
+    };
+    match sig.recover_public_key(message_digest(message), recovery_id) {
+        Ok(point) => [false, true].iter().any(|&testnet| point.address(compressed, testnet) == address),
+        Err(_) => false,
+    }
+}
+
+// Signatures per batch equation. The parity search in batch_holds is
+// exponential in it.
+const BATCH_SIZE: usize = 8;
+
+// Random a_i (a_1 = 1) turn the equations R_i = u_i G + v_i P_i into the
+// single check sum(a_i R_i) = (sum a_i u_i) G + sum(a_i v_i P_i). An
+// ECDSA signature only gives the x of R_i, so each a_i R_i is added with
+// either sign, walking all sign choices in Gray code order. A false
+// result does not mean a signature is invalid: x(R) may also be r + N, or
+// r may not be on the curve at all, which verify itself decides.
+fn batch_holds(items: &[(S256Point, U256, Signature)]) -> bool {
+    let mut seed_hasher = RandomState::new().build_hasher();
+    seed_hasher.write_usize(items.len());
+    let mut seed = seed_hasher.finish().to_le_bytes().to_vec();
+    let mut pairs = Vec::new();
+    let mut g_coefficient = U256::ZERO;
+    let mut multiples = Vec::new();
+    for (i, (point, z, sig)) in items.iter().enumerate() {
+        if sig.r.is_zero() || sig.r >= N || sig.s.is_zero() || sig.s >= N || point.is_infinity() {
+            return false;
+        }
+        let x = S256Field(sig.r);
+        let alpha = x * x * x + S256Field(U256::from_u64(7));
+        let beta = alpha.sqrt();
+        if beta * beta != alpha {
+            return false;
+        }
+        seed.extend_from_slice(&z.to_be_bytes());
+        seed.extend_from_slice(&sig.r.to_be_bytes());
+        seed.extend_from_slice(&sig.s.to_be_bytes());
+        seed.extend(point.sec(true));
+        let a = if i == 0 {
+            U256::ONE
+        } else {
+            let digest = sha256(&[seed.as_slice(), &(i as u64).to_le_bytes()].concat());
+            U256::from_be_slice(&digest[..16]).unwrap_or(U256::ONE)
+        };
+        let s_inv = sig.s.inv_mod(N);
+        g_coefficient = g_coefficient.add_mod(a.mul_mod(z.mul_mod(s_inv, N), N), N);
+        pairs.push((a.mul_mod(sig.r.mul_mod(s_inv, N), N), *point));
+        multiples.push((S256Point { coords: Some((x, beta)) } * a).to_jacobian());
+    }
+    pairs.push((g_coefficient, G));
+    let expected = S256Point::multi_scalar_mul(&pairs);
+    let mut total = multiples.iter().fold(Jacobian::INFINITY, |total, multiple| total.add(multiple));
+    let mut negated = vec![false; multiples.len()];
+    // With the first sign fixed, a match against +/- expected covers the rest
+    for step in 1..=(1usize << (multiples.len() - 1)) {
+        if total.matches_up_to_sign(&expected) {
+            return true;
+        }
+        let i = step.trailing_zeros() as usize + 1;
+        if i >= multiples.len() {
+            break;
+        }
+        let double = multiples[i].double();
+        total = total.add(&if negated[i] { double } else { double.negate() });
+        negated[i] = !negated[i];
+    }
+    false
+}
+
+const INVALID_DER: ParseError = ParseError::InvalidFormat("invalid DER signature");
+
+fn der_integer(num: U256) -> Vec<u8> {
+    let bytes = num.to_be_bytes();
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(31);
+    let mut body = bytes[start..].to_vec();
+    // A set high bit would read as negative, so pad with a zero byte
+    if body[0] & 0x80 != 0 {
+        body.insert(0, 0x00);
+    }
+    let mut result = vec![0x02, body.len() as u8];
+    result.extend(body);
+    result
+}
+
+fn parse_der_integer(bytes: &[u8]) -> Result<(U256, &[u8]), ParseError> {
+    let [0x02, length, rest @ ..] = bytes else {
+        return Err(INVALID_DER);
+    };
+    let length = *length as usize;
+    if length == 0 || length > rest.len() {
+        return Err(INVALID_DER);
+    }
+    let (body, rest) = rest.split_at(length);
+    let body = match body {
+        [0x00, tail @ ..] => tail,
+        _ => body,
+    };
+    let num = U256::from_be_slice(body).ok_or(INVALID_DER)?;
+    Ok((num, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::decode_hex;
+
+    fn u256(hex: &str) -> U256 {
+        U256::from_be_slice(&decode_hex(hex).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn verify_signature() {
+        let z = u256("bc62d4b80d9e36da29c16c5d4d9f11731f36052c72401a76c23c0fb5a9b74423");
+        let r = u256("37206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c6");
+        let s = u256("8ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec");
+        let point = S256Point::new(
+            S256Field::new(u256("04519fac3d910ca7e7138f7013706f619fa8f033e6ec6e09370ea38cee6a7574")),
+            S256Field::new(u256("82b51eab8c27c66e26c858a079bcdf4f1ada34cec420cafc7eac1a42216fb6c4")),
+        )
+        .unwrap();
+        assert!(point.verify(z, &Signature::new(r, s)));
+        assert!(!point.verify(z + U256::ONE, &Signature::new(r, s)));
+    }
+
+    #[test]
+    fn sec_and_der_round_trip() {
+        let point = PrivateKey::new(U256::from_u64(5001)).point;
+        assert_eq!(S256Point::parse(&point.sec(true)).unwrap(), point);
+        assert_eq!(S256Point::parse(&point.sec(false)).unwrap(), point);
+        let sig = Signature::new(u256("37206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c6"), U256::ONE);
+        assert_eq!(Signature::parse(&sig.der()).unwrap(), sig);
+    }
+
+    #[test]
+    fn scalar_multiplication() {
+        assert!((G * N).is_infinity());
+        assert_eq!(G * U256::from_u64(5), G + G + G + G + G);
+        let x = (G * U256::from_u64(5000)).x().unwrap().num();
+        assert_eq!(format!("{:x}", x), "ffe558e388852f0120e46af2d1b370f85854a8eb0841811ece0e3e03d282d57c");
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let key = PrivateKey::new(U256::from_u64(12345));
+        let z = U256::from_u64(987654321);
+        let sig = key.sign(z);
+        assert!(key.point.verify(z, &sig));
+        // RFC 6979 nonces make signing deterministic
+        assert_eq!(key.sign(z), sig);
+        assert!(!key.point.verify(z + U256::ONE, &sig));
+    }
+
+    #[test]
+    fn p2pkh_addresses() {
+        let address =
+            |secret: u64, compressed, testnet| PrivateKey::new(U256::from_u64(secret)).point.address(compressed, testnet);
+        assert_eq!(address(5002, false, true), "mmTPbXQFxboEtNRkwfh6K51jvdtHLxGeMA");
+        assert_eq!(address(2020u64.pow(5), true, true), "mopVkxp8UhXqRYbCYJsbeE1h1fiF64jcoH");
+        assert_eq!(address(0x12345deadbeef, true, false), "1F1Pn2y6pDb68E5nYJJeba4TLg2U7B6KF1");
+    }
+
+    #[test]
+    fn low_s_normalization() {
+        let key = PrivateKey::new(U256::from_u64(12345));
+        let z = U256::from_u64(987654321);
+        let sig = key.sign(z);
+        assert!(sig.is_low_s());
+        let mut high = Signature::new(sig.r, N - sig.s);
+        assert!(!high.is_low_s());
+        assert!(key.point.verify(z, &high));
+        let high_der = high.der();
+        high.normalize_s();
+        assert_eq!(high, sig);
+        assert_ne!(high.der(), high_der);
+    }
+
+    #[test]
+    fn recover_public_key() {
+        for i in 1..20 {
+            let key = PrivateKey::new(U256::from_u64(i * 104729 + 3));
+            let z = U256::from_u64(i * 99991);
+            let sig = key.sign(z);
+            let matching: Vec<u8> =
+                (0..4).filter(|&id| sig.recover_public_key(z, id).is_ok_and(|point| point == key.point)).collect();
+            assert_eq!(matching.len(), 1);
+            // Every key that can be recovered verifies the signature
+            for id in 0..4 {
+                if let Ok(point) = sig.recover_public_key(z, id) {
+                    assert!(point.verify(z, &sig));
+                }
+            }
+            assert!(matches!(sig.recover_public_key(z, 4), Err(PointError::InvalidRecoveryId(4))));
+        }
+    }
+
+    #[test]
+    fn compact_signatures() {
+        for i in 1..10 {
+            let key = PrivateKey::new(U256::from_u64(i * 7777 + 1));
+            let z = U256::from_u64(i * 13);
+            let (sig, recovery_id) = key.sign_recoverable(z);
+            assert_eq!(sig, key.sign(z));
+            assert_eq!(sig.recover_public_key(z, recovery_id).unwrap(), key.point);
+            for compressed in [true, false] {
+                let compact = sig.to_compact(recovery_id, compressed);
+                assert_eq!(compact[0], 27 + recovery_id + if compressed { 4 } else { 0 });
+                assert_eq!(Signature::from_compact(&compact).unwrap(), (sig, recovery_id, compressed));
+            }
+        }
+        let mut compact = [0; 65];
+        compact[0] = 35;
+        assert!(Signature::from_compact(&compact).is_err());
+    }
+
+    #[test]
+    fn sign_and_verify_message() {
+        let wif = crate::util::decode_base58_checksum("L4rK1yDtCWekvXuE6oXD9jCYfFNV2cWRpVuPLBcCU2z8TrisoyY1").unwrap();
+        let key = PrivateKey::new(U256::from_be_slice(&wif[1..33]).unwrap());
+        let address = "1F3sAm6ZtwLAUnj7d38pGFxtP3RVEvtsbV";
+        let message = b"This is an example of a signed message.";
+        // A signature made by another wallet for the same key
+        let reference = "H9L5yLFjti0QTHhPyFrZCT1V/MMnBtXKmoiKDZ78NDBjERki6ZTQZdSMCtkgoNmp17By9ItJr8o7ChX0XxY91nk=";
+        assert!(verify_message(address, message, reference));
+        let signature = key.sign_message(message);
+        assert!(verify_message(address, message, &signature));
+        assert!(verify_message(&key.point.address(true, true), message, &signature));
+        assert!(!verify_message(address, b"other", reference));
+        assert!(!verify_message(address, message, "garbage"));
+    }
+
+    #[test]
+    fn segwit_address() {
+        assert_eq!(PrivateKey::new(U256::ONE).point.segwit_address(false), "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4");
+    }
+
+    #[test]
+    fn batch_verification() {
+        assert!(S256Point::verify_batch(&[]));
+        // More than one chunk, so the bad item lands in the second
+        let mut items: Vec<(S256Point, U256, Signature)> = (1..=BATCH_SIZE as u64 + 2)
+            .map(|secret| {
+                let key = PrivateKey::new(U256::from_u64(secret * 1000));
+                let z = U256::from_u64(secret);
+                (key.point, z, key.sign(z))
+            })
+            .collect();
+        assert!(S256Point::verify_batch(&items));
+        items[BATCH_SIZE].1 = U256::from_u64(0xdead);
+        assert!(!S256Point::verify_batch(&items));
+        items[BATCH_SIZE].1 = U256::from_u64(BATCH_SIZE as u64 + 1);
+        items[0].0 = items[1].0;
+        assert!(!S256Point::verify_batch(&items));
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn drop_wipes_secret() {
+        let mut key = core::mem::ManuallyDrop::new(PrivateKey::new(U256::from_u64(12345)));
+        let copy = PrivateKey::clone(&key);
+        // Safety: key is not dropped again, and its fields are plain data
+        // that stay valid to read once wiped
+        unsafe { core::mem::ManuallyDrop::drop(&mut key) };
+        assert_eq!(key.secret(), U256::ZERO);
+        assert_eq!(copy.secret(), U256::from_u64(12345));
+    }
+
+    #[test]
+    fn signature_display() {
+        let sig = Signature::new(U256::from_u64(0xab), U256::from_u64(1));
+        let padded = |last: &str| format!("{:0>64}", last);
+        assert_eq!(sig.to_string(), format!("Signature({},{})", padded("ab"), padded("1")));
+    }
+}
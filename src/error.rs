@@ -0,0 +1,319 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::bech32::Bech32Error;
+use crate::bip32::Bip32Error;
+use crate::bip39::Bip39Error;
+use crate::block::{BlockError, ChainError};
+use crate::builder::BuilderError;
+use crate::coin_selection::CoinSelectionError;
+use crate::field::FieldElementError;
+use crate::merkle::MerkleError;
+use crate::network::NodeError;
+use crate::point::PointError;
+use crate::polynomial::PolynomialError;
+use crate::psbt::PsbtError;
+use crate::script::ScriptError;
+use crate::tx::{FetchError, TxError};
+use crate::util::{Base58Error, Base64Error, HexError};
+
+// Returned by every parse method. Running out of input is reported as
+// UnexpectedEof rather than as a generic Io error.
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    UnexpectedEof,
+    InvalidFormat(&'static str),
+    BadChecksum,
+    WrongMagic { expected: [u8; 4], actual: [u8; 4] },
+    ConnectionClosed,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(err) => write!(f, "Failed to read input: {}", err),
+            ParseError::UnexpectedEof => write!(f, "Input ended unexpectedly"),
+            ParseError::InvalidFormat(reason) => write!(f, "Invalid format: {}", reason),
+            ParseError::BadChecksum => write!(f, "Checksum does not match"),
+            ParseError::WrongMagic { expected, actual } => {
+                write!(f, "Expected network magic {:02x?}, got {:02x?}", expected, actual)
+            }
+            ParseError::ConnectionClosed => write!(f, "Connection closed by peer"),
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> ParseError {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => ParseError::UnexpectedEof,
+            _ => ParseError::Io(err),
+        }
+    }
+}
+
+// One error for everything in the crate, so `?` works across modules
+#[derive(Debug)]
+pub enum Error {
+    FieldElement(FieldElementError),
+    Point(PointError),
+    Parse(ParseError),
+    Script(ScriptError),
+    Tx(TxError),
+    Fetch(FetchError),
+    Builder(BuilderError),
+    CoinSelection(CoinSelectionError),
+    Psbt(PsbtError),
+    Hex(HexError),
+    Base58(Base58Error),
+    Base64(Base64Error),
+    Bip32(Bip32Error),
+    Bip39(Bip39Error),
+    Bech32(Bech32Error),
+    Chain(ChainError),
+    Block(BlockError),
+    Merkle(MerkleError),
+    Polynomial(PolynomialError),
+    Node(NodeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::FieldElement(err) => write!(f, "{}", err),
+            Error::Point(err) => write!(f, "{}", err),
+            Error::Parse(err) => write!(f, "{}", err),
+            Error::Script(err) => write!(f, "{}", err),
+            Error::Tx(err) => write!(f, "{}", err),
+            Error::Fetch(err) => write!(f, "{}", err),
+            Error::Builder(err) => write!(f, "{}", err),
+            Error::CoinSelection(err) => write!(f, "{}", err),
+            Error::Psbt(err) => write!(f, "{}", err),
+            Error::Hex(err) => write!(f, "{}", err),
+            Error::Base58(err) => write!(f, "{}", err),
+            Error::Base64(err) => write!(f, "{}", err),
+            Error::Bip32(err) => write!(f, "{}", err),
+            Error::Bip39(err) => write!(f, "{}", err),
+            Error::Bech32(err) => write!(f, "{}", err),
+            Error::Chain(err) => write!(f, "{}", err),
+            Error::Block(err) => write!(f, "{}", err),
+            Error::Merkle(err) => write!(f, "{}", err),
+            Error::Polynomial(err) => write!(f, "{}", err),
+            Error::Node(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::FieldElement(err) => Some(err),
+            Error::Point(err) => Some(err),
+            Error::Parse(err) => Some(err),
+            Error::Script(err) => Some(err),
+            Error::Tx(err) => Some(err),
+            Error::Fetch(err) => Some(err),
+            Error::Builder(err) => Some(err),
+            Error::CoinSelection(err) => Some(err),
+            Error::Psbt(err) => Some(err),
+            Error::Hex(err) => Some(err),
+            Error::Base58(err) => Some(err),
+            Error::Base64(err) => Some(err),
+            Error::Bip32(err) => Some(err),
+            Error::Bip39(err) => Some(err),
+            Error::Bech32(err) => Some(err),
+            Error::Chain(err) => Some(err),
+            Error::Block(err) => Some(err),
+            Error::Merkle(err) => Some(err),
+            Error::Polynomial(err) => Some(err),
+            Error::Node(err) => Some(err),
+        }
+    }
+}
+
+impl error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParseError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl error::Error for FieldElementError {}
+impl error::Error for PointError {}
+impl error::Error for ScriptError {}
+impl error::Error for TxError {}
+impl error::Error for FetchError {}
+impl error::Error for BuilderError {}
+impl error::Error for CoinSelectionError {}
+impl error::Error for PsbtError {}
+impl error::Error for HexError {}
+impl error::Error for Base58Error {}
+impl error::Error for Base64Error {}
+impl error::Error for Bip32Error {}
+impl error::Error for Bip39Error {}
+impl error::Error for Bech32Error {}
+impl error::Error for ChainError {}
+impl error::Error for BlockError {}
+impl error::Error for MerkleError {}
+impl error::Error for PolynomialError {}
+impl error::Error for NodeError {}
+
+impl From<FieldElementError> for Error {
+    fn from(err: FieldElementError) -> Error {
+        Error::FieldElement(err)
+    }
+}
+
+impl From<PointError> for Error {
+    fn from(err: PointError) -> Error {
+        Error::Point(err)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Error {
+        Error::Parse(err)
+    }
+}
+
+impl From<ScriptError> for Error {
+    fn from(err: ScriptError) -> Error {
+        Error::Script(err)
+    }
+}
+
+impl From<TxError> for Error {
+    fn from(err: TxError) -> Error {
+        Error::Tx(err)
+    }
+}
+
+impl From<FetchError> for Error {
+    fn from(err: FetchError) -> Error {
+        Error::Fetch(err)
+    }
+}
+
+impl From<BuilderError> for Error {
+    fn from(err: BuilderError) -> Error {
+        Error::Builder(err)
+    }
+}
+
+impl From<CoinSelectionError> for Error {
+    fn from(err: CoinSelectionError) -> Error {
+        Error::CoinSelection(err)
+    }
+}
+
+impl From<PsbtError> for Error {
+    fn from(err: PsbtError) -> Error {
+        Error::Psbt(err)
+    }
+}
+
+impl From<HexError> for Error {
+    fn from(err: HexError) -> Error {
+        Error::Hex(err)
+    }
+}
+
+impl From<Base58Error> for Error {
+    fn from(err: Base58Error) -> Error {
+        Error::Base58(err)
+    }
+}
+
+impl From<Base64Error> for Error {
+    fn from(err: Base64Error) -> Error {
+        Error::Base64(err)
+    }
+}
+
+impl From<Bip32Error> for Error {
+    fn from(err: Bip32Error) -> Error {
+        Error::Bip32(err)
+    }
+}
+
+impl From<Bip39Error> for Error {
+    fn from(err: Bip39Error) -> Error {
+        Error::Bip39(err)
+    }
+}
+
+impl From<Bech32Error> for Error {
+    fn from(err: Bech32Error) -> Error {
+        Error::Bech32(err)
+    }
+}
+
+impl From<ChainError> for Error {
+    fn from(err: ChainError) -> Error {
+        Error::Chain(err)
+    }
+}
+
+impl From<BlockError> for Error {
+    fn from(err: BlockError) -> Error {
+        Error::Block(err)
+    }
+}
+
+impl From<MerkleError> for Error {
+    fn from(err: MerkleError) -> Error {
+        Error::Merkle(err)
+    }
+}
+
+impl From<PolynomialError> for Error {
+    fn from(err: PolynomialError) -> Error {
+        Error::Polynomial(err)
+    }
+}
+
+impl From<NodeError> for Error {
+    fn from(err: NodeError) -> Error {
+        Error::Node(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Parse(err.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    use crate::field::FieldElement;
+
+    fn add_across_fields() -> Result<FieldElement, Error> {
+        let a = FieldElement::new(1, 7)?;
+        let b = FieldElement::new(1, 11)?;
+        Ok((a + b)?)
+    }
+
+    #[test]
+    fn question_mark_converts() {
+        assert!(matches!(add_across_fields(), Err(Error::FieldElement(FieldElementError::DifferentFields))));
+        let err = add_across_fields().unwrap_err();
+        assert!(err.source().is_some());
+        assert_eq!(err.to_string(), FieldElementError::DifferentFields.to_string());
+    }
+
+    #[test]
+    fn eof_maps_to_unexpected_eof() {
+        let err = ParseError::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+        assert!(matches!(err, ParseError::UnexpectedEof));
+        let err = ParseError::from(io::Error::from(io::ErrorKind::Other));
+        assert!(matches!(err, ParseError::Io(_)));
+    }
+}
@@ -0,0 +1,610 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Add, Sub, Mul, Div};
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct FieldElement {
+    num: i64,
+    prime: i64,
+}
+
+// Fields above this print their elements in hex
+const DEBUG_HEX_THRESHOLD: i64 = 1 << 32;
+
+#[derive(Debug)]
+pub enum FieldElementError {
+    DifferentFields,
+    InvalidElement,
+    InvalidLength(usize),
+    NotCoprime(i64, i64),
+    Overflow,
+    NotPrime(i64),
+}
+
+impl fmt::Display for FieldElementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldElementError::DifferentFields => write!(f, "Cannot operate on elements from different fields"),
+            FieldElementError::InvalidElement => write!(f, "Element is not in valid field range"),
+            FieldElementError::InvalidLength(len) => write!(f, "{} bytes does not fit the field element", len),
+            FieldElementError::NotCoprime(a, b) => write!(f, "Moduli {} and {} are not coprime", a, b),
+            FieldElementError::Overflow => write!(f, "Result does not fit an i64"),
+            FieldElementError::NotPrime(n) => write!(f, "{} is not a prime", n),
+        }
+    }
+}
+
+impl fmt::Debug for FieldElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.prime > DEBUG_HEX_THRESHOLD {
+            write!(f, "FieldElement {{ num: {:#x}, prime: {:#x} }}", self.num, self.prime)
+        } else {
+            write!(f, "FieldElement {{ num: {}, prime: {} }}", self.num, self.prime)
+        }
+    }
+}
+
+impl FieldElement {
+    pub fn new(num: i64, prime: i64) -> Result<Self, FieldElementError> {
+        if num >= prime || num < 0 {
+            Err(FieldElementError::InvalidElement)
+        } else {
+            Ok(FieldElement { num, prime })
+        }
+    }
+
+    pub fn num(&self) -> i64 {
+        self.num
+    }
+
+    pub fn prime(&self) -> i64 {
+        self.prime
+    }
+
+    // Uniform over [0, prime): gen_range rejects out-of-range draws rather
+    // than reducing them, so there is no modulo bias
+    #[cfg(feature = "rand")]
+    pub fn random<R: rand::Rng>(prime: i64, rng: &mut R) -> FieldElement {
+        FieldElement { num: rng.gen_range(0..prime), prime }
+    }
+
+    // A negative exponent is an inverse: since a^(p-1) = 1 it can be
+    // brought into range modulo p - 1
+    pub fn pow(self, exponent: i64) -> FieldElement {
+        let mut exp = if exponent < 0 { exponent.rem_euclid(self.prime - 1) } else { exponent };
+        let mut base = self.num;
+        let mut result = 1;
+        while exp > 0 {
+            if exp % 2 == 1 {
+                result = mul_mod(result, base, self.prime);
+            }
+            base = mul_mod(base, base, self.prime);
+            exp /= 2;
+        }
+        FieldElement { num: result, prime: self.prime }
+    }
+
+    // Big-endian, zero padded on the left to len bytes. Fails if num
+    // needs more than len bytes.
+    pub fn to_bytes_be(&self, len: usize) -> Result<Vec<u8>, FieldElementError> {
+        let be = self.num.to_be_bytes();
+        let needed = byte_len(self.num);
+        if len < needed {
+            return Err(FieldElementError::InvalidLength(len));
+        }
+        let mut result = vec![0u8; len - needed];
+        result.extend_from_slice(&be[be.len() - needed..]);
+        Ok(result)
+    }
+
+    // Little-endian, zero padded on the right
+    pub fn to_bytes_le(&self, len: usize) -> Result<Vec<u8>, FieldElementError> {
+        let mut result = self.to_bytes_be(len)?;
+        result.reverse();
+        Ok(result)
+    }
+
+    // Takes at most as many bytes as the prime needs, and the value must
+    // still be below the prime
+    pub fn from_bytes_be(bytes: &[u8], prime: i64) -> Result<FieldElement, FieldElementError> {
+        if bytes.len() > byte_len(prime) {
+            return Err(FieldElementError::InvalidLength(bytes.len()));
+        }
+        let num = bytes.iter().fold(0i64, |acc, &byte| (acc << 8) | byte as i64);
+        FieldElement::new(num, prime)
+    }
+
+    pub fn from_bytes_le(bytes: &[u8], prime: i64) -> Result<FieldElement, FieldElementError> {
+        let be: Vec<u8> = bytes.iter().rev().copied().collect();
+        FieldElement::from_bytes_be(&be, prime)
+    }
+
+    // The 32-byte big-endian width of secp256k1 coordinates and scalars.
+    // Every i64 fits, so unlike to_bytes_be this cannot fail.
+    pub fn to_32_bytes(&self) -> [u8; 32] {
+        let mut result = [0u8; 32];
+        result[24..].copy_from_slice(&self.num.to_be_bytes());
+        result
+    }
+
+    // Reduces the 256-bit value into the field rather than rejecting it
+    pub fn from_32_bytes(bytes: &[u8; 32], prime: i64) -> Result<FieldElement, FieldElementError> {
+        if prime <= 0 {
+            return Err(FieldElementError::InvalidElement);
+        }
+        let num = bytes.iter().fold(0i64, |acc, &byte| ((acc as i128 * 256 + byte as i128) % prime as i128) as i64);
+        FieldElement::new(num, prime)
+    }
+
+    // a when choice is false, b when it is true, with the choice turned
+    // into an all-zeros or all-ones mask rather than branched on. Panics
+    // if the fields differ.
+    pub fn conditional_select(a: &FieldElement, b: &FieldElement, choice: bool) -> FieldElement {
+        assert_eq!(a.prime, b.prime, "elements from different fields");
+        let mask = (choice as i64).wrapping_neg();
+        FieldElement { num: a.num ^ (mask & (a.num ^ b.num)), prime: a.prime }
+    }
+
+    // Euler's criterion: a nonzero a is a square iff a^((p-1)/2) = 1
+    pub fn is_quadratic_residue(&self) -> bool {
+        self.pow((self.prime - 1) / 2).num == 1
+    }
+
+    // Smallest k > 0 with a^k = 1. By Lagrange it divides p - 1, so only
+    // the divisors need trying. Zero has no order.
+    pub fn multiplicative_order(&self) -> Option<i64> {
+        if self.num == 0 {
+            return None;
+        }
+        let group_order = self.prime - 1;
+        let mut divisors = Vec::new();
+        let mut d = 1;
+        while d * d <= group_order {
+            if group_order % d == 0 {
+                divisors.push(d);
+                divisors.push(group_order / d);
+            }
+            d += 1;
+        }
+        divisors.sort_unstable();
+        divisors.into_iter().find(|&k| self.pow(k).num == 1)
+    }
+}
+
+// A prime checked once, for making elements of its field without
+// repeating it. Elements from one Field can always be combined.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Field {
+    prime: i64,
+}
+
+impl Field {
+    // Trial division, so only quick for the small primes of the exercises
+    pub fn new(prime: i64) -> Result<Field, FieldElementError> {
+        if prime < 2 {
+            return Err(FieldElementError::NotPrime(prime));
+        }
+        let mut d = 2;
+        while d <= prime / d {
+            if prime % d == 0 {
+                return Err(FieldElementError::NotPrime(prime));
+            }
+            d += 1;
+        }
+        Ok(Field { prime })
+    }
+
+    pub fn prime(&self) -> i64 {
+        self.prime
+    }
+
+    pub fn element(&self, num: i64) -> Result<FieldElement, FieldElementError> {
+        FieldElement::new(num, self.prime)
+    }
+}
+
+// Primitive roots of the field, ascending. g generates the multiplicative
+// group when g^((p-1)/q) != 1 for every prime factor q of p - 1.
+pub fn field_generators(prime: i64) -> Vec<i64> {
+    if prime < 2 {
+        return Vec::new();
+    }
+    let order = prime - 1;
+    let mut factors = Vec::new();
+    let mut rest = order;
+    let mut q = 2;
+    while q * q <= rest {
+        if rest % q == 0 {
+            factors.push(q);
+            while rest % q == 0 {
+                rest /= q;
+            }
+        }
+        q += 1;
+    }
+    if rest > 1 {
+        factors.push(rest);
+    }
+    (1..prime)
+        .filter(|&g| {
+            let g = FieldElement { num: g, prime };
+            factors.iter().all(|q| g.pow(order / q).num != 1)
+        })
+        .collect()
+}
+
+// Bytes needed to hold a non-negative value, zero needing none
+fn byte_len(num: i64) -> usize {
+    (64 - num.leading_zeros() as usize).div_ceil(8)
+}
+
+// Products of two i64 residues can overflow i64, so widen first
+fn mul_mod(a: i64, b: i64, prime: i64) -> i64 {
+    (a as i128 * b as i128 % prime as i128) as i64
+}
+
+impl fmt::Display for FieldElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FieldElement_{}({})", self.prime, self.num)
+    }
+}
+
+impl Add for FieldElement {
+    type Output = Result<FieldElement, FieldElementError>;
+
+    fn add(self, other: FieldElement) -> Result<FieldElement, FieldElementError> {
+        if self.prime != other.prime {
+            Err(FieldElementError::DifferentFields)
+        } else {
+            let num = ((self.num as i128 + other.num as i128) % self.prime as i128) as i64;
+            Ok(FieldElement { num, prime: self.prime })
+        }
+    }
+}
+
+impl Sub for FieldElement {
+    type Output = Result<FieldElement, FieldElementError>;
+
+    fn sub(self, other: FieldElement) -> Result<FieldElement, FieldElementError> {
+        if self.prime != other.prime {
+            Err(FieldElementError::DifferentFields)
+        } else {
+            // Ensuring positive result; widened because num + prime can overflow i64
+            let num = (self.num as i128 - other.num as i128).rem_euclid(self.prime as i128) as i64;
+            Ok(FieldElement { num, prime: self.prime })
+        }
+    }
+}
+
+impl Mul for FieldElement {
+    type Output = Result<FieldElement, FieldElementError>;
+
+    fn mul(self, other: FieldElement) -> Result<FieldElement, FieldElementError> {
+        if self.prime != other.prime {
+            Err(FieldElementError::DifferentFields)
+        } else {
+            let num = mul_mod(self.num, other.num, self.prime);
+            Ok(FieldElement { num, prime: self.prime })
+        }
+    }
+}
+
+impl Div for FieldElement {
+    type Output = Result<FieldElement, FieldElementError>;
+
+    fn div(self, other: FieldElement) -> Result<FieldElement, FieldElementError> {
+        if self.prime != other.prime {
+            Err(FieldElementError::DifferentFields)
+        } else {
+            // Use Fermat's Little Theorem to find the multiplicative inverse:
+            // a^(p-1) ≡ 1 (mod p) -> a^(p-2) ≡ a^(-1) (mod p)
+            let num = mul_mod(self.num, other.pow(self.prime - 2).num, self.prime);
+            Ok(FieldElement { num, prime: self.prime })
+        }
+    }
+}
+
+// The prime is part of the type, so elements of different fields cannot
+// be mixed and the operators need no Result
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FieldElementConst<const P: u64> {
+    num: u64,
+}
+
+impl<const P: u64> FieldElementConst<P> {
+    pub fn new(num: u64) -> Result<Self, FieldElementError> {
+        if num >= P {
+            Err(FieldElementError::InvalidElement)
+        } else {
+            Ok(FieldElementConst { num })
+        }
+    }
+
+    pub fn num(&self) -> u64 {
+        self.num
+    }
+
+    pub fn prime(&self) -> u64 {
+        P
+    }
+
+    pub fn pow(self, exponent: i64) -> FieldElementConst<P> {
+        let exp = if exponent < 0 { (exponent as i128).rem_euclid(P as i128 - 1) as u64 } else { exponent as u64 };
+        self.pow_u64(exp)
+    }
+
+    // Fermat: a^(p-2) = a^(-1)
+    pub fn inverse(self) -> FieldElementConst<P> {
+        self.pow_u64(P - 2)
+    }
+
+    fn pow_u64(self, mut exp: u64) -> FieldElementConst<P> {
+        let mut base = self.num;
+        let mut result = 1;
+        while exp > 0 {
+            if exp % 2 == 1 {
+                result = mul_mod_u64(result, base, P);
+            }
+            base = mul_mod_u64(base, base, P);
+            exp /= 2;
+        }
+        FieldElementConst { num: result }
+    }
+}
+
+fn mul_mod_u64(a: u64, b: u64, prime: u64) -> u64 {
+    (a as u128 * b as u128 % prime as u128) as u64
+}
+
+impl<const P: u64> fmt::Display for FieldElementConst<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FieldElement_{}({})", P, self.num)
+    }
+}
+
+impl<const P: u64> Add for FieldElementConst<P> {
+    type Output = FieldElementConst<P>;
+
+    fn add(self, other: FieldElementConst<P>) -> FieldElementConst<P> {
+        FieldElementConst { num: ((self.num as u128 + other.num as u128) % P as u128) as u64 }
+    }
+}
+
+impl<const P: u64> Sub for FieldElementConst<P> {
+    type Output = FieldElementConst<P>;
+
+    fn sub(self, other: FieldElementConst<P>) -> FieldElementConst<P> {
+        FieldElementConst { num: ((self.num as u128 + P as u128 - other.num as u128) % P as u128) as u64 }
+    }
+}
+
+impl<const P: u64> Mul for FieldElementConst<P> {
+    type Output = FieldElementConst<P>;
+
+    fn mul(self, other: FieldElementConst<P>) -> FieldElementConst<P> {
+        FieldElementConst { num: mul_mod_u64(self.num, other.num, P) }
+    }
+}
+
+impl<const P: u64> Div for FieldElementConst<P> {
+    type Output = FieldElementConst<P>;
+
+    fn div(self, other: FieldElementConst<P>) -> FieldElementConst<P> {
+        FieldElementConst { num: mul_mod_u64(self.num, other.inverse().num, P) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use proptest::prelude::*;
+
+    // A small prime and the largest below 2^63, where the products
+    // overflow i64 unless widened
+    const PRIMES: [i64; 2] = [223, 9223372036854775783];
+
+    fn elements() -> impl Strategy<Value = (FieldElement, FieldElement, FieldElement)> {
+        proptest::sample::select(&PRIMES[..]).prop_flat_map(|prime| {
+            let element = move || (0..prime).prop_map(move |num| FieldElement::new(num, prime).unwrap());
+            (element(), element(), element())
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn addition_axioms((a, b, c) in elements()) {
+            prop_assert_eq!((a + b).unwrap(), (b + a).unwrap());
+            prop_assert_eq!(((a + b).unwrap() + c).unwrap(), (a + (b + c).unwrap()).unwrap());
+            let zero = FieldElement::new(0, a.prime()).unwrap();
+            prop_assert_eq!((a + (zero - a).unwrap()).unwrap(), zero);
+            prop_assert_eq!((a - a).unwrap(), zero);
+            prop_assert_eq!(((a - b).unwrap() + b).unwrap(), a);
+        }
+
+        #[test]
+        fn multiplication_axioms((a, b, c) in elements()) {
+            prop_assert_eq!((a * b).unwrap(), (b * a).unwrap());
+            prop_assert_eq!(((a * b).unwrap() * c).unwrap(), (a * (b * c).unwrap()).unwrap());
+            prop_assert_eq!((a * (b + c).unwrap()).unwrap(), ((a * b).unwrap() + (a * c).unwrap()).unwrap());
+        }
+
+        #[test]
+        fn inverse_and_pow((a, b, _) in elements()) {
+            prop_assume!(a.num() != 0);
+            let one = FieldElement::new(1, a.prime()).unwrap();
+            prop_assert_eq!(a.pow(a.prime() - 1), one);
+            prop_assert_eq!((a * (one / a).unwrap()).unwrap(), one);
+            prop_assert_eq!((a * a.pow(-1)).unwrap(), one);
+            prop_assert_eq!(((b / a).unwrap() * a).unwrap(), b);
+            prop_assert_eq!(a.pow(3), ((a * a).unwrap() * a).unwrap());
+        }
+    }
+
+    #[test]
+    fn subtraction_near_large_prime() {
+        let p = PRIMES[1];
+        let a = FieldElement::new(p - 1, p).unwrap();
+        let b = FieldElement::new(0, p).unwrap();
+        assert_eq!((a - b).unwrap().num(), p - 1);
+        assert_eq!((b - a).unwrap().num(), 1);
+        assert_eq!(((a - b).unwrap() + b).unwrap(), a);
+    }
+
+    #[test]
+    fn small_field_values() {
+        let a = FieldElement::new(2, 19).unwrap();
+        let b = FieldElement::new(7, 19).unwrap();
+        assert_eq!((a / b).unwrap().num(), 3);
+        assert_eq!(a.pow(3).num(), 8);
+        assert_eq!(FieldElement::new(17, 31).unwrap().pow(-3).num(), 29);
+        assert_eq!(FieldElement::new(0, 19).unwrap().pow(18).num(), 0);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn random_covers_the_field() {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(64);
+        let mut seen = [0u32; 19];
+        for _ in 0..10000 {
+            let element = FieldElement::random(19, &mut rng);
+            assert_eq!(element.prime(), 19);
+            seen[element.num() as usize] += 1;
+        }
+        assert!(seen.iter().all(|&count| count > 300));
+    }
+
+    #[test]
+    fn const_prime_arithmetic() {
+        type F19 = FieldElementConst<19>;
+        let a = F19::new(2).unwrap();
+        let b = F19::new(7).unwrap();
+        assert_eq!((a + b).num(), 9);
+        assert_eq!((a - b).num(), 14);
+        assert_eq!((a * b).num(), 14);
+        assert_eq!((a / b).num(), 3);
+        assert_eq!(a.pow(3).num(), 8);
+        assert_eq!(a.pow(-3), F19::new(8).unwrap().inverse());
+        assert!(F19::new(19).is_err());
+        assert_eq!(a.to_string(), "FieldElement_19(2)");
+    }
+
+    #[test]
+    fn const_prime_near_u64_max() {
+        type Big = FieldElementConst<18446744073709551557>;
+        let minus_one = Big::new(18446744073709551556).unwrap();
+        assert_eq!((minus_one * minus_one).num(), 1);
+        assert_eq!((minus_one + minus_one).num(), 18446744073709551555);
+        assert_eq!((minus_one / minus_one).num(), 1);
+        assert_eq!((Big::new(1).unwrap() - minus_one).num(), 2);
+    }
+
+    #[test]
+    fn quadratic_residues_and_generators() {
+        let residues: Vec<i64> =
+            (1..19).filter(|&n| FieldElement::new(n, 19).unwrap().is_quadratic_residue()).collect();
+        assert_eq!(residues, vec![1, 4, 5, 6, 7, 9, 11, 16, 17]);
+        assert!(!FieldElement::new(0, 19).unwrap().is_quadratic_residue());
+        assert_eq!(field_generators(19), vec![2, 3, 10, 13, 14, 15]);
+        assert_eq!(field_generators(7), vec![3, 5]);
+        assert_eq!(field_generators(2), vec![1]);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let prime = PRIMES[1];
+        for num in [0, 1, 255, 256, 0x1234567890, prime - 1] {
+            let element = FieldElement::new(num, prime).unwrap();
+            let be = element.to_bytes_be(8).unwrap();
+            let mut le = element.to_bytes_le(8).unwrap();
+            assert_eq!(FieldElement::from_bytes_be(&be, prime).unwrap(), element);
+            assert_eq!(FieldElement::from_bytes_le(&le, prime).unwrap(), element);
+            le.reverse();
+            assert_eq!(le, be);
+        }
+    }
+
+    #[test]
+    fn bytes_padding_and_errors() {
+        let element = FieldElement::new(0x1234, 65521).unwrap();
+        assert_eq!(element.to_bytes_be(4).unwrap(), vec![0, 0, 0x12, 0x34]);
+        assert_eq!(element.to_bytes_le(4).unwrap(), vec![0x34, 0x12, 0, 0]);
+        assert!(matches!(element.to_bytes_be(1), Err(FieldElementError::InvalidLength(1))));
+        assert!(matches!(FieldElement::from_bytes_be(&[0, 0, 1], 65521), Err(FieldElementError::InvalidLength(3))));
+        assert!(matches!(FieldElement::from_bytes_be(&[0xff, 0xff], 65521), Err(FieldElementError::InvalidElement)));
+        assert!(matches!(FieldElement::from_bytes_be(&[0xff; 8], PRIMES[1]), Err(FieldElementError::InvalidElement)));
+        assert_eq!(FieldElement::new(0, 7).unwrap().to_bytes_be(0).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn multiplicative_order() {
+        let element = |num| FieldElement::new(num, 19).unwrap();
+        assert_eq!(element(2).multiplicative_order(), Some(18));
+        assert_eq!(element(1).multiplicative_order(), Some(1));
+        assert_eq!(element(18).multiplicative_order(), Some(2));
+        assert_eq!(element(0).multiplicative_order(), None);
+        for num in 1..19 {
+            let order = element(num).multiplicative_order().unwrap();
+            assert_eq!(18 % order, 0);
+            assert_eq!(element(num).pow(order).num(), 1);
+            assert_eq!(order == 18, field_generators(19).contains(&num));
+        }
+    }
+
+    #[test]
+    fn debug_output() {
+        assert_eq!(alloc::format!("{:?}", FieldElement::new(7, 19).unwrap()), "FieldElement { num: 7, prime: 19 }");
+        // Primes above 2^32 print in hex
+        let large = FieldElement::new(255, 4294967311).unwrap();
+        assert_eq!(alloc::format!("{:?}", large), "FieldElement { num: 0xff, prime: 0x10000000f }");
+    }
+
+    #[test]
+    fn thirty_two_byte_round_trip() {
+        let prime = (1 << 61) - 1;
+        let element = FieldElement::new(prime - 1, prime).unwrap();
+        let bytes = element.to_32_bytes();
+        assert_eq!(bytes[..24], [0; 24]);
+        assert_eq!(FieldElement::from_32_bytes(&bytes, prime).unwrap(), element);
+        // 2^256 is 2^12 modulo the Mersenne prime 2^61 - 1
+        assert_eq!(FieldElement::from_32_bytes(&[0xff; 32], prime).unwrap().num(), 4095);
+        assert!(FieldElement::from_32_bytes(&[0xff; 32], 0).is_err());
+    }
+
+    #[test]
+    fn field_builder() {
+        let field = Field::new(19).unwrap();
+        assert_eq!(field.prime(), 19);
+        let (a, b) = (field.element(2).unwrap(), field.element(7).unwrap());
+        assert_eq!((a + b).unwrap(), field.element(9).unwrap());
+        assert_eq!((a - b).unwrap(), field.element(14).unwrap());
+        assert_eq!((a / b).unwrap(), field.element(3).unwrap());
+        assert!(field.element(19).is_err());
+        assert!(field.element(-1).is_err());
+        for not_prime in [0, 1, 4, 9, 221, -7] {
+            assert!(matches!(Field::new(not_prime), Err(FieldElementError::NotPrime(_))));
+        }
+        for prime in [2, 3, 223, 1_000_003] {
+            assert!(Field::new(prime).is_ok());
+        }
+    }
+
+    #[test]
+    fn conditional_select() {
+        let prime = (1 << 61) - 1;
+        let a = FieldElement::new(prime - 1, prime).unwrap();
+        let b = FieldElement::new(12345, prime).unwrap();
+        assert_eq!(FieldElement::conditional_select(&a, &b, false), a);
+        assert_eq!(FieldElement::conditional_select(&a, &b, true), b);
+    }
+
+    #[test]
+    #[should_panic(expected = "elements from different fields")]
+    fn conditional_select_across_fields() {
+        let a = FieldElement::new(1, 7).unwrap();
+        FieldElement::conditional_select(&a, &FieldElement::new(1, 11).unwrap(), true);
+    }
+}
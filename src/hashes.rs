@@ -0,0 +1,114 @@
+use hmac::{Hmac, Mac};
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256, Sha512};
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+// Double SHA-256, used for block and transaction hashes
+pub fn hash256(data: &[u8]) -> [u8; 32] {
+    sha256(&sha256(data))
+}
+
+// RIPEMD-160 of SHA-256, used for addresses and p2sh
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(sha256(data)).into()
+}
+
+// BIP340: SHA256(SHA256(tag) || SHA256(tag) || msg), so hashes for one
+// purpose can never collide with hashes for another
+pub fn tagged_hash(tag: &str, msg: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256(tag.as_bytes());
+    Sha256::new().chain_update(tag_hash).chain_update(tag_hash).chain_update(msg).finalize().into()
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+// Used by BIP32 key derivation
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+// PBKDF2 with HMAC-SHA512 as the PRF, for a single 64-byte block
+pub fn pbkdf2_hmac_sha512(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 64] {
+    let mac = Hmac::<Sha512>::new_from_slice(password).expect("HMAC accepts keys of any length");
+    let mut block = mac.clone();
+    block.update(salt);
+    block.update(&1u32.to_be_bytes());
+    let mut u: [u8; 64] = block.finalize().into_bytes().into();
+    let mut result = u;
+    for _ in 1..iterations {
+        let mut block = mac.clone();
+        block.update(&u);
+        u = block.finalize().into_bytes().into();
+        for (r, byte) in result.iter_mut().zip(u.iter()) {
+            *r ^= byte;
+        }
+    }
+    result
+}
+
+// 32-bit MurmurHash3 (x86 variant), as used by BIP37 filters
+pub fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+    let mut h = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h ^= k;
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        for (i, byte) in tail.iter().enumerate() {
+            k |= (*byte as u32) << (8 * i);
+        }
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85ebca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2ae35);
+    h ^= h >> 16;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{decode_hex, encode_hex};
+
+    #[test]
+    fn murmur3_vectors() {
+        assert_eq!(murmur3_32(b"", 0), 0);
+        assert_eq!(murmur3_32(b"", 1), 0x514e28b7);
+        assert_eq!(murmur3_32(b"", 0xffffffff), 0x81f16f39);
+        assert_eq!(murmur3_32(b"hello", 0), 0x248bfa47);
+        assert_eq!(murmur3_32(b"Hello, world!", 1234), 0xfaf6cdb3);
+        assert_eq!(murmur3_32(b"The quick brown fox jumps over the lazy dog", 0x9747b28c), 0x2fa826cd);
+    }
+
+    #[test]
+    fn tagged_hashes() {
+        // The BIP340 challenge for test vector 0: R, the key, then the message
+        let mut msg = decode_hex("e907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca8215f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9").unwrap();
+        msg.extend_from_slice(&[0; 32]);
+        assert_eq!(
+            encode_hex(&tagged_hash("BIP0340/challenge", &msg)),
+            "6bb6b93a91f2ecc0cd924f4f9baabb5e6eb21745bb00f2cebdaac908bb5d86ce"
+        );
+        let tag = sha256(b"TapLeaf");
+        assert_eq!(tagged_hash("TapLeaf", b"abc"), sha256(&[&tag[..], &tag[..], b"abc"].concat()));
+    }
+}
@@ -0,0 +1,47 @@
+// Without the std feature only the small-field arithmetic is built, for
+// targets with an allocator but no standard library
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod bech32;
+#[cfg(feature = "std")]
+pub mod bip32;
+#[cfg(feature = "std")]
+pub mod bip39;
+#[cfg(feature = "std")]
+pub mod block;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod bloomfilter;
+#[cfg(feature = "std")]
+pub mod coin_selection;
+#[cfg(feature = "std")]
+pub mod ecc;
+#[cfg(feature = "std")]
+pub mod error;
+pub mod field;
+#[cfg(feature = "std")]
+pub mod hashes;
+#[cfg(feature = "std")]
+pub mod merkle;
+#[cfg(feature = "std")]
+pub mod network;
+#[cfg(feature = "std")]
+pub mod op;
+pub mod point;
+pub mod polynomial;
+#[cfg(feature = "std")]
+pub mod psbt;
+#[cfg(feature = "std")]
+pub mod schnorr;
+#[cfg(feature = "std")]
+pub mod script;
+#[cfg(feature = "std")]
+pub mod tx;
+#[cfg(feature = "std")]
+pub mod u256;
+#[cfg(feature = "std")]
+pub mod util;
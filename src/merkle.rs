@@ -0,0 +1,434 @@
+use std::fmt;
+use std::io::Read;
+
+use crate::block::BlockHeader;
+use crate::error::ParseError;
+use crate::hashes::hash256;
+use crate::util::{bytes_to_bit_field, read_bytes, read_u32, read_varint};
+
+// All hashes here are in wire (little-endian) order, as they are hashed
+
+// The most transactions a block can hold: its 4M weight limit over the
+// 240-weight smallest transaction
+const MAX_BLOCK_TRANSACTIONS: u32 = 4_000_000 / 240;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MerkleError {
+    Empty,
+    MissingFlagBits,
+    MissingHashes,
+    UnusedHashes(usize),
+    UnusedFlagBits,
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::Empty => write!(f, "No hashes to build a merkle tree from"),
+            MerkleError::MissingFlagBits => write!(f, "Ran out of flag bits populating the merkle tree"),
+            MerkleError::MissingHashes => write!(f, "Ran out of hashes populating the merkle tree"),
+            MerkleError::UnusedHashes(count) => write!(f, "{} hashes left over after populating the merkle tree", count),
+            MerkleError::UnusedFlagBits => write!(f, "Set flag bits left over after populating the merkle tree"),
+        }
+    }
+}
+
+pub fn merkle_parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut data = left.to_vec();
+    data.extend_from_slice(right);
+    hash256(&data)
+}
+
+// Half as many hashes, rounded up: an odd level pairs its last hash with
+// itself
+pub fn merkle_parent_level(hashes: &[[u8; 32]]) -> Result<Vec<[u8; 32]>, MerkleError> {
+    if hashes.is_empty() {
+        return Err(MerkleError::Empty);
+    }
+    Ok(hashes.chunks(2).map(|pair| merkle_parent(&pair[0], pair.get(1).unwrap_or(&pair[0]))).collect())
+}
+
+// A single hash is its own root
+pub fn merkle_root(hashes: &[[u8; 32]]) -> Result<[u8; 32], MerkleError> {
+    if hashes.is_empty() {
+        return Err(MerkleError::Empty);
+    }
+    let mut level = hashes.to_vec();
+    while level.len() > 1 {
+        level = merkle_parent_level(&level)?;
+    }
+    Ok(level[0])
+}
+
+// A tree over total leaves with nodes filled in as they become known, root
+// at depth 0. The cursor walks it depth first while populating.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MerkleTree {
+    pub total: usize,
+    pub max_depth: usize,
+    pub nodes: Vec<Vec<Option<[u8; 32]>>>,
+    pub current_depth: usize,
+    pub current_index: usize,
+}
+
+impl MerkleTree {
+    pub fn new(total: usize) -> MerkleTree {
+        // ceil(log2(total)) levels below the root
+        let max_depth = total.next_power_of_two().trailing_zeros() as usize;
+        let nodes = (0..=max_depth).map(|depth| vec![None; total.div_ceil(1 << (max_depth - depth))]).collect();
+        MerkleTree { total, max_depth, nodes, current_depth: 0, current_index: 0 }
+    }
+
+    // Every node filled in from all the leaves, level by level, pairing
+    // odd last hashes with themselves as merkle_root does
+    pub fn from_leaves(leaves: &[[u8; 32]]) -> MerkleTree {
+        let mut tree = MerkleTree::new(leaves.len());
+        let mut level = leaves.to_vec();
+        for depth in (0..=tree.max_depth).rev() {
+            tree.nodes[depth] = level.iter().copied().map(Some).collect();
+            if depth > 0 {
+                level = merkle_parent_level(&level).expect("the tree has leaves below the root");
+            }
+        }
+        tree
+    }
+
+    // The siblings from a leaf up to the root. None if the leaf is out of
+    // range or the tree is not fully populated along the way.
+    pub fn proof_for(&self, leaf_index: usize) -> Option<MerkleProof> {
+        let mut index = leaf_index;
+        self.nodes[self.max_depth].get(index)?;
+        let mut steps = Vec::with_capacity(self.max_depth);
+        for depth in (1..=self.max_depth).rev() {
+            let level = &self.nodes[depth];
+            let step = if index % 2 == 1 {
+                (Side::Left, level[index - 1]?)
+            } else {
+                // The last node of an odd level pairs with itself
+                (Side::Right, level.get(index + 1).copied().unwrap_or(level[index])?)
+            };
+            steps.push(step);
+            index /= 2;
+        }
+        Some(MerkleProof { steps })
+    }
+
+    // Stays put at the root
+    pub fn up(&mut self) {
+        self.current_depth = self.current_depth.saturating_sub(1);
+        self.current_index /= 2;
+    }
+
+    pub fn left(&mut self) {
+        self.current_depth += 1;
+        self.current_index *= 2;
+    }
+
+    pub fn right(&mut self) {
+        self.current_depth += 1;
+        self.current_index = self.current_index * 2 + 1;
+    }
+
+    pub fn root(&self) -> Option<[u8; 32]> {
+        self.nodes[0].first().copied().flatten()
+    }
+
+    pub fn set_current_node(&mut self, hash: [u8; 32]) {
+        self.nodes[self.current_depth][self.current_index] = Some(hash);
+    }
+
+    pub fn current_node(&self) -> Option<[u8; 32]> {
+        self.nodes[self.current_depth][self.current_index]
+    }
+
+    pub fn left_node(&self) -> Option<[u8; 32]> {
+        self.nodes[self.current_depth + 1][self.current_index * 2]
+    }
+
+    pub fn right_node(&self) -> Option<[u8; 32]> {
+        self.nodes[self.current_depth + 1][self.current_index * 2 + 1]
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.current_depth == self.max_depth
+    }
+
+    pub fn right_exists(&self) -> bool {
+        self.nodes[self.current_depth + 1].len() > self.current_index * 2 + 1
+    }
+
+    // Both are consumed from the front. A set flag on an inner node means
+    // descend into it, a clear one means its hash comes next; a leaf always
+    // takes the next hash. Clear flag bits left at the end are byte padding.
+    pub fn populate_tree(&mut self, flag_bits: &mut Vec<u8>, hashes: &mut Vec<[u8; 32]>) -> Result<(), MerkleError> {
+        if self.total == 0 {
+            return Err(MerkleError::Empty);
+        }
+        let mut flag_bits = flag_bits.drain(..);
+        let mut hashes = hashes.drain(..);
+        while self.root().is_none() {
+            if self.is_leaf() {
+                flag_bits.next().ok_or(MerkleError::MissingFlagBits)?;
+                self.set_current_node(hashes.next().ok_or(MerkleError::MissingHashes)?);
+                self.up();
+                continue;
+            }
+            match self.left_node() {
+                None => {
+                    if flag_bits.next().ok_or(MerkleError::MissingFlagBits)? == 0 {
+                        self.set_current_node(hashes.next().ok_or(MerkleError::MissingHashes)?);
+                        self.up();
+                    } else {
+                        self.left();
+                    }
+                }
+                Some(left) if self.right_exists() => match self.right_node() {
+                    None => self.right(),
+                    Some(right) => {
+                        self.set_current_node(merkle_parent(&left, &right));
+                        self.up();
+                    }
+                },
+                Some(left) => {
+                    self.set_current_node(merkle_parent(&left, &left));
+                    self.up();
+                }
+            }
+        }
+        if hashes.len() > 0 {
+            return Err(MerkleError::UnusedHashes(hashes.len()));
+        }
+        if flag_bits.any(|bit| bit != 0) {
+            return Err(MerkleError::UnusedFlagBits);
+        }
+        Ok(())
+    }
+}
+
+// A header with just enough of its merkle tree to prove which of its
+// transactions matched a filter. Hashes are in display order, like txids.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MerkleBlock {
+    pub header: BlockHeader,
+    pub total: u32,
+    pub hashes: Vec<[u8; 32]>,
+    pub flags: Vec<u8>,
+}
+
+impl MerkleBlock {
+    pub fn parse<R: Read>(reader: &mut R) -> Result<MerkleBlock, ParseError> {
+        let header = BlockHeader::parse(reader)?;
+        let total = read_u32(reader)?;
+        let count = read_varint(reader)?;
+        let mut hashes = Vec::with_capacity(count.min(4096) as usize);
+        for _ in 0..count {
+            let mut hash = [0u8; 32];
+            reader.read_exact(&mut hash)?;
+            hash.reverse();
+            hashes.push(hash);
+        }
+        let flags_length = read_varint(reader)?;
+        let flags = read_bytes(reader, flags_length)?;
+        Ok(MerkleBlock { header, total, hashes, flags })
+    }
+
+    // Rebuilds the root from the partial tree, in wire order as it is
+    // hashed, and checks it against the header. total comes off the wire,
+    // so it is bounded as Core does before a tree that size is allocated.
+    pub fn is_valid(&self) -> bool {
+        let mut flag_bits = bytes_to_bit_field(&self.flags);
+        // Each hash covers at least one transaction and takes a flag bit
+        if self.total == 0
+            || self.total > MAX_BLOCK_TRANSACTIONS
+            || self.hashes.len() > self.total as usize
+            || flag_bits.len() < self.hashes.len()
+        {
+            return false;
+        }
+        let mut hashes: Vec<[u8; 32]> = self
+            .hashes
+            .iter()
+            .map(|hash| {
+                let mut hash = *hash;
+                hash.reverse();
+                hash
+            })
+            .collect();
+        let mut tree = MerkleTree::new(self.total as usize);
+        if tree.populate_tree(&mut flag_bits, &mut hashes).is_err() {
+            return false;
+        }
+        let Some(mut root) = tree.root() else {
+            return false;
+        };
+        root.reverse();
+        root == self.header.merkle_root
+    }
+}
+
+// Which side of the running hash a proof's sibling goes on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+// The path from one leaf to the root, leaf end first
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MerkleProof {
+    pub steps: Vec<(Side, [u8; 32])>,
+}
+
+impl MerkleProof {
+    pub fn verify(&self, leaf: &[u8; 32], root: &[u8; 32]) -> bool {
+        let computed = self.steps.iter().fold(*leaf, |hash, (side, sibling)| match side {
+            Side::Left => merkle_parent(sibling, &hash),
+            Side::Right => merkle_parent(&hash, sibling),
+        });
+        computed == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::decode_hex;
+
+    // The leaves of the chapter 11 example tree
+    const HASHES: [&str; 12] = [
+        "c117ea8ec828342f4dfb0ad6bd140e03a50720ece40169ee38bdc15d9eb64cf5",
+        "c131474164b412e3406696da1ee20ab0fc9bf41c8f05fa8ceea7a08d672d7cc5",
+        "f391da6ecfeed1814efae39e7fcb3838ae0b02c02ae7d0a5848a66947c0727b0",
+        "3d238a92a94532b946c90e19c49351c763696cff3db400485b813aecb8a13181",
+        "10092f2633be5f3ce349bf9ddbde36caa3dd10dfa0ec8106bce23acbff637dae",
+        "7d37b3d54fa6a64869084bfd2e831309118b9e833610e6228adacdbd1b4ba161",
+        "8118a77e542892fe15ae3fc771a4abfd2f5d5d5997544c3487ac36b5c85170fc",
+        "dff6879848c2c9b62fe652720b8df5272093acfaa45a43cdb3696fe2466a3877",
+        "b825c0745f46ac58f7d3759e6dc535a1fec7820377f24d4c2c6ad2cc55c0cb59",
+        "95513952a04bd8992721e9b7e2937f1c04ba31e0469fbe615a78197f68f52b7c",
+        "2e6d722e5e4dbdf2447ddecc9f7dabb8e299bae921c99ad5b0184cd9eb8e5908",
+        "b13a750047bc0bdceb2473e5fe488c2596d7a7124b4e716fdd29b046ef99bbf0",
+    ];
+
+    fn hash(hex: &str) -> [u8; 32] {
+        decode_hex(hex).unwrap().try_into().unwrap()
+    }
+
+    fn leaves() -> Vec<[u8; 32]> {
+        HASHES.iter().map(|hex| hash(hex)).collect()
+    }
+
+    #[test]
+    fn parent_level_and_root() {
+        let leaves = leaves();
+        let parent = hash("8b30c5ba100f6f2e5ad1e2a742e5020491240f8eb514fe97c713c31718ad7ecd");
+        assert_eq!(merkle_parent(&leaves[0], &leaves[1]), parent);
+        let root = hash("acbcab8bcc1af95d8d563b77d24c3d19b18f1486383d75a5085c4e86c86beed6");
+        assert_eq!(merkle_root(&leaves).unwrap(), root);
+        let three: Vec<[u8; 32]> = (0..3).map(|i| [i; 32]).collect();
+        assert_eq!(merkle_parent_level(&three).unwrap().len(), 2);
+        let root = hash("d6384640762f797ede7e7f13839222f9452272809932cc6089f701331df4552d");
+        assert_eq!(merkle_root(&three).unwrap(), root);
+        assert_eq!(merkle_root(&leaves[..1]).unwrap(), leaves[0]);
+        assert_eq!(merkle_parent_level(&[]), Err(MerkleError::Empty));
+        assert_eq!(merkle_root(&[]), Err(MerkleError::Empty));
+    }
+
+    #[test]
+    fn populate_tree() {
+        // Every node of the chapter 11 tree flagged, giving its published root
+        let mut tree = MerkleTree::new(12);
+        let mut flag_bits = vec![1; 1 + 2 + 3 + 6 + 12];
+        let mut hashes = leaves();
+        tree.populate_tree(&mut flag_bits, &mut hashes).unwrap();
+        assert!(flag_bits.is_empty());
+        assert!(hashes.is_empty());
+        assert_eq!(tree.root(), Some(hash("acbcab8bcc1af95d8d563b77d24c3d19b18f1486383d75a5085c4e86c86beed6")));
+        let leaves: Vec<[u8; 32]> = (0..27).map(|i| hash256(&[i])).collect();
+        let empty = MerkleTree::new(27);
+        assert_eq!(empty.max_depth, 5);
+        assert_eq!(empty.nodes.iter().map(Vec::len).collect::<Vec<_>>(), vec![1, 2, 4, 7, 14, 27]);
+        let node_count: usize = empty.nodes.iter().map(Vec::len).sum();
+        // Every node flagged, plus byte padding
+        let mut tree = empty.clone();
+        let mut flag_bits = vec![1; node_count];
+        flag_bits.extend([0, 0, 0]);
+        tree.populate_tree(&mut flag_bits, &mut leaves.clone()).unwrap();
+        assert!(flag_bits.is_empty());
+        assert_eq!(tree.root(), Some(merkle_root(&leaves).unwrap()));
+        let mut extra_hash = leaves.clone();
+        extra_hash.push([0; 32]);
+        let result = empty.clone().populate_tree(&mut vec![1; node_count], &mut extra_hash);
+        assert_eq!(result, Err(MerkleError::UnusedHashes(1)));
+        let result = empty.clone().populate_tree(&mut vec![1; node_count + 1], &mut leaves.clone());
+        assert_eq!(result, Err(MerkleError::UnusedFlagBits));
+        let result = empty.clone().populate_tree(&mut vec![1; node_count], &mut leaves[..26].to_vec());
+        assert_eq!(result, Err(MerkleError::MissingHashes));
+        let result = empty.clone().populate_tree(&mut vec![1; 3], &mut leaves.clone());
+        assert_eq!(result, Err(MerkleError::MissingFlagBits));
+        // A clear flag on the root takes it as the next hash
+        let mut tree = empty.clone();
+        tree.populate_tree(&mut vec![0], &mut vec![leaves[0]]).unwrap();
+        assert_eq!(tree.root(), Some(leaves[0]));
+        let mut one = MerkleTree::new(1);
+        one.populate_tree(&mut vec![1], &mut vec![leaves[0]]).unwrap();
+        assert_eq!(one.root(), Some(leaves[0]));
+        assert_eq!(MerkleTree::new(0).populate_tree(&mut vec![], &mut vec![]), Err(MerkleError::Empty));
+    }
+
+    // The merkleblock message from chapter 11
+    const MERKLE_BLOCK_HEX: &str = "00000020df3b053dc46f162a9b00c7f0d5124e2676d47bbe7c5d0793a500000000000000ef445fef2ed495c275892206ca533e7411907971013ab83e3b47bd0d692d14d4dc7c835b67d8001ac157e670bf0d00000aba412a0d1480e370173072c9562becffe87aa661c1e4a6dbc305d38ec5dc088a7cf92e6458aca7b32edae818f9c2c98c37e06bf72ae0ce80649a38655ee1e27d34d9421d940b16732f24b94023e9d572a7f9ab8023434a4feb532d2adfc8c2c2158785d1bd04eb99df2e86c54bc13e139862897217400def5d72c280222c4cbaee7261831e1550dbb8fa82853e9fe506fc5fda3f7b919d8fe74b6282f92763cef8e625f977af7c8619c32a369b832bc2d051ecd9c73c51e76370ceabd4f25097c256597fa898d404ed53425de608ac6bfe426f6e2bb457f1c554866eb69dcb8d6bf6f880e9a59b3cd053e6c7060eeacaacf4dac6697dac20e4bd3f38a2ea2543d1ab7953e3430790a9f81e1c67f5b58c825acf46bd02848384eebe9af917274cdfbb1a28a5d58a23a17977def0de10d644258d9c54f886d47d293a411cb6226103b55635";
+
+    fn merkle_block() -> MerkleBlock {
+        MerkleBlock::parse(&mut &decode_hex(MERKLE_BLOCK_HEX).unwrap()[..]).unwrap()
+    }
+
+    #[test]
+    fn merkle_block_validity() {
+        let block = merkle_block();
+        assert_eq!(block.header.id(), "00000000000000cac712b726e4326e596170574c01a16001692510c44025eb30");
+        assert_eq!(block.total, 3519);
+        assert_eq!(block.hashes.len(), 10);
+        assert_eq!(block.flags, vec![0xb5, 0x56, 0x35]);
+        let mut first = hash("ba412a0d1480e370173072c9562becffe87aa661c1e4a6dbc305d38ec5dc088a");
+        first.reverse();
+        assert_eq!(block.hashes[0], first);
+        assert!(block.is_valid());
+        for index in [0, 9] {
+            let mut corrupt = block.clone();
+            corrupt.hashes[index][5] ^= 1;
+            assert!(!corrupt.is_valid());
+        }
+        let wrong_flags = MerkleBlock { flags: vec![0xb5, 0x56, 0x34], ..block.clone() };
+        assert!(!wrong_flags.is_valid());
+    }
+
+    #[test]
+    fn merkle_block_bounds_total() {
+        let block = merkle_block();
+        for total in [0, 1, MAX_BLOCK_TRANSACTIONS + 1, u32::MAX] {
+            assert!(!MerkleBlock { total, ..block.clone() }.is_valid());
+        }
+        let no_flags = MerkleBlock { flags: vec![], ..block.clone() };
+        assert!(!no_flags.is_valid());
+    }
+
+    #[test]
+    fn proofs_from_leaves() {
+        for count in 1..=13 {
+            let leaves: Vec<[u8; 32]> = (0..count).map(|i| hash256(&[i])).collect();
+            let root = merkle_root(&leaves).unwrap();
+            let tree = MerkleTree::from_leaves(&leaves);
+            assert_eq!(tree.root(), Some(root));
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof_for(index).unwrap();
+                assert_eq!(proof.steps.len(), tree.max_depth);
+                assert!(proof.verify(leaf, &root));
+                assert!(!proof.verify(leaf, &[7; 32]));
+            }
+            assert!(tree.proof_for(count as usize).is_none());
+        }
+        // Unpopulated nodes give no proof
+        assert!(MerkleTree::new(2).proof_for(0).is_none());
+    }
+}
@@ -0,0 +1,830 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::block::{BlockHeader, ChainError, HeaderChain};
+use crate::error::ParseError;
+use crate::hashes::hash256;
+use crate::util::{encode_hex, encode_varint, read_bytes, read_u32, read_u64, read_varint};
+
+pub const PROTOCOL_VERSION: u32 = 70015;
+pub const USER_AGENT: &[u8] = b"/programmingbitcoin:0.1/";
+
+pub const NETWORK_MAGIC: [u8; 4] = [0xf9, 0xbe, 0xb4, 0xd9];
+pub const TESTNET_NETWORK_MAGIC: [u8; 4] = [0x0b, 0x11, 0x09, 0x07];
+
+// Wire frame: magic, zero-padded command, LE payload length, the first
+// four bytes of hash256(payload), then the payload itself
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct NetworkEnvelope {
+    pub magic: [u8; 4],
+    pub command: [u8; 12],
+    pub payload: Vec<u8>,
+}
+
+impl NetworkEnvelope {
+    // Commands longer than 12 bytes are truncated
+    pub fn new(command: &[u8], payload: Vec<u8>, testnet: bool) -> NetworkEnvelope {
+        let mut padded = [0u8; 12];
+        let len = command.len().min(12);
+        padded[..len].copy_from_slice(&command[..len]);
+        let magic = if testnet { TESTNET_NETWORK_MAGIC } else { NETWORK_MAGIC };
+        NetworkEnvelope { magic, command: padded, payload }
+    }
+
+    // The command without its zero padding
+    pub fn command_name(&self) -> &[u8] {
+        let len = self.command.iter().position(|&b| b == 0).unwrap_or(12);
+        &self.command[..len]
+    }
+
+    pub fn parse<R: Read>(reader: &mut R, testnet: bool) -> Result<NetworkEnvelope, ParseError> {
+        let mut magic = [0u8; 4];
+        // Nothing at all where a frame should start is the peer hanging up,
+        // a frame cut short is not
+        if reader.read(&mut magic[..1])? == 0 {
+            return Err(ParseError::ConnectionClosed);
+        }
+        reader.read_exact(&mut magic[1..])?;
+        let expected = if testnet { TESTNET_NETWORK_MAGIC } else { NETWORK_MAGIC };
+        if magic != expected {
+            return Err(ParseError::WrongMagic { expected, actual: magic });
+        }
+        let mut command = [0u8; 12];
+        reader.read_exact(&mut command)?;
+        let payload_length = read_u32(reader)?;
+        let mut checksum = [0u8; 4];
+        reader.read_exact(&mut checksum)?;
+        let payload = read_bytes(reader, payload_length as u64)?;
+        if hash256(&payload)[..4] != checksum {
+            return Err(ParseError::BadChecksum);
+        }
+        Ok(NetworkEnvelope { magic, command, payload })
+    }
+
+    pub fn from_message<M: Message>(message: &M, testnet: bool) -> NetworkEnvelope {
+        NetworkEnvelope::new(message.command(), message.serialize(), testnet)
+    }
+
+    // The payload as the message type its command names
+    pub fn message<M: Message>(&self) -> Result<M, ParseError> {
+        if self.command_name() != M::COMMAND {
+            return Err(ParseError::InvalidFormat("envelope carries a different command"));
+        }
+        M::parse(&mut &self.payload[..])
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.magic.to_vec();
+        result.extend_from_slice(&self.command);
+        result.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        result.extend_from_slice(&hash256(&self.payload)[..4]);
+        result.extend_from_slice(&self.payload);
+        result
+    }
+}
+
+impl fmt::Display for NetworkEnvelope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", String::from_utf8_lossy(self.command_name()), encode_hex(&self.payload))
+    }
+}
+
+// A payload an envelope can carry, identified by its command
+pub trait Message: Sized {
+    const COMMAND: &'static [u8];
+
+    fn parse<R: Read>(reader: &mut R) -> Result<Self, ParseError>;
+
+    fn serialize(&self) -> Vec<u8>;
+
+    fn command(&self) -> &'static [u8] {
+        Self::COMMAND
+    }
+}
+
+// A payload built elsewhere, sent under whatever command it is given. Its
+// command is not on the type, so parsing one gives an empty command.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GenericMessage {
+    pub command: &'static [u8],
+    pub payload: Vec<u8>,
+}
+
+impl GenericMessage {
+    pub fn new(command: &'static [u8], payload: Vec<u8>) -> GenericMessage {
+        GenericMessage { command, payload }
+    }
+}
+
+impl Message for GenericMessage {
+    const COMMAND: &'static [u8] = b"";
+
+    fn parse<R: Read>(reader: &mut R) -> Result<GenericMessage, ParseError> {
+        let mut payload = Vec::new();
+        reader.read_to_end(&mut payload)?;
+        Ok(GenericMessage { command: Self::COMMAND, payload })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.payload.clone()
+    }
+
+    fn command(&self) -> &'static [u8] {
+        self.command
+    }
+}
+
+// The first message of the handshake. Addresses go on the wire as 16-byte
+// IPv6 (IPv4 mapped into it) with a big-endian port.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VersionMessage {
+    pub version: u32,
+    pub services: u64,
+    pub timestamp: u64,
+    pub receiver_services: u64,
+    pub receiver: SocketAddr,
+    pub sender_services: u64,
+    pub sender: SocketAddr,
+    pub nonce: [u8; 8],
+    pub user_agent: Vec<u8>,
+    pub latest_block: u32,
+    pub relay: bool,
+}
+
+impl Default for VersionMessage {
+    // Timestamped now, with a random nonce so a node can spot a
+    // connection to itself
+    fn default() -> VersionMessage {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        let unspecified = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 8333);
+        VersionMessage {
+            version: PROTOCOL_VERSION,
+            services: 0,
+            timestamp,
+            receiver_services: 0,
+            receiver: unspecified,
+            sender_services: 0,
+            sender: unspecified,
+            nonce: random_nonce(),
+            user_agent: USER_AGENT.to_vec(),
+            latest_block: 0,
+            relay: false,
+        }
+    }
+}
+
+impl VersionMessage {
+    pub fn new() -> VersionMessage {
+        VersionMessage::default()
+    }
+}
+
+impl Message for VersionMessage {
+    const COMMAND: &'static [u8] = b"version";
+
+    // Peers older than BIP37 leave off the relay flag, which then means true
+    fn parse<R: Read>(reader: &mut R) -> Result<VersionMessage, ParseError> {
+        let version = read_u32(reader)?;
+        let services = read_u64(reader)?;
+        let timestamp = read_u64(reader)?;
+        let receiver_services = read_u64(reader)?;
+        let receiver = read_socket_addr(reader)?;
+        let sender_services = read_u64(reader)?;
+        let sender = read_socket_addr(reader)?;
+        let mut nonce = [0u8; 8];
+        reader.read_exact(&mut nonce)?;
+        let user_agent_len = read_varint(reader)?;
+        let user_agent = read_bytes(reader, user_agent_len)?;
+        let latest_block = read_u32(reader)?;
+        let mut relay = [1u8; 1];
+        if reader.read(&mut relay)? == 0 {
+            relay[0] = 1;
+        }
+        Ok(VersionMessage {
+            version,
+            services,
+            timestamp,
+            receiver_services,
+            receiver,
+            sender_services,
+            sender,
+            nonce,
+            user_agent,
+            latest_block,
+            relay: relay[0] != 0,
+        })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut result = self.version.to_le_bytes().to_vec();
+        result.extend_from_slice(&self.services.to_le_bytes());
+        result.extend_from_slice(&self.timestamp.to_le_bytes());
+        result.extend_from_slice(&self.receiver_services.to_le_bytes());
+        result.extend(serialize_socket_addr(&self.receiver));
+        result.extend_from_slice(&self.sender_services.to_le_bytes());
+        result.extend(serialize_socket_addr(&self.sender));
+        result.extend_from_slice(&self.nonce);
+        result.extend(encode_varint(self.user_agent.len() as u64));
+        result.extend_from_slice(&self.user_agent);
+        result.extend_from_slice(&self.latest_block.to_le_bytes());
+        result.push(self.relay as u8);
+        result
+    }
+}
+
+// Acknowledges the other side's version message, and has no payload
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct VerAckMessage;
+
+impl Message for VerAckMessage {
+    const COMMAND: &'static [u8] = b"verack";
+
+    fn parse<R: Read>(_reader: &mut R) -> Result<VerAckMessage, ParseError> {
+        Ok(VerAckMessage)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+// Sent to check a peer is still there, answered with a pong carrying the
+// same nonce
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PingMessage {
+    pub nonce: [u8; 8],
+}
+
+impl PingMessage {
+    pub fn new() -> PingMessage {
+        PingMessage { nonce: random_nonce() }
+    }
+}
+
+impl Default for PingMessage {
+    fn default() -> PingMessage {
+        PingMessage::new()
+    }
+}
+
+impl Message for PingMessage {
+    const COMMAND: &'static [u8] = b"ping";
+
+    fn parse<R: Read>(reader: &mut R) -> Result<PingMessage, ParseError> {
+        let mut nonce = [0u8; 8];
+        reader.read_exact(&mut nonce)?;
+        Ok(PingMessage { nonce })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.nonce.to_vec()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PongMessage {
+    pub nonce: [u8; 8],
+}
+
+impl Message for PongMessage {
+    const COMMAND: &'static [u8] = b"pong";
+
+    fn parse<R: Read>(reader: &mut R) -> Result<PongMessage, ParseError> {
+        let mut nonce = [0u8; 8];
+        reader.read_exact(&mut nonce)?;
+        Ok(PongMessage { nonce })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.nonce.to_vec()
+    }
+}
+
+// Only needs to differ between connections, not be unpredictable
+fn random_nonce() -> [u8; 8] {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64));
+    hasher.finish().to_le_bytes()
+}
+
+fn serialize_socket_addr(addr: &SocketAddr) -> Vec<u8> {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    };
+    let mut result = ip.octets().to_vec();
+    result.extend_from_slice(&addr.port().to_be_bytes());
+    result
+}
+
+fn read_socket_addr<R: Read>(reader: &mut R) -> io::Result<SocketAddr> {
+    let mut ip = [0u8; 16];
+    reader.read_exact(&mut ip)?;
+    let mut port = [0u8; 2];
+    reader.read_exact(&mut port)?;
+    let ip = Ipv6Addr::from(ip);
+    let ip = match ip.to_ipv4_mapped() {
+        Some(ip) => IpAddr::V4(ip),
+        None => IpAddr::V6(ip),
+    };
+    Ok(SocketAddr::new(ip, u16::from_be_bytes(port)))
+}
+
+// Block locator hashes and the stop hash are kept in display order, the
+// wire format stores them reversed. An all-zero stop hash asks for as
+// many headers as the peer will send.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GetHeadersMessage {
+    pub version: u32,
+    pub block_locator: Vec<[u8; 32]>,
+    pub stop_block: [u8; 32],
+}
+
+impl GetHeadersMessage {
+    pub fn new(start_block: [u8; 32]) -> GetHeadersMessage {
+        GetHeadersMessage { version: PROTOCOL_VERSION, block_locator: vec![start_block], stop_block: [0u8; 32] }
+    }
+}
+
+impl Message for GetHeadersMessage {
+    const COMMAND: &'static [u8] = b"getheaders";
+
+    fn parse<R: Read>(reader: &mut R) -> Result<GetHeadersMessage, ParseError> {
+        let version = read_u32(reader)?;
+        let num_hashes = read_varint(reader)?;
+        let mut block_locator = Vec::new();
+        for _ in 0..num_hashes {
+            block_locator.push(read_hash(reader)?);
+        }
+        let stop_block = read_hash(reader)?;
+        Ok(GetHeadersMessage { version, block_locator, stop_block })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut result = self.version.to_le_bytes().to_vec();
+        result.extend(encode_varint(self.block_locator.len() as u64));
+        for hash in &self.block_locator {
+            result.extend(hash.iter().rev());
+        }
+        result.extend(self.stop_block.iter().rev());
+        result
+    }
+}
+
+// Each header is followed by a transaction count, which is always zero
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HeadersMessage {
+    pub headers: Vec<BlockHeader>,
+}
+
+impl HeadersMessage {
+    pub fn new(headers: Vec<BlockHeader>) -> HeadersMessage {
+        HeadersMessage { headers }
+    }
+}
+
+impl Message for HeadersMessage {
+    const COMMAND: &'static [u8] = b"headers";
+
+    fn parse<R: Read>(reader: &mut R) -> Result<HeadersMessage, ParseError> {
+        let num_headers = read_varint(reader)?;
+        let mut headers = Vec::new();
+        for _ in 0..num_headers {
+            headers.push(BlockHeader::parse(reader)?);
+            if read_varint(reader)? != 0 {
+                return Err(ParseError::InvalidFormat("headers message carries transactions"));
+            }
+        }
+        Ok(HeadersMessage { headers })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut result = encode_varint(self.headers.len() as u64);
+        for header in &self.headers {
+            result.extend_from_slice(&header.serialize());
+            result.push(0);
+        }
+        result
+    }
+}
+
+fn read_hash<R: Read>(reader: &mut R) -> io::Result<[u8; 32]> {
+    let mut hash = [0u8; 32];
+    reader.read_exact(&mut hash)?;
+    hash.reverse();
+    Ok(hash)
+}
+
+#[derive(Debug)]
+pub enum NodeError {
+    Parse(ParseError),
+    Chain(ChainError),
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeError::Parse(err) => write!(f, "{}", err),
+            NodeError::Chain(err) => write!(f, "Peer sent an invalid header: {}", err),
+        }
+    }
+}
+
+impl From<ParseError> for NodeError {
+    fn from(err: ParseError) -> NodeError {
+        NodeError::Parse(err)
+    }
+}
+
+impl From<io::Error> for NodeError {
+    fn from(err: io::Error) -> NodeError {
+        NodeError::Parse(err.into())
+    }
+}
+
+impl From<ChainError> for NodeError {
+    fn from(err: ChainError) -> NodeError {
+        NodeError::Chain(err)
+    }
+}
+
+// A peer connection that speaks just enough of the protocol to keep the
+// other side talking. Any stream works, which is how it is tested.
+#[derive(Debug)]
+pub struct SimpleNode<S = TcpStream> {
+    stream: S,
+    testnet: bool,
+    logging: bool,
+    last_ping: Option<Instant>,
+}
+
+impl SimpleNode<TcpStream> {
+    pub fn connect(host: &str, port: u16, testnet: bool, logging: bool) -> io::Result<SimpleNode<TcpStream>> {
+        Ok(SimpleNode::new(TcpStream::connect((host, port))?, testnet, logging))
+    }
+}
+
+impl<S: Read + Write> SimpleNode<S> {
+    pub fn new(stream: S, testnet: bool, logging: bool) -> SimpleNode<S> {
+        SimpleNode { stream, testnet, logging, last_ping: None }
+    }
+
+    pub fn send<M: Message>(&mut self, message: &M) -> io::Result<()> {
+        self.send_envelope(&NetworkEnvelope::from_message(message, self.testnet))
+    }
+
+    fn send_envelope(&mut self, envelope: &NetworkEnvelope) -> io::Result<()> {
+        if self.logging {
+            println!("sending: {}", envelope);
+        }
+        self.stream.write_all(&envelope.serialize())
+    }
+
+    pub fn read(&mut self) -> Result<NetworkEnvelope, ParseError> {
+        let envelope = NetworkEnvelope::parse(&mut self.stream, self.testnet)?;
+        if self.logging {
+            println!("receiving: {}", envelope);
+        }
+        Ok(envelope)
+    }
+
+    // Reads until an envelope with one of the commands arrives, answering
+    // version with verack and ping with pong along the way
+    pub fn wait_for(&mut self, commands: &[&[u8]]) -> Result<NetworkEnvelope, ParseError> {
+        loop {
+            let envelope = self.read()?;
+            let command = envelope.command_name();
+            if commands.contains(&command) {
+                return Ok(envelope);
+            }
+            if command == VersionMessage::COMMAND {
+                self.send(&VerAckMessage)?;
+            } else if command == PingMessage::COMMAND {
+                let ping: PingMessage = envelope.message()?;
+                self.send(&PongMessage { nonce: ping.nonce })?;
+            }
+        }
+    }
+
+    pub fn wait_for_message<M: Message>(&mut self) -> Result<M, ParseError> {
+        self.wait_for(&[M::COMMAND])?.message()
+    }
+
+    // Round trip to the pong for a fresh ping. Pongs for other pings are
+    // skipped.
+    pub fn ping(&mut self) -> Result<Duration, ParseError> {
+        let ping = PingMessage::new();
+        let start = Instant::now();
+        self.last_ping = Some(start);
+        self.send(&ping)?;
+        loop {
+            let pong: PongMessage = self.wait_for_message()?;
+            if pong.nonce == ping.nonce {
+                return Ok(start.elapsed());
+            }
+        }
+    }
+
+    // Whether interval has passed since the last ping, or there has been
+    // none, for callers pinging an otherwise idle connection
+    pub fn keepalive_due(&self, interval: Duration) -> bool {
+        self.last_ping.is_none_or(|last_ping| last_ping.elapsed() >= interval)
+    }
+
+    // Up to count headers on from the chain's tip, each checked and added to
+    // the chain as it arrives. Peers send at most 2000 per headers message,
+    // so this asks again from the new tip until it has enough or the peer
+    // has nothing more.
+    pub fn download_headers(&mut self, chain: &mut HeaderChain, count: usize) -> Result<Vec<BlockHeader>, NodeError> {
+        let mut headers = Vec::new();
+        while headers.len() < count {
+            self.send(&GetHeadersMessage::new(chain.tip().hash()))?;
+            let batch: HeadersMessage = self.wait_for_message()?;
+            if batch.headers.is_empty() {
+                break;
+            }
+            for header in batch.headers.into_iter().take(count - headers.len()) {
+                chain.add(header)?;
+                headers.push(header);
+            }
+        }
+        Ok(headers)
+    }
+
+    // Our version out, and the peer's verack back
+    pub fn handshake(&mut self) -> Result<(), ParseError> {
+        self.send(&VersionMessage::new())?;
+        self.wait_for_message::<VerAckMessage>()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::decode_hex;
+
+    #[test]
+    fn getheaders_serialize() {
+        let mut start_block = [0u8; 32];
+        start_block.copy_from_slice(&decode_hex("0000000000000000001237f46acddf58578a37e213d2a6edc4884a2fcad05ba3").unwrap());
+        let message = GetHeadersMessage::new(start_block);
+        let serialized = message.serialize();
+        assert_eq!(encode_hex(&serialized), "7f11010001a35bd0ca2f4a88c4eda6d213e2378a5758dfcd6af437120000000000000000000000000000000000000000000000000000000000000000000000000000000000");
+        assert_eq!(GetHeadersMessage::parse(&mut &serialized[..]).unwrap(), message);
+    }
+
+    #[test]
+    fn headers_parse() {
+        let raw = decode_hex("0200000020df3b053dc46f162a9b00c7f0d5124e2676d47bbe7c5d0793a500000000000000ef445fef2ed495c275892206ca533e7411907971013ab83e3b47bd0d692d14d4dc7c835b67d8001ac157e670000000002030eb2540c41025690160a1014c577061596e32e426b712c7ca00000000000000768b89f07044e6130ead292a3f51951adbd2202df447d98789339937fd006bd44880835b67d8001ade09204600").unwrap();
+        let headers = HeadersMessage::parse(&mut &raw[..]).unwrap();
+        assert_eq!(headers.headers.len(), 2);
+        assert!(headers.headers.iter().all(|header| header.check_pow()));
+        assert_eq!(headers.serialize(), raw);
+    }
+
+    const VERACK_HEX: &str = "f9beb4d976657261636b000000000000000000005df6e0e2";
+    const VERSION_HEX: &str = "f9beb4d976657273696f6e0000000000650000005f1a69d2721101000100000000000000bc8f5e5400000000010000000000000000000000000000000000ffffc61b6409208d010000000000000000000000000000000000ffffcb0071c0208d128035cbc97953f80f2f5361746f7368693a302e392e332fcf05050001";
+
+    #[test]
+    fn envelope_parse() {
+        let raw = decode_hex(VERACK_HEX).unwrap();
+        let envelope = NetworkEnvelope::parse(&mut &raw[..], false).unwrap();
+        assert_eq!(envelope.command_name(), b"verack");
+        assert!(envelope.payload.is_empty());
+        assert_eq!(envelope.serialize(), raw);
+        let raw = decode_hex(VERSION_HEX).unwrap();
+        let envelope = NetworkEnvelope::parse(&mut &raw[..], false).unwrap();
+        assert_eq!(envelope.command_name(), b"version");
+        assert_eq!(envelope.serialize(), raw);
+        assert_eq!(NetworkEnvelope::new(b"version", envelope.payload.clone(), false), envelope);
+    }
+
+    #[test]
+    fn envelope_rejects_magic_and_checksum() {
+        let raw = decode_hex(VERSION_HEX).unwrap();
+        assert!(matches!(NetworkEnvelope::parse(&mut &raw[..], true), Err(ParseError::WrongMagic { .. })));
+        let mut corrupt = raw.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(matches!(NetworkEnvelope::parse(&mut &corrupt[..], false), Err(ParseError::BadChecksum)));
+    }
+
+    #[test]
+    fn version_message() {
+        let version = VersionMessage { timestamp: 0, nonce: [0; 8], ..VersionMessage::new() };
+        let serialized = version.serialize();
+        assert_eq!(
+            encode_hex(&serialized),
+            "7f11010000000000000000000000000000000000000000000000000000000000000000000000ffff00000000208d000000000000000000000000000000000000ffff00000000208d0000000000000000182f70726f6772616d6d696e67626974636f696e3a302e312f0000000000"
+        );
+        assert_eq!(VersionMessage::parse(&mut &serialized[..]).unwrap(), version);
+        let raw = decode_hex(VERSION_HEX).unwrap();
+        let envelope = NetworkEnvelope::parse(&mut &raw[..], false).unwrap();
+        let received = VersionMessage::parse(&mut &envelope.payload[..]).unwrap();
+        assert_eq!(received.user_agent, b"/Satoshi:0.9.3/");
+        assert_eq!(received.receiver.to_string(), "198.27.100.9:8333");
+        assert_eq!(received.serialize(), envelope.payload);
+        assert_ne!(VersionMessage::new().nonce, VersionMessage::new().nonce);
+    }
+
+    #[test]
+    fn envelope_display_and_closed_connections() {
+        let raw = decode_hex(VERACK_HEX).unwrap();
+        assert_eq!(NetworkEnvelope::parse(&mut &raw[..], false).unwrap().to_string(), "verack: ");
+        assert_eq!(NetworkEnvelope::new(b"pong", vec![1, 2, 0xab], false).to_string(), "pong: 0102ab");
+        // Nothing at all is a closed connection, part of an envelope is not
+        assert!(matches!(NetworkEnvelope::parse(&mut &[][..], false), Err(ParseError::ConnectionClosed)));
+        assert!(matches!(NetworkEnvelope::parse(&mut &raw[..2], false), Err(ParseError::UnexpectedEof)));
+        assert!(matches!(NetworkEnvelope::parse(&mut &raw[..10], false), Err(ParseError::UnexpectedEof)));
+    }
+
+    #[test]
+    fn verack_message() {
+        let envelope = NetworkEnvelope::from_message(&VerAckMessage, false);
+        assert_eq!(envelope.serialize(), decode_hex(VERACK_HEX).unwrap());
+        assert_eq!(VerAckMessage.command(), b"verack");
+        assert_eq!(envelope.message::<VerAckMessage>().unwrap(), VerAckMessage);
+        let version = NetworkEnvelope::parse(&mut &decode_hex(VERSION_HEX).unwrap()[..], false).unwrap();
+        assert!(version.message::<VerAckMessage>().is_err());
+        assert!(version.message::<VersionMessage>().is_ok());
+    }
+
+    type Responder = Box<dyn FnMut(&NetworkEnvelope) -> Vec<NetworkEnvelope>>;
+
+    // A peer that hands out queued envelopes and answers each one sent to
+    // it with whatever respond returns
+    struct MockPeer {
+        pending: Vec<u8>,
+        sent: Vec<NetworkEnvelope>,
+        respond: Responder,
+    }
+
+    impl MockPeer {
+        fn new<F>(queued: &[NetworkEnvelope], respond: F) -> MockPeer
+        where
+            F: FnMut(&NetworkEnvelope) -> Vec<NetworkEnvelope> + 'static,
+        {
+            let pending = queued.iter().flat_map(NetworkEnvelope::serialize).collect();
+            MockPeer { pending, sent: vec![], respond: Box::new(respond) }
+        }
+    }
+
+    impl Read for MockPeer {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.pending.len());
+            buf[..len].copy_from_slice(&self.pending[..len]);
+            self.pending.drain(..len);
+            Ok(len)
+        }
+    }
+
+    impl Write for MockPeer {
+        // SimpleNode writes each envelope whole
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let envelope = NetworkEnvelope::parse(&mut &buf[..], false).expect("a whole envelope");
+            for response in (self.respond)(&envelope) {
+                self.pending.extend(response.serialize());
+            }
+            self.sent.push(envelope);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn handshake_answers_version_and_ping() {
+        let queued = [
+            NetworkEnvelope::from_message(&VersionMessage::new(), false),
+            NetworkEnvelope::from_message(&PingMessage { nonce: [9; 8] }, false),
+            NetworkEnvelope::from_message(&VerAckMessage, false),
+        ];
+        let mut node = SimpleNode::new(MockPeer::new(&queued, |_| vec![]), false, false);
+        node.handshake().unwrap();
+        let sent = &node.stream.sent;
+        assert_eq!(sent.len(), 3);
+        assert!(sent[0].message::<VersionMessage>().is_ok());
+        assert_eq!(sent[1].message::<VerAckMessage>().unwrap(), VerAckMessage);
+        assert_eq!(sent[2].message::<PongMessage>().unwrap(), PongMessage { nonce: [9; 8] });
+        assert!(matches!(node.read(), Err(ParseError::ConnectionClosed)));
+    }
+
+    #[test]
+    fn ping_skips_other_pongs() {
+        // The peer pings back, then answers with a stale pong before the right one
+        let respond = |envelope: &NetworkEnvelope| match envelope.message::<PingMessage>() {
+            Ok(ping) => vec![
+                NetworkEnvelope::from_message(&PingMessage { nonce: [5; 8] }, false),
+                NetworkEnvelope::from_message(&PongMessage { nonce: [0; 8] }, false),
+                NetworkEnvelope::from_message(&PongMessage { nonce: ping.nonce }, false),
+            ],
+            Err(_) => vec![],
+        };
+        let mut node = SimpleNode::new(MockPeer::new(&[], respond), false, false);
+        assert!(node.keepalive_due(Duration::from_secs(60)));
+        node.ping().unwrap();
+        assert!(!node.keepalive_due(Duration::from_secs(60)));
+        assert!(node.keepalive_due(Duration::ZERO));
+        assert_eq!(node.stream.sent[1].message::<PongMessage>().unwrap(), PongMessage { nonce: [5; 8] });
+        assert!(node.stream.pending.is_empty());
+    }
+
+    #[test]
+    fn ping_message() {
+        let ping = PingMessage { nonce: [1, 2, 3, 4, 5, 6, 7, 8] };
+        assert_eq!(ping.serialize(), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(PingMessage::parse(&mut &ping.serialize()[..]).unwrap(), ping);
+        assert_ne!(PingMessage::new().nonce, PingMessage::new().nonce);
+    }
+
+    // Serves the headers one per message, each after a ping, to a node
+    // asking from the header before it
+    fn header_peer(served: Vec<BlockHeader>) -> MockPeer {
+        MockPeer::new(&[], move |envelope| {
+            let Ok(request) = envelope.message::<GetHeadersMessage>() else {
+                return vec![];
+            };
+            let start_block = request.block_locator[0];
+            let next = served.iter().filter(|header| header.prev_block == start_block).take(1).copied();
+            vec![
+                NetworkEnvelope::new(b"ping", vec![1; 8], false),
+                NetworkEnvelope::from_message(&HeadersMessage::new(next.collect()), false),
+            ]
+        })
+    }
+
+    #[test]
+    fn download_headers() {
+        let header = |hex| BlockHeader::parse(&mut &decode_hex(hex).unwrap()[..]).unwrap();
+        let block1 = header("010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299");
+        let block2 = header("010000004860eb18bf1b1620e37e9490fc8a427514416fd75159ab86688e9a8300000000d5fdcc541e25de1c7a5addedf24858b8bb665c9f36ef744ee42c316022c90f9bb0bc6649ffff001d08d2bd61");
+        let mut node = SimpleNode::new(header_peer(vec![block1, block2]), false, false);
+        let mut chain = HeaderChain::new(false);
+        assert_eq!(node.download_headers(&mut chain, 10).unwrap(), vec![block1, block2]);
+        assert_eq!(chain.height(), 2);
+        let mut node = SimpleNode::new(header_peer(vec![block1, block2]), false, false);
+        assert_eq!(node.download_headers(&mut HeaderChain::new(false), 1).unwrap(), vec![block1]);
+        let no_pow = BlockHeader { nonce: [0; 4], ..block2 };
+        let mut node = SimpleNode::new(header_peer(vec![block1, no_pow]), false, false);
+        let err = node.download_headers(&mut HeaderChain::new(false), 5);
+        assert!(matches!(err, Err(NodeError::Chain(ChainError::InsufficientPow))));
+    }
+
+    // The peer's side of a handshake and a header download from genesis:
+    // version and verack, then mainnet headers 1 to 8 in one headers
+    // message, and an empty one when asked for more
+    const HEADERS_HEX: &str = "f9beb4d968656164657273000000000089020000e318aa9c08010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e3629900010000004860eb18bf1b1620e37e9490fc8a427514416fd75159ab86688e9a8300000000d5fdcc541e25de1c7a5addedf24858b8bb665c9f36ef744ee42c316022c90f9bb0bc6649ffff001d08d2bd610001000000bddd99ccfda39da1b108ce1a5d70038d0a967bacb68b6b63065f626a0000000044f672226090d85db9a9f2fbfe5f0f9609b387af7be5b7fbb7a1767c831c9e995dbe6649ffff001d05e0ed6d00010000004944469562ae1c2c74d9a535e00b6f3e40ffbad4f2fda3895501b582000000007a06ea98cd40ba2e3288262b28638cec5337c1456aaf5eedc8e9e5a20f062bdf8cc16649ffff001d2bfee0a9000100000085144a84488ea88d221c8bd6c059da090e88f8a2c99690ee55dbba4e00000000e11c48fecdd9e72510ca84f023370c9a38bf91ac5cae88019bee94d24528526344c36649ffff001d1d03e4770001000000fc33f596f822a0a1951ffdbf2a897b095636ad871707bf5d3162729b00000000379dfb96a5ea8c81700ea4ac6b97ae9a9312b2d4301a29580e924ee6761a2520adc46649ffff001d189c4c9700010000008d778fdc15a2d3fb76b7122a3b5582bea4f21f5a0c693537e7a03130000000003f674005103b42f984169c7d008370967e91920a6a5d64fd51282f75bc73a68af1c66649ffff001d39a59c8600010000004494c8cf4154bdcc0720cd4a59d9c9b285e4b146d45f061d2b6c967100000000e3855ed886605b6d4a99d5fa2ef2e9b0b164e63df3c4136bebf2d0dac0f1f7a667c86649ffff001d1c4b566600f9beb4d9686561646572730000000000010000001406e05800";
+
+    #[test]
+    fn download_headers_from_transcript() {
+        let mut transcript = decode_hex(VERSION_HEX).unwrap();
+        transcript.extend(decode_hex(VERACK_HEX).unwrap());
+        transcript.extend(decode_hex(HEADERS_HEX).unwrap());
+        let mut node = SimpleNode::new(MockPeer::new(&[], |_| vec![]), false, false);
+        node.stream.pending = transcript;
+        node.handshake().unwrap();
+        let mut chain = HeaderChain::new(false);
+        let headers = node.download_headers(&mut chain, 2000).unwrap();
+        assert_eq!(headers.len(), 8);
+        assert_eq!(chain.height(), 8);
+        assert_eq!(chain.tip().id(), "00000000408c48f847aa786c2268fc3e6ec2af68e8468a34a28c61b7f1de0dc6");
+        let sent: Vec<&[u8]> = node.stream.sent.iter().map(NetworkEnvelope::command_name).collect();
+        assert_eq!(sent, vec![&b"version"[..], b"verack", b"getheaders", b"getheaders"]);
+        let second: GetHeadersMessage = node.stream.sent[3].message().unwrap();
+        assert_eq!(second.block_locator, vec![chain.tip().hash()]);
+        assert!(node.stream.pending.is_empty());
+    }
+
+    // A mainnet DNS seed
+    const MAINNET_SEED: &str = "seed.bitcoin.sipa.be";
+
+    // Needs the network, and takes a while: cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn download_mainnet_headers_from_genesis() {
+        let mut node = SimpleNode::connect(MAINNET_SEED, 8333, false, false).unwrap();
+        node.handshake().unwrap();
+        let mut chain = HeaderChain::new(false);
+        let headers = node.download_headers(&mut chain, 40_000).unwrap();
+        // Nineteen retargets along the way
+        assert_eq!(headers.len(), 40_000);
+        assert_eq!(chain.height(), 40_000);
+    }
+
+    // A testnet DNS seed, resolving to nodes that accept connections
+    const TESTNET_SEED: &str = "seed.testnet.bitcoin.sprovoost.nl";
+
+    // Needs the network: cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn handshake_with_testnet_node() {
+        let mut node = SimpleNode::connect(TESTNET_SEED, 18333, true, false).unwrap();
+        node.handshake().unwrap();
+    }
+}
@@ -0,0 +1,700 @@
+use crate::ecc::{S256Point, Signature};
+use crate::hashes::{hash160, hash256, sha256};
+use crate::script::MAX_PUBKEYS_PER_MULTISIG;
+use crate::tx::{
+    SighashType, LOCKTIME_THRESHOLD, SEQUENCE_DISABLE_FLAG, SEQUENCE_FINAL, SEQUENCE_MASK, SEQUENCE_TYPE_FLAG,
+};
+use crate::u256::U256;
+
+// Opcodes that aren't defined (and the direct push lengths 0x01-0x4b,
+// which the parser turns into data) round-trip through Unknown
+macro_rules! opcodes {
+    ($($name:ident = $byte:expr,)*) => {
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+        pub enum OpCode {
+            $($name,)*
+            Unknown(u8),
+        }
+
+        impl OpCode {
+            pub fn from_byte(byte: u8) -> OpCode {
+                match byte {
+                    $($byte => OpCode::$name,)*
+                    other => OpCode::Unknown(other),
+                }
+            }
+
+            pub fn to_byte(self) -> u8 {
+                match self {
+                    $(OpCode::$name => $byte,)*
+                    OpCode::Unknown(byte) => byte,
+                }
+            }
+
+            pub fn name(self) -> Option<&'static str> {
+                match self {
+                    $(OpCode::$name => Some(stringify!($name)),)*
+                    OpCode::Unknown(_) => None,
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<OpCode> {
+                match name {
+                    $(stringify!($name) => Some(OpCode::$name),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    OP_0 = 0x00,
+    OP_PUSHDATA1 = 0x4c,
+    OP_PUSHDATA2 = 0x4d,
+    OP_PUSHDATA4 = 0x4e,
+    OP_1NEGATE = 0x4f,
+    OP_RESERVED = 0x50,
+    OP_1 = 0x51,
+    OP_2 = 0x52,
+    OP_3 = 0x53,
+    OP_4 = 0x54,
+    OP_5 = 0x55,
+    OP_6 = 0x56,
+    OP_7 = 0x57,
+    OP_8 = 0x58,
+    OP_9 = 0x59,
+    OP_10 = 0x5a,
+    OP_11 = 0x5b,
+    OP_12 = 0x5c,
+    OP_13 = 0x5d,
+    OP_14 = 0x5e,
+    OP_15 = 0x5f,
+    OP_16 = 0x60,
+    OP_NOP = 0x61,
+    OP_VER = 0x62,
+    OP_IF = 0x63,
+    OP_NOTIF = 0x64,
+    OP_VERIF = 0x65,
+    OP_VERNOTIF = 0x66,
+    OP_ELSE = 0x67,
+    OP_ENDIF = 0x68,
+    OP_VERIFY = 0x69,
+    OP_RETURN = 0x6a,
+    OP_TOALTSTACK = 0x6b,
+    OP_FROMALTSTACK = 0x6c,
+    OP_2DROP = 0x6d,
+    OP_2DUP = 0x6e,
+    OP_3DUP = 0x6f,
+    OP_2OVER = 0x70,
+    OP_2ROT = 0x71,
+    OP_2SWAP = 0x72,
+    OP_IFDUP = 0x73,
+    OP_DEPTH = 0x74,
+    OP_DROP = 0x75,
+    OP_DUP = 0x76,
+    OP_NIP = 0x77,
+    OP_OVER = 0x78,
+    OP_PICK = 0x79,
+    OP_ROLL = 0x7a,
+    OP_ROT = 0x7b,
+    OP_SWAP = 0x7c,
+    OP_TUCK = 0x7d,
+    OP_CAT = 0x7e,
+    OP_SUBSTR = 0x7f,
+    OP_LEFT = 0x80,
+    OP_RIGHT = 0x81,
+    OP_SIZE = 0x82,
+    OP_INVERT = 0x83,
+    OP_AND = 0x84,
+    OP_OR = 0x85,
+    OP_XOR = 0x86,
+    OP_EQUAL = 0x87,
+    OP_EQUALVERIFY = 0x88,
+    OP_RESERVED1 = 0x89,
+    OP_RESERVED2 = 0x8a,
+    OP_1ADD = 0x8b,
+    OP_1SUB = 0x8c,
+    OP_2MUL = 0x8d,
+    OP_2DIV = 0x8e,
+    OP_NEGATE = 0x8f,
+    OP_ABS = 0x90,
+    OP_NOT = 0x91,
+    OP_0NOTEQUAL = 0x92,
+    OP_ADD = 0x93,
+    OP_SUB = 0x94,
+    OP_MUL = 0x95,
+    OP_DIV = 0x96,
+    OP_MOD = 0x97,
+    OP_LSHIFT = 0x98,
+    OP_RSHIFT = 0x99,
+    OP_BOOLAND = 0x9a,
+    OP_BOOLOR = 0x9b,
+    OP_NUMEQUAL = 0x9c,
+    OP_NUMEQUALVERIFY = 0x9d,
+    OP_NUMNOTEQUAL = 0x9e,
+    OP_LESSTHAN = 0x9f,
+    OP_GREATERTHAN = 0xa0,
+    OP_LESSTHANOREQUAL = 0xa1,
+    OP_GREATERTHANOREQUAL = 0xa2,
+    OP_MIN = 0xa3,
+    OP_MAX = 0xa4,
+    OP_WITHIN = 0xa5,
+    OP_RIPEMD160 = 0xa6,
+    OP_SHA1 = 0xa7,
+    OP_SHA256 = 0xa8,
+    OP_HASH160 = 0xa9,
+    OP_HASH256 = 0xaa,
+    OP_CODESEPARATOR = 0xab,
+    OP_CHECKSIG = 0xac,
+    OP_CHECKSIGVERIFY = 0xad,
+    OP_CHECKMULTISIG = 0xae,
+    OP_CHECKMULTISIGVERIFY = 0xaf,
+    OP_NOP1 = 0xb0,
+    OP_CHECKLOCKTIMEVERIFY = 0xb1,
+    OP_CHECKSEQUENCEVERIFY = 0xb2,
+    OP_NOP4 = 0xb3,
+    OP_NOP5 = 0xb4,
+    OP_NOP6 = 0xb5,
+    OP_NOP7 = 0xb6,
+    OP_NOP8 = 0xb7,
+    OP_NOP9 = 0xb8,
+    OP_NOP10 = 0xb9,
+    OP_CHECKSIGADD = 0xba,
+    OP_INVALIDOPCODE = 0xff,
+}
+
+impl OpCode {
+    // Opcodes that fail the script wherever they appear, even in a branch
+    // that is not executed
+    pub fn is_disabled(self) -> bool {
+        matches!(
+            self,
+            OpCode::OP_CAT
+                | OpCode::OP_SUBSTR
+                | OpCode::OP_LEFT
+                | OpCode::OP_RIGHT
+                | OpCode::OP_INVERT
+                | OpCode::OP_AND
+                | OpCode::OP_OR
+                | OpCode::OP_XOR
+                | OpCode::OP_2MUL
+                | OpCode::OP_2DIV
+                | OpCode::OP_MUL
+                | OpCode::OP_DIV
+                | OpCode::OP_MOD
+                | OpCode::OP_LSHIFT
+                | OpCode::OP_RSHIFT
+                | OpCode::OP_VERIF
+                | OpCode::OP_VERNOTIF
+        )
+    }
+}
+
+pub type Stack = Vec<Vec<u8>>;
+
+// Minimal little-endian sign-magnitude encoding used for script numbers
+pub fn encode_num(num: i64) -> Vec<u8> {
+    if num == 0 {
+        return Vec::new();
+    }
+    let negative = num < 0;
+    let mut abs_num = num.unsigned_abs();
+    let mut result = Vec::new();
+    while abs_num > 0 {
+        result.push((abs_num & 0xff) as u8);
+        abs_num >>= 8;
+    }
+    let last = result.len() - 1;
+    if result[last] & 0x80 != 0 {
+        result.push(if negative { 0x80 } else { 0x00 });
+    } else if negative {
+        result[last] |= 0x80;
+    }
+    result
+}
+
+pub fn decode_num(element: &[u8]) -> i64 {
+    let Some((&last, rest)) = element.split_last() else {
+        return 0;
+    };
+    let negative = last & 0x80 != 0;
+    let mut result = (last & 0x7f) as i64;
+    for &byte in rest.iter().rev() {
+        result = (result << 8) | byte as i64;
+    }
+    if negative {
+        -result
+    } else {
+        result
+    }
+}
+
+// Any non-zero byte is true, except for negative zero (a lone sign bit)
+pub fn cast_to_bool(element: &[u8]) -> bool {
+    for (i, &byte) in element.iter().enumerate() {
+        if byte != 0 {
+            return !(i == element.len() - 1 && byte == 0x80);
+        }
+    }
+    false
+}
+
+// Arithmetic operands are limited to four bytes, results may be longer
+const MAX_NUM_SIZE: usize = 4;
+
+fn pop_num(stack: &mut Stack) -> Option<i64> {
+    match stack.pop() {
+        Some(element) if element.len() <= MAX_NUM_SIZE => Some(decode_num(&element)),
+        _ => None,
+    }
+}
+
+pub fn op_0(stack: &mut Stack) -> bool {
+    stack.push(Vec::new());
+    true
+}
+
+// OP_1NEGATE and OP_1 to OP_16
+pub fn op_num(stack: &mut Stack, num: i64) -> bool {
+    stack.push(encode_num(num));
+    true
+}
+
+pub fn op_dup(stack: &mut Stack) -> bool {
+    match stack.last() {
+        Some(top) => {
+            let top = top.clone();
+            stack.push(top);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn op_drop(stack: &mut Stack) -> bool {
+    stack.pop().is_some()
+}
+
+pub fn op_swap(stack: &mut Stack) -> bool {
+    let len = stack.len();
+    if len < 2 {
+        return false;
+    }
+    stack.swap(len - 1, len - 2);
+    true
+}
+
+pub fn op_toaltstack(stack: &mut Stack, altstack: &mut Stack) -> bool {
+    match stack.pop() {
+        Some(element) => {
+            altstack.push(element);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn op_fromaltstack(stack: &mut Stack, altstack: &mut Stack) -> bool {
+    match altstack.pop() {
+        Some(element) => {
+            stack.push(element);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn op_sha256(stack: &mut Stack) -> bool {
+    match stack.pop() {
+        Some(element) => {
+            stack.push(sha256(&element).to_vec());
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn op_hash256(stack: &mut Stack) -> bool {
+    match stack.pop() {
+        Some(element) => {
+            stack.push(hash256(&element).to_vec());
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn op_hash160(stack: &mut Stack) -> bool {
+    match stack.pop() {
+        Some(element) => {
+            stack.push(hash160(&element).to_vec());
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn op_equal(stack: &mut Stack) -> bool {
+    if stack.len() < 2 {
+        return false;
+    }
+    let element1 = stack.pop().unwrap();
+    let element2 = stack.pop().unwrap();
+    stack.push(encode_num((element1 == element2) as i64));
+    true
+}
+
+pub fn op_verify(stack: &mut Stack) -> bool {
+    match stack.pop() {
+        Some(element) => cast_to_bool(&element),
+        None => false,
+    }
+}
+
+pub fn op_equalverify(stack: &mut Stack) -> bool {
+    op_equal(stack) && op_verify(stack)
+}
+
+// Pops b then a and pushes f(a, b)
+fn binary_num_op(stack: &mut Stack, f: fn(i64, i64) -> i64) -> bool {
+    let (Some(b), Some(a)) = (pop_num(stack), pop_num(stack)) else {
+        return false;
+    };
+    stack.push(encode_num(f(a, b)));
+    true
+}
+
+pub fn op_add(stack: &mut Stack) -> bool {
+    binary_num_op(stack, |a, b| a + b)
+}
+
+pub fn op_sub(stack: &mut Stack) -> bool {
+    binary_num_op(stack, |a, b| a - b)
+}
+
+// Disabled by consensus, so scripts using it fail, but the book's chapter
+// 6 exercises use it
+pub fn op_mul(stack: &mut Stack) -> bool {
+    binary_num_op(stack, |a, b| a * b)
+}
+
+pub fn op_not(stack: &mut Stack) -> bool {
+    match pop_num(stack) {
+        Some(num) => {
+            stack.push(encode_num((num == 0) as i64));
+            true
+        }
+        None => false,
+    }
+}
+
+// Computes the digest a signature commits to from its sighash type, None
+// when no digest can be produced for that input
+pub type SigHasher<'a> = dyn Fn(SighashType) -> Option<U256> + 'a;
+
+// A DER signature with its trailing sighash byte, and the digest it signs.
+// Only the six defined sighash types are accepted.
+pub(crate) fn signature_and_digest(element: &[u8], sig_hash: &SigHasher) -> Option<(Signature, U256)> {
+    let (&sighash_byte, der) = element.split_last()?;
+    let z = sig_hash(SighashType::from_byte(sighash_byte)?)?;
+    Some((Signature::parse(der).ok()?, z))
+}
+
+pub fn op_checksig(stack: &mut Stack, sig_hash: &SigHasher) -> bool {
+    if stack.len() < 2 {
+        return false;
+    }
+    let sec_pubkey = stack.pop().unwrap();
+    let der_signature = stack.pop().unwrap();
+    let valid = match (S256Point::parse(&sec_pubkey), signature_and_digest(&der_signature, sig_hash)) {
+        (Ok(point), Some((sig, z))) => point.verify(z, &sig),
+        _ => false,
+    };
+    stack.push(encode_num(valid as i64));
+    true
+}
+
+pub fn op_checksigverify(stack: &mut Stack, sig_hash: &SigHasher) -> bool {
+    op_checksig(stack, sig_hash) && op_verify(stack)
+}
+
+// Stack layout, top first: n, n pubkeys, m, m signatures, plus one extra
+// element that consensus pops because of an off-by-one in the original
+// implementation. Signatures must match pubkeys in the same order.
+pub fn op_checkmultisig(stack: &mut Stack, sig_hash: &SigHasher) -> bool {
+    let Some(n) = pop_num(stack) else {
+        return false;
+    };
+    if !(0..=MAX_PUBKEYS_PER_MULTISIG as i64).contains(&n) || stack.len() < n as usize + 1 {
+        return false;
+    }
+    let sec_pubkeys: Vec<Vec<u8>> = (0..n).map(|_| stack.pop().unwrap()).collect();
+    let Some(m) = pop_num(stack) else {
+        return false;
+    };
+    if !(0..=n).contains(&m) || stack.len() < m as usize + 1 {
+        return false;
+    }
+    let der_signatures: Vec<Vec<u8>> = (0..m).map(|_| stack.pop().unwrap()).collect();
+    stack.pop();
+
+    // As in op_checksig, a key or signature that does not parse is just
+    // a failed match
+    let mut points = sec_pubkeys.iter().map(|sec| S256Point::parse(sec).ok());
+    let valid = der_signatures.iter().all(|der| match signature_and_digest(der, sig_hash) {
+        Some((sig, z)) => points.any(|point| point.is_some_and(|point| point.verify(z, &sig))),
+        None => false,
+    });
+    stack.push(encode_num(valid as i64));
+    true
+}
+
+pub fn op_checkmultisigverify(stack: &mut Stack, sig_hash: &SigHasher) -> bool {
+    op_checkmultisig(stack, sig_hash) && op_verify(stack)
+}
+
+// CSV compares the type flag along with the value
+const CSV_SEQUENCE_MASK: u32 = SEQUENCE_TYPE_FLAG | SEQUENCE_MASK;
+
+// Locktime operands may be five bytes, enough for any u32
+const MAX_LOCKTIME_NUM_SIZE: usize = 5;
+
+// The parts of the spending transaction the timelock opcodes check. The
+// default is for scripts run outside a transaction, where CLTV and CSV
+// always fail.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct ExecutionContext {
+    pub locktime: u32,
+    pub sequence: u32,
+    pub version: u32,
+}
+
+// Reads the top element without popping it, the opcodes being
+// redefined NOPs. Negative numbers always fail.
+fn peek_timelock(stack: &Stack) -> Option<i64> {
+    match stack.last() {
+        Some(element) if element.len() <= MAX_LOCKTIME_NUM_SIZE => Some(decode_num(element)).filter(|&num| num >= 0),
+        _ => None,
+    }
+}
+
+// BIP65: the transaction's locktime must be of the same kind (height or
+// time) as the operand and at least as large, and the input must not be
+// final, or the locktime would not be enforced at all
+pub fn op_checklocktimeverify(stack: &mut Stack, context: &ExecutionContext) -> bool {
+    // No u32 locktime reaches an operand of 2^32 or more
+    let Some(locktime) = peek_timelock(stack).and_then(|num| u32::try_from(num).ok()) else {
+        return false;
+    };
+    (locktime < LOCKTIME_THRESHOLD) == (context.locktime < LOCKTIME_THRESHOLD)
+        && locktime <= context.locktime
+        && context.sequence != SEQUENCE_FINAL
+}
+
+// BIP112: the same for the input's relative locktime in its sequence.
+// An operand with the disable flag set makes this a NOP. As in Core,
+// only the flag and value bits of the operand count, so one of 2^32 or
+// more is fine and its high bits are ignored.
+pub fn op_checksequenceverify(stack: &mut Stack, context: &ExecutionContext) -> bool {
+    let Some(sequence) = peek_timelock(stack) else {
+        return false;
+    };
+    if sequence & i64::from(SEQUENCE_DISABLE_FLAG) != 0 {
+        return true;
+    }
+    if context.version < 2 || context.sequence & SEQUENCE_DISABLE_FLAG != 0 {
+        return false;
+    }
+    let sequence = (sequence & i64::from(CSV_SEQUENCE_MASK)) as u32;
+    let tx_sequence = context.sequence & CSV_SEQUENCE_MASK;
+    (sequence & SEQUENCE_TYPE_FLAG) == (tx_sequence & SEQUENCE_TYPE_FLAG) && sequence <= tx_sequence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::decode_hex;
+
+    #[test]
+    fn num_encoding() {
+        assert_eq!(encode_num(0), Vec::<u8>::new());
+        assert_eq!(encode_num(-255), vec![0xff, 0x80]);
+        for num in [0i64, -1, 127, 128, -255, 1000, -32768] {
+            assert_eq!(decode_num(&encode_num(num)), num);
+        }
+    }
+
+    fn u256(hex: &str) -> U256 {
+        U256::from_be_slice(&decode_hex(hex).unwrap()).unwrap()
+    }
+
+    // The 2-of-2 input from chapter 8
+    fn multisig_stack(sig1_first: bool) -> Stack {
+        let sig1 = decode_hex("3045022100dc92655fe37036f47756db8102e0d7d5e28b3beb83a8fef4f5dc0559bddfb94e02205a36d4e4e6c7fcd16658c50783e00c341609977aed3ad00937bf4ee942a8993701").unwrap();
+        let sig2 = decode_hex("3045022100da6bee3c93766232079a01639d07fa869598749729ae323eab8eef53577d611b02207bef15429dcadce2121ea07f233115c6f09034c0be68db99980b9a6c5e75402201").unwrap();
+        let sec1 = decode_hex("022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb70").unwrap();
+        let sec2 = decode_hex("03b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb71").unwrap();
+        let sigs = if sig1_first { vec![sig1, sig2] } else { vec![sig2, sig1] };
+        let mut stack = vec![vec![]];
+        stack.extend(sigs);
+        stack.extend([vec![2], sec1, sec2, vec![2]]);
+        stack
+    }
+
+    #[test]
+    fn checkmultisig() {
+        let z = u256("e71bfa115715d6fd33796948126f40a8cdd39f187e4afb03896795189fe1423c");
+        let mut stack = multisig_stack(true);
+        assert!(op_checkmultisig(&mut stack, &|_| Some(z)));
+        assert_eq!(stack, vec![vec![1]]);
+        // Signatures out of key order fail
+        let mut stack = multisig_stack(false);
+        assert!(op_checkmultisig(&mut stack, &|_| Some(z)));
+        assert_eq!(stack, vec![Vec::<u8>::new()]);
+        let mut stack = multisig_stack(true);
+        assert!(op_checkmultisig(&mut stack, &|_| Some(U256::ONE)));
+        assert_eq!(stack, vec![Vec::<u8>::new()]);
+    }
+
+    #[test]
+    fn checkmultisig_needs_dummy() {
+        let z = u256("e71bfa115715d6fd33796948126f40a8cdd39f187e4afb03896795189fe1423c");
+        let mut stack = multisig_stack(true);
+        stack.remove(0);
+        assert!(!op_checkmultisig(&mut stack, &|_| Some(z)));
+    }
+
+    #[test]
+    fn checkmultisig_parse_failures_push_zero() {
+        let z = u256("e71bfa115715d6fd33796948126f40a8cdd39f187e4afb03896795189fe1423c");
+        let mut stack = multisig_stack(true);
+        stack[1] = vec![0x30, 0x00, 0x01];
+        assert!(op_checkmultisig(&mut stack, &|_| Some(z)));
+        assert_eq!(stack, vec![Vec::<u8>::new()]);
+        let mut stack = multisig_stack(true);
+        let sec2 = stack.len() - 2;
+        stack[sec2] = vec![0x05; 33];
+        assert!(op_checkmultisig(&mut stack, &|_| Some(z)));
+        assert_eq!(stack, vec![Vec::<u8>::new()]);
+        // A bad key that no signature needs is skipped over
+        let mut stack = multisig_stack(true);
+        stack.pop();
+        stack.insert(5, vec![0x05; 33]);
+        stack.push(vec![3]);
+        assert!(op_checkmultisig(&mut stack, &|_| Some(z)));
+        assert_eq!(stack, vec![vec![1]]);
+    }
+
+    #[test]
+    fn num_encoding_edges() {
+        for (num, encoded) in [(-1, vec![0x81]), (127, vec![0x7f]), (128, vec![0x80, 0x00])] {
+            assert_eq!(encode_num(num), encoded);
+            assert_eq!(decode_num(&encoded), num);
+        }
+        // Negative zero decodes to zero and is false
+        assert_eq!(decode_num(&[0x80]), 0);
+        assert!(!cast_to_bool(&[0x80]));
+    }
+
+    #[test]
+    fn checksig_uncompressed() {
+        // The chapter 6 p2pk example
+        let z = u256("7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d");
+        let sec = decode_hex("04887387e452b8eacc4acfde10d9aaf7f6d9a0f975aabb10d006e4da568744d06c61de6d95231cd89026e286df3b6ae4a894a3378e393e93a0f45b666329a0ae34").unwrap();
+        let sig = decode_hex("3045022000eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c022100c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab601").unwrap();
+        let mut stack = vec![sig.clone(), sec.clone()];
+        assert!(op_checksig(&mut stack, &|_| Some(z)));
+        assert_eq!(stack, vec![vec![1]]);
+        let mut stack = vec![sig, sec];
+        assert!(op_checksig(&mut stack, &|_| Some(U256::ONE)));
+        assert_eq!(stack, vec![Vec::<u8>::new()]);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut stack = vec![encode_num(2), encode_num(3)];
+        assert!(op_add(&mut stack));
+        assert_eq!(stack, vec![encode_num(5)]);
+        let mut stack = vec![encode_num(2), encode_num(3)];
+        assert!(op_swap(&mut stack));
+        assert!(op_sub(&mut stack));
+        assert_eq!(stack, vec![encode_num(1)]);
+        let mut stack = vec![encode_num(6), encode_num(-7)];
+        assert!(op_mul(&mut stack));
+        assert_eq!(decode_num(&stack[0]), -42);
+        let mut stack = vec![encode_num(0)];
+        assert!(op_not(&mut stack));
+        assert_eq!(stack, vec![encode_num(1)]);
+        // Operands are limited to four bytes
+        let mut stack = vec![vec![1; 5], encode_num(1)];
+        assert!(!op_add(&mut stack));
+    }
+
+    #[test]
+    fn hashes_of_empty_input() {
+        let mut stack = vec![vec![]];
+        assert!(op_sha256(&mut stack));
+        assert_eq!(crate::util::encode_hex(&stack[0]), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        let mut stack = vec![vec![]];
+        assert!(op_hash256(&mut stack));
+        assert_eq!(crate::util::encode_hex(&stack[0]), "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456");
+    }
+
+    fn context(locktime: u32, sequence: u32, version: u32) -> ExecutionContext {
+        ExecutionContext { locktime, sequence, version }
+    }
+
+    fn cltv(operand: i64, context: ExecutionContext) -> bool {
+        let mut stack = vec![encode_num(operand)];
+        op_checklocktimeverify(&mut stack, &context)
+    }
+
+    fn csv(operand: i64, context: ExecutionContext) -> bool {
+        let mut stack = vec![encode_num(operand)];
+        op_checksequenceverify(&mut stack, &context)
+    }
+
+    #[test]
+    fn checklocktimeverify() {
+        assert!(cltv(100, context(100, 0, 1)));
+        assert!(cltv(100, context(200, 0xfffffffe, 1)));
+        assert!(!cltv(101, context(100, 0, 1)));
+        // A final input does not enforce the locktime
+        assert!(!cltv(100, context(100, 0xffffffff, 1)));
+        // Heights and times do not compare
+        assert!(!cltv(100, context(600_000_000, 0, 1)));
+        assert!(!cltv(600_000_000, context(100_000_001, 0, 1)));
+        assert!(cltv(600_000_000, context(600_000_001, 0, 1)));
+        assert!(!cltv(-1, context(100, 0, 1)));
+        assert!(!cltv(1 << 32, context(0xffffffff, 0, 1)));
+        assert!(!op_checklocktimeverify(&mut vec![], &context(1, 0, 1)));
+        assert!(!op_checklocktimeverify(&mut vec![vec![1; 6]], &context(1, 0, 1)));
+    }
+
+    #[test]
+    fn checksequenceverify() {
+        assert!(csv(10, context(0, 10, 2)));
+        assert!(!csv(10, context(0, 10, 1)));
+        assert!(!csv(11, context(0, 10, 2)));
+        // Blocks and time do not compare
+        assert!(!csv(10, context(0, 10 | (1 << 22), 2)));
+        assert!(csv(10 | (1 << 22), context(0, 20 | (1 << 22), 2)));
+        // Bits outside the type flag and value are ignored in the input
+        assert!(csv(10, context(0, 10 | (1 << 20), 2)));
+        assert!(!csv(10, context(0, (1 << 31) | 10, 2)));
+        assert!(!csv(-1, context(0, 10, 2)));
+    }
+
+    #[test]
+    fn checksequenceverify_operand_masks() {
+        // The disable flag makes it a NOP whatever the transaction
+        assert!(csv(1 << 31, context(0, 0, 1)));
+        assert!(csv((1 << 31) | 5, context(0, 0xffffffff, 0)));
+        // Five-byte operands of 2^32 and up are masked, not rejected
+        assert!(csv((1 << 32) | 10, context(0, 10, 2)));
+        assert!(!csv((1 << 32) | 11, context(0, 10, 2)));
+        assert!(csv((1 << 32) | (1 << 31), context(0, 0, 1)));
+    }
+}
@@ -0,0 +1,285 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Add, Mul};
+
+use crate::field::{FieldElement, FieldElementError};
+
+// Shared with S256Point, hence the recovery variants. Lives here so it is
+// available without std.
+#[derive(Debug)]
+pub enum PointError {
+    NotOnCurve,
+    DifferentCurves,
+    Field(FieldElementError),
+    InvalidRecoveryId(u8),
+    NotRecoverable,
+}
+
+impl fmt::Display for PointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PointError::NotOnCurve => write!(f, "Point is not on the curve"),
+            PointError::DifferentCurves => write!(f, "Cannot add points from different curves"),
+            PointError::Field(err) => write!(f, "{}", err),
+            PointError::InvalidRecoveryId(recovery_id) => write!(f, "Invalid recovery id {}", recovery_id),
+            PointError::NotRecoverable => write!(f, "No public key can be recovered from the signature"),
+        }
+    }
+}
+
+impl From<FieldElementError> for PointError {
+    fn from(err: FieldElementError) -> PointError {
+        PointError::Field(err)
+    }
+}
+
+// A point on y^2 = x^3 + ax + b over a small prime field, None being the
+// point at infinity. S256Point is the same idea specialised to secp256k1.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Point {
+    coords: Option<(FieldElement, FieldElement)>,
+    a: FieldElement,
+    b: FieldElement,
+}
+
+impl Point {
+    pub fn new(x: FieldElement, y: FieldElement, a: FieldElement, b: FieldElement) -> Result<Point, PointError> {
+        // Mixing fields fails in the arithmetic
+        let lhs = (y * y)?;
+        let rhs = ((((x * x)? * x)? + (a * x)?)? + b)?;
+        if lhs != rhs {
+            return Err(PointError::NotOnCurve);
+        }
+        Ok(Point { coords: Some((x, y)), a, b })
+    }
+
+    pub fn infinity(a: FieldElement, b: FieldElement) -> Result<Point, PointError> {
+        if a.prime() != b.prime() {
+            return Err(FieldElementError::DifferentFields.into());
+        }
+        Ok(Point { coords: None, a, b })
+    }
+
+    pub fn is_infinity(&self) -> bool {
+        self.coords.is_none()
+    }
+
+    pub fn x(&self) -> Option<FieldElement> {
+        self.coords.map(|(x, _)| x)
+    }
+
+    pub fn y(&self) -> Option<FieldElement> {
+        self.coords.map(|(_, y)| y)
+    }
+
+    pub fn a(&self) -> FieldElement {
+        self.a
+    }
+
+    pub fn b(&self) -> FieldElement {
+        self.b
+    }
+
+    // Every point on the curve, infinity first, by trying each (x, y) in
+    // the field. Only meant for small primes, being quadratic in the prime.
+    pub fn all_points(a: FieldElement, b: FieldElement) -> Result<Vec<Point>, PointError> {
+        let mut points = vec![Point::infinity(a, b)?];
+        let prime = a.prime();
+        let element = |n: i64| FieldElement::new(n, prime).expect("below the prime");
+        for x in (0..prime).map(element) {
+            let rhs = ((((x * x)? * x)? + (a * x)?)? + b)?;
+            for y in (0..prime).map(element) {
+                if (y * y)? == rhs {
+                    points.push(Point { coords: Some((x, y)), a, b });
+                }
+            }
+        }
+        Ok(points)
+    }
+
+    // Smallest n with n * self at infinity, found by adding self until
+    // getting there. The group is finite so it always does, but it takes
+    // up to about prime steps.
+    pub fn group_order(&self) -> u64 {
+        let mut order = 1;
+        let mut current = *self;
+        while !current.is_infinity() {
+            current = current.add_same_curve(*self);
+            order += 1;
+        }
+        order
+    }
+
+    // Straus: one run of doublings shared by all scalars, each adding its
+    // point wherever it has a bit set, as S256Point does for verify. None
+    // when there are no pairs to give a curve or they mix curves.
+    pub fn multi_scalar_mul(pairs: &[(u64, Point)]) -> Option<Point> {
+        let (_, first) = pairs.first()?;
+        if pairs.iter().any(|(_, point)| !point.same_curve(first)) {
+            return None;
+        }
+        let bits = pairs.iter().map(|(coefficient, _)| 64 - coefficient.leading_zeros()).max().unwrap_or(0);
+        let mut result = Point { coords: None, ..*first };
+        for i in (0..bits).rev() {
+            result = result.add_same_curve(result);
+            for (coefficient, point) in pairs {
+                if (coefficient >> i) & 1 == 1 {
+                    result = result.add_same_curve(*point);
+                }
+            }
+        }
+        Some(result)
+    }
+
+    fn same_curve(&self, other: &Point) -> bool {
+        self.a == other.a && self.b == other.b
+    }
+
+    // Both points are known to be on the same curve, so every element is
+    // in one field and none of the arithmetic can fail
+    fn add_same_curve(self, other: Point) -> Point {
+        fn fe(result: Result<FieldElement, FieldElementError>) -> FieldElement {
+            result.expect("points on one curve share a field")
+        }
+        let constant = |n: i64| fe(FieldElement::new(n % self.a.prime(), self.a.prime()));
+        let (Some((x1, y1)), Some((x2, y2))) = (self.coords, other.coords) else {
+            return if self.is_infinity() { other } else { self };
+        };
+        // Vertical line: the points are inverses, or a point with y = 0 is doubled
+        if x1 == x2 && (y1 != y2 || y1.num() == 0) {
+            return Point { coords: None, ..self };
+        }
+        let slope = if x1 == x2 {
+            fe(fe(fe(constant(3) * fe(x1 * x1)) + self.a) / fe(constant(2) * y1))
+        } else {
+            fe(fe(y2 - y1) / fe(x2 - x1))
+        };
+        let x3 = fe(fe(fe(slope * slope) - x1) - x2);
+        let y3 = fe(fe(slope * fe(x1 - x3)) - y1);
+        Point { coords: Some((x3, y3)), ..self }
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.coords {
+            Some((x, y)) => {
+                write!(f, "Point({},{})_{}_{} FieldElement({})", x.num(), y.num(), self.a.num(), self.b.num(), self.a.prime())
+            }
+            None => write!(f, "Point(infinity)"),
+        }
+    }
+}
+
+impl Add for Point {
+    type Output = Result<Point, PointError>;
+
+    fn add(self, other: Point) -> Result<Point, PointError> {
+        if !self.same_curve(&other) {
+            return Err(PointError::DifferentCurves);
+        }
+        Ok(self.add_same_curve(other))
+    }
+}
+
+// Double-and-add, which stays on one curve so cannot fail
+impl Mul<Point> for u64 {
+    type Output = Point;
+
+    fn mul(self, point: Point) -> Point {
+        let mut coefficient = self;
+        let mut current = point;
+        let mut result = Point { coords: None, ..point };
+        while coefficient > 0 {
+            if coefficient & 1 == 1 {
+                result = result.add_same_curve(current);
+            }
+            current = current.add_same_curve(current);
+            coefficient >>= 1;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    // Elements of F_223, the field of the chapter 3 curve y^2 = x^3 + 7
+    fn fe(num: i64) -> FieldElement {
+        FieldElement::new(num, 223).unwrap()
+    }
+
+    fn point(x: i64, y: i64) -> Point {
+        Point::new(fe(x), fe(y), fe(0), fe(7)).unwrap()
+    }
+
+    fn coords(point: Point) -> Option<(i64, i64)> {
+        Some((point.x()?.num(), point.y()?.num()))
+    }
+
+    #[test]
+    fn addition() {
+        assert_eq!(coords((point(192, 105) + point(17, 56)).unwrap()), Some((170, 142)));
+        let infinity = Point::infinity(fe(0), fe(7)).unwrap();
+        assert_eq!((infinity + point(192, 105)).unwrap(), point(192, 105));
+        assert_eq!((point(192, 105) + infinity).unwrap(), point(192, 105));
+        assert!((point(192, 105) + point(192, 223 - 105)).unwrap().is_infinity());
+    }
+
+    #[test]
+    fn scalar_multiplication() {
+        assert_eq!(coords(2 * point(47, 71)), Some((36, 111)));
+        assert!((21 * point(47, 71)).is_infinity());
+        assert_eq!(22 * point(47, 71), point(47, 71));
+    }
+
+    #[test]
+    fn errors() {
+        let other_curve = Point::new(fe(1), fe(1), fe(0), fe(0)).unwrap();
+        assert!(matches!(point(192, 105) + other_curve, Err(PointError::DifferentCurves)));
+        assert!(matches!(Point::new(fe(200), fe(119), fe(0), fe(7)), Err(PointError::NotOnCurve)));
+        let other_field = FieldElement::new(1, 7).unwrap();
+        assert!(matches!(Point::new(other_field, fe(1), fe(0), fe(7)), Err(PointError::Field(_))));
+    }
+
+    #[test]
+    fn all_points_of_small_curve() {
+        let points = Point::all_points(fe(0), fe(7)).unwrap();
+        assert_eq!(points.len(), 252);
+        assert!(points[0].is_infinity());
+        assert!(points.contains(&point(47, 71)));
+        assert!(points.iter().all(|point| (252 * *point).is_infinity()));
+        let other_field = FieldElement::new(7, 19).unwrap();
+        assert!(Point::all_points(fe(0), other_field).is_err());
+    }
+
+    #[test]
+    fn multi_scalar_mul_matches_separate_products() {
+        let points = Point::all_points(fe(0), fe(7)).unwrap();
+        for (i, (a, b)) in [(0, 0), (1, 2), (20, 33), (251, 1000), (u64::MAX, 7)].into_iter().enumerate() {
+            let (p, q) = (points[i * 17 + 3], points[i * 31 + 5]);
+            assert_eq!(Point::multi_scalar_mul(&[(a, p), (b, q)]).unwrap(), ((a * p) + (b * q)).unwrap());
+        }
+        assert!(Point::multi_scalar_mul(&[]).is_none());
+        let other_curve = Point::infinity(fe(1), fe(7)).unwrap();
+        assert!(Point::multi_scalar_mul(&[(1, points[1]), (1, other_curve)]).is_none());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Point::infinity(fe(0), fe(7)).unwrap().to_string(), "Point(infinity)");
+        assert_eq!(point(192, 105).to_string(), "Point(192,105)_0_7 FieldElement(223)");
+    }
+
+    #[test]
+    fn group_order() {
+        assert_eq!(point(15, 86).group_order(), 7);
+        assert!((7 * point(15, 86)).is_infinity());
+        assert_eq!(point(47, 71).group_order(), 21);
+        assert!(!(20 * point(47, 71)).is_infinity());
+        assert_eq!(Point::infinity(fe(0), fe(7)).unwrap().group_order(), 1);
+    }
+}
@@ -0,0 +1,158 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::field::{FieldElement, FieldElementError};
+
+#[derive(Debug)]
+pub enum PolynomialError {
+    Field(FieldElementError),
+    DuplicateX,
+}
+
+impl fmt::Display for PolynomialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolynomialError::Field(err) => write!(f, "{}", err),
+            PolynomialError::DuplicateX => write!(f, "Points to interpolate share an x coordinate"),
+        }
+    }
+}
+
+impl From<FieldElementError> for PolynomialError {
+    fn from(err: FieldElementError) -> PolynomialError {
+        PolynomialError::Field(err)
+    }
+}
+
+// Coefficients over one field, constant term first, with no trailing zeros
+// so that equal polynomials compare equal. No coefficients is the zero
+// polynomial, in whatever field it is evaluated.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Polynomial {
+    coefficients: Vec<FieldElement>,
+}
+
+// Elements checked to share a field combine without error
+fn same_field(result: Result<FieldElement, FieldElementError>) -> FieldElement {
+    result.expect("elements share a field")
+}
+
+impl Polynomial {
+    fn trimmed(mut coefficients: Vec<FieldElement>) -> Polynomial {
+        while coefficients.last().is_some_and(|c| c.num() == 0) {
+            coefficients.pop();
+        }
+        Polynomial { coefficients }
+    }
+
+    pub fn new(coefficients: Vec<FieldElement>) -> Result<Polynomial, PolynomialError> {
+        if let Some(first) = coefficients.first() {
+            if coefficients.iter().any(|c| c.prime() != first.prime()) {
+                return Err(FieldElementError::DifferentFields.into());
+            }
+        }
+        Ok(Polynomial::trimmed(coefficients))
+    }
+
+    pub fn coefficients(&self) -> &[FieldElement] {
+        &self.coefficients
+    }
+
+    // Horner's method: one multiply and add per coefficient
+    pub fn evaluate(&self, x: FieldElement) -> Result<FieldElement, PolynomialError> {
+        let zero = FieldElement::new(0, x.prime())?;
+        Ok(self.coefficients.iter().rev().try_fold(zero, |acc, &c| (acc * x)? + c)?)
+    }
+
+    // Lagrange: the sum over each point of its y times the basis polynomial
+    // that is 1 at its x and 0 at every other. The result has degree below
+    // the number of points, lower when the points lie on such a curve.
+    pub fn interpolate(points: &[(FieldElement, FieldElement)]) -> Result<Polynomial, PolynomialError> {
+        let Some(&(first, _)) = points.first() else {
+            return Ok(Polynomial { coefficients: Vec::new() });
+        };
+        let prime = first.prime();
+        if points.iter().any(|(x, y)| x.prime() != prime || y.prime() != prime) {
+            return Err(FieldElementError::DifferentFields.into());
+        }
+        if points.iter().enumerate().any(|(i, (x, _))| points[..i].iter().any(|(other, _)| other == x)) {
+            return Err(PolynomialError::DuplicateX);
+        }
+        let zero = FieldElement::new(0, prime)?;
+        let one = FieldElement::new(1, prime)?;
+        let mut coefficients = vec![zero; points.len()];
+        for (i, &(xi, yi)) in points.iter().enumerate() {
+            let mut basis = vec![one];
+            let mut denominator = one;
+            for (j, &(xj, _)) in points.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+                // Multiply by (x - xj)
+                let mut next = vec![zero; basis.len() + 1];
+                for (k, &c) in basis.iter().enumerate() {
+                    next[k + 1] = same_field(next[k + 1] + c);
+                    next[k] = same_field(next[k] - same_field(c * xj));
+                }
+                basis = next;
+                denominator = same_field(denominator * same_field(xi - xj));
+            }
+            let scale = same_field(yi / denominator);
+            for (coefficient, c) in coefficients.iter_mut().zip(basis) {
+                *coefficient = same_field(*coefficient + same_field(c * scale));
+            }
+        }
+        Ok(Polynomial::trimmed(coefficients))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::Field;
+
+    fn element(num: i64) -> FieldElement {
+        Field::new(223).unwrap().element(num).unwrap()
+    }
+
+    fn samples(polynomial: &Polynomial, xs: &[i64]) -> Vec<(FieldElement, FieldElement)> {
+        xs.iter().map(|&x| (element(x), polynomial.evaluate(element(x)).unwrap())).collect()
+    }
+
+    #[test]
+    fn evaluate_and_interpolate() {
+        let polynomial = Polynomial::new(vec![element(42), element(7), element(100), element(3)]).unwrap();
+        // 42 + 7*5 + 100*25 + 3*125 = 2952, which is 53 mod 223
+        assert_eq!(polynomial.evaluate(element(5)).unwrap(), element(53));
+        let interpolated = Polynomial::interpolate(&samples(&polynomial, &[1, 2, 3, 200])).unwrap();
+        assert_eq!(interpolated, polynomial);
+        // The secret of a Shamir share set is the constant term
+        assert_eq!(interpolated.evaluate(element(0)).unwrap(), element(42));
+        assert_eq!(Polynomial::interpolate(&[(element(4), element(9))]).unwrap().coefficients(), &[element(9)]);
+        assert_eq!(Polynomial::new(vec![]).unwrap().evaluate(element(9)).unwrap(), element(0));
+    }
+
+    #[test]
+    fn trailing_zeros_are_trimmed() {
+        let line = Polynomial::new(vec![element(5), element(3)]).unwrap();
+        assert_eq!(Polynomial::interpolate(&samples(&line, &[1, 2, 3])).unwrap(), line);
+        assert_eq!(Polynomial::new(vec![element(5), element(3), element(0)]).unwrap(), line);
+        let zero = Polynomial::new(vec![element(0), element(0)]).unwrap();
+        assert!(zero.coefficients().is_empty());
+        let constant_points = [(element(1), element(0)), (element(2), element(0))];
+        assert_eq!(Polynomial::interpolate(&constant_points).unwrap(), zero);
+    }
+
+    #[test]
+    fn errors() {
+        let other_field = FieldElement::new(1, 19).unwrap();
+        let duplicate_x = [(element(1), element(2)), (element(1), element(3))];
+        assert!(matches!(Polynomial::interpolate(&duplicate_x), Err(PolynomialError::DuplicateX)));
+        let mixed = [(element(1), element(2)), (other_field, element(3))];
+        let err = Polynomial::interpolate(&mixed);
+        assert!(matches!(err, Err(PolynomialError::Field(FieldElementError::DifferentFields))));
+        assert!(Polynomial::new(vec![element(1), other_field]).is_err());
+        assert!(Polynomial::new(vec![element(1)]).unwrap().evaluate(other_field).is_err());
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+
+use crate::ecc::{PrivateKey, S256Point};
+use crate::error::ParseError;
+use crate::hashes::hash160;
+use crate::op::OpCode;
+use crate::script::{Cmd, Script};
+use crate::tx::{SighashType, Tx, TxError, TxOut};
+use crate::util::{encode_varint, read_bytes, read_u32, read_varint};
+
+pub const PSBT_MAGIC: [u8; 5] = [0x70, 0x73, 0x62, 0x74, 0xff];
+
+const GLOBAL_UNSIGNED_TX: u8 = 0x00;
+
+const IN_NON_WITNESS_UTXO: u8 = 0x00;
+const IN_WITNESS_UTXO: u8 = 0x01;
+const IN_PARTIAL_SIG: u8 = 0x02;
+const IN_SIGHASH_TYPE: u8 = 0x03;
+const IN_REDEEM_SCRIPT: u8 = 0x04;
+const IN_WITNESS_SCRIPT: u8 = 0x05;
+const IN_FINAL_SCRIPTSIG: u8 = 0x07;
+const IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+
+const OUT_REDEEM_SCRIPT: u8 = 0x00;
+const OUT_WITNESS_SCRIPT: u8 = 0x01;
+
+type KeyValueMap = BTreeMap<Vec<u8>, Vec<u8>>;
+
+#[derive(Debug)]
+pub enum PsbtError {
+    NotUnsigned,
+    MissingUtxo(usize),
+    UtxoMismatch(usize),
+    DifferentTx,
+    CannotFinalize(usize),
+    NotFinalized(usize),
+    Tx(TxError),
+}
+
+impl fmt::Display for PsbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PsbtError::NotUnsigned => write!(f, "Transaction already carries script_sigs or witnesses"),
+            PsbtError::MissingUtxo(index) => write!(f, "Input {} has no utxo", index),
+            PsbtError::UtxoMismatch(index) => write!(f, "Utxo of input {} is not the one it spends", index),
+            PsbtError::DifferentTx => write!(f, "PSBTs are for different transactions"),
+            PsbtError::CannotFinalize(index) => write!(f, "Input {} cannot be finalized", index),
+            PsbtError::NotFinalized(index) => write!(f, "Input {} is not finalized", index),
+            PsbtError::Tx(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<TxError> for PsbtError {
+    fn from(err: TxError) -> PsbtError {
+        PsbtError::Tx(err)
+    }
+}
+
+// BIP174 partially signed transaction. Key-value pairs this module does
+// not interpret, BIP32 derivations included, are kept in `unknown` and
+// written back unchanged.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Psbt {
+    pub unsigned_tx: Tx,
+    pub inputs: Vec<PsbtInput>,
+    pub outputs: Vec<PsbtOutput>,
+    pub unknown: KeyValueMap,
+}
+
+// Partial signatures are keyed by SEC pubkey, each a DER signature
+// followed by its sighash byte
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct PsbtInput {
+    pub non_witness_utxo: Option<Tx>,
+    pub witness_utxo: Option<TxOut>,
+    pub partial_sigs: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub sighash_type: Option<SighashType>,
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    pub final_script_sig: Option<Script>,
+    pub final_script_witness: Option<Vec<Vec<u8>>>,
+    pub unknown: KeyValueMap,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct PsbtOutput {
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    pub unknown: KeyValueMap,
+}
+
+impl Psbt {
+    pub fn from_unsigned_tx(unsigned_tx: Tx) -> Result<Psbt, PsbtError> {
+        if unsigned_tx.tx_ins.iter().any(|tx_in| !tx_in.script_sig.cmds.is_empty() || !tx_in.witness.is_empty()) {
+            return Err(PsbtError::NotUnsigned);
+        }
+        Ok(Psbt {
+            inputs: vec![PsbtInput::default(); unsigned_tx.tx_ins.len()],
+            outputs: vec![PsbtOutput::default(); unsigned_tx.tx_outs.len()],
+            unsigned_tx,
+            unknown: BTreeMap::new(),
+        })
+    }
+
+    // Magic, the global map, then one map per input and per output
+    pub fn parse<R: Read>(reader: &mut R, testnet: bool) -> Result<Psbt, ParseError> {
+        let mut magic = [0u8; 5];
+        reader.read_exact(&mut magic)?;
+        if magic != PSBT_MAGIC {
+            return Err(ParseError::InvalidFormat("missing psbt magic"));
+        }
+        let mut unknown = read_map(reader)?;
+        let raw_tx = match unknown.remove([GLOBAL_UNSIGNED_TX].as_slice()) {
+            Some(raw_tx) => raw_tx,
+            None => return Err(ParseError::InvalidFormat("psbt has no unsigned transaction")),
+        };
+        if unknown.keys().any(|key| key[0] == GLOBAL_UNSIGNED_TX) {
+            return Err(ParseError::InvalidFormat("psbt key has trailing data"));
+        }
+        let unsigned_tx = parse_complete(&raw_tx, |reader| Tx::parse(reader, testnet))?;
+        if unsigned_tx.tx_ins.iter().any(|tx_in| !tx_in.script_sig.cmds.is_empty() || !tx_in.witness.is_empty()) {
+            return Err(ParseError::InvalidFormat("psbt transaction is not unsigned"));
+        }
+        let mut inputs = Vec::new();
+        for _ in 0..unsigned_tx.tx_ins.len() {
+            inputs.push(PsbtInput::parse(reader, testnet)?);
+        }
+        let mut outputs = Vec::new();
+        for _ in 0..unsigned_tx.tx_outs.len() {
+            outputs.push(PsbtOutput::parse(reader)?);
+        }
+        Ok(Psbt { unsigned_tx, inputs, outputs, unknown })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = PSBT_MAGIC.to_vec();
+        let mut global = self.unknown.clone();
+        global.insert(vec![GLOBAL_UNSIGNED_TX], self.unsigned_tx.serialize_legacy());
+        result.extend(serialize_map(&global));
+        for input in &self.inputs {
+            result.extend(input.serialize());
+        }
+        for output in &self.outputs {
+            result.extend(output.serialize());
+        }
+        result
+    }
+
+    // The output each input spends, from its witness_utxo or else from
+    // the full previous transaction
+    pub fn prevouts(&self) -> Result<Vec<TxOut>, PsbtError> {
+        let mut prevouts = Vec::new();
+        for (index, (input, tx_in)) in self.inputs.iter().zip(&self.unsigned_tx.tx_ins).enumerate() {
+            let prevout = match (&input.witness_utxo, &input.non_witness_utxo) {
+                (Some(witness_utxo), _) => witness_utxo.clone(),
+                (None, Some(prev_tx)) => {
+                    if prev_tx.hash() != tx_in.prev_tx {
+                        return Err(PsbtError::UtxoMismatch(index));
+                    }
+                    let prevout = prev_tx.tx_outs.get(tx_in.prev_index as usize);
+                    prevout.ok_or(PsbtError::UtxoMismatch(index))?.clone()
+                }
+                (None, None) => return Err(PsbtError::MissingUtxo(index)),
+            };
+            prevouts.push(prevout);
+        }
+        Ok(prevouts)
+    }
+
+    // Adds a partial signature to every unfinalized p2pkh, p2wpkh or
+    // p2sh-p2wpkh input this key can spend and returns how many it signed
+    pub fn sign(&mut self, private_key: &PrivateKey) -> Result<usize, PsbtError> {
+        let prevouts = self.prevouts()?;
+        let mut signed = 0;
+        for input_index in 0..self.inputs.len() {
+            let input = &self.inputs[input_index];
+            if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+                continue;
+            }
+            let sighash_type = input.sighash_type.unwrap_or(SighashType::All);
+            let mut tx = self.unsigned_tx.clone();
+            match tx.sign_input(input_index, &prevouts, private_key, sighash_type) {
+                Ok(_) => {}
+                Err(TxError::CannotSign(_)) => continue,
+                Err(err) => return Err(err.into()),
+            }
+            let tx_in = &tx.tx_ins[input_index];
+            let input = &mut self.inputs[input_index];
+            let (sig, sec) = match (tx_in.witness.as_slice(), tx_in.script_sig.cmds.as_slice()) {
+                ([sig, sec], [Cmd::Data(redeem_script)]) => {
+                    input.redeem_script = Some(Script::parse_raw(redeem_script).map_err(TxError::from)?);
+                    (sig.clone(), sec.clone())
+                }
+                ([sig, sec], _) => (sig.clone(), sec.clone()),
+                (_, [Cmd::Data(sig), Cmd::Data(sec)]) => (sig.clone(), sec.clone()),
+                _ => continue,
+            };
+            input.partial_sigs.insert(sec, sig);
+            signed += 1;
+        }
+        Ok(signed)
+    }
+
+    // Merges what another signer added to the same transaction
+    pub fn combine(&mut self, other: Psbt) -> Result<(), PsbtError> {
+        if self.unsigned_tx != other.unsigned_tx {
+            return Err(PsbtError::DifferentTx);
+        }
+        self.unknown.extend(other.unknown);
+        for (input, other) in self.inputs.iter_mut().zip(other.inputs) {
+            input.non_witness_utxo = input.non_witness_utxo.take().or(other.non_witness_utxo);
+            input.witness_utxo = input.witness_utxo.take().or(other.witness_utxo);
+            input.partial_sigs.extend(other.partial_sigs);
+            input.sighash_type = input.sighash_type.or(other.sighash_type);
+            input.redeem_script = input.redeem_script.take().or(other.redeem_script);
+            input.witness_script = input.witness_script.take().or(other.witness_script);
+            input.final_script_sig = input.final_script_sig.take().or(other.final_script_sig);
+            input.final_script_witness = input.final_script_witness.take().or(other.final_script_witness);
+            input.unknown.extend(other.unknown);
+        }
+        for (output, other) in self.outputs.iter_mut().zip(other.outputs) {
+            output.redeem_script = output.redeem_script.take().or(other.redeem_script);
+            output.witness_script = output.witness_script.take().or(other.witness_script);
+            output.unknown.extend(other.unknown);
+        }
+        Ok(())
+    }
+
+    // Turns the partial signature of each p2pkh, p2wpkh and p2sh-p2wpkh
+    // input into its final script_sig and witness, dropping the fields
+    // only signers need
+    pub fn finalize(&mut self) -> Result<(), PsbtError> {
+        let prevouts = self.prevouts()?;
+        for (input_index, (input, prevout)) in self.inputs.iter_mut().zip(&prevouts).enumerate() {
+            if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+                continue;
+            }
+            let script_pubkey = &prevout.script_pubkey;
+            let (program, script_sig) = match &input.redeem_script {
+                Some(redeem_script) if script_pubkey.is_p2sh_script_pubkey() => {
+                    (redeem_script.clone(), Script::new(vec![Cmd::Data(redeem_script.raw_serialize())]))
+                }
+                _ => (script_pubkey.clone(), Script::new(vec![])),
+            };
+            let (h160, is_witness) = match program.cmds.as_slice() {
+                [Cmd::Op(OpCode::OP_0), Cmd::Data(h160)] if h160.len() == 20 => (h160, true),
+                [
+                    Cmd::Op(OpCode::OP_DUP),
+                    Cmd::Op(OpCode::OP_HASH160),
+                    Cmd::Data(h160),
+                    Cmd::Op(OpCode::OP_EQUALVERIFY),
+                    Cmd::Op(OpCode::OP_CHECKSIG),
+                ] if *script_pubkey == program => (h160, false),
+                _ => return Err(PsbtError::CannotFinalize(input_index)),
+            };
+            let signature = input.partial_sigs.iter().find(|(sec, _)| hash160(sec)[..] == h160[..]);
+            let (sec, sig) = signature.ok_or(PsbtError::CannotFinalize(input_index))?;
+            let (sec, sig) = (sec.clone(), sig.clone());
+            if is_witness {
+                input.final_script_sig = Some(script_sig);
+                input.final_script_witness = Some(vec![sig, sec]);
+            } else {
+                input.final_script_sig = Some(Script::new(vec![Cmd::Data(sig), Cmd::Data(sec)]));
+            }
+            input.partial_sigs.clear();
+            input.sighash_type = None;
+            input.redeem_script = None;
+            input.witness_script = None;
+        }
+        Ok(())
+    }
+
+    // The network-ready transaction once every input is finalized
+    pub fn extract_tx(&self) -> Result<Tx, PsbtError> {
+        let mut tx = self.unsigned_tx.clone();
+        for (input_index, (tx_in, input)) in tx.tx_ins.iter_mut().zip(&self.inputs).enumerate() {
+            if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+                return Err(PsbtError::NotFinalized(input_index));
+            }
+            tx_in.script_sig = input.final_script_sig.clone().unwrap_or_else(|| Script::new(vec![]));
+            tx_in.witness = input.final_script_witness.clone().unwrap_or_default();
+        }
+        Ok(tx)
+    }
+}
+
+impl PsbtInput {
+    fn parse<R: Read>(reader: &mut R, testnet: bool) -> Result<PsbtInput, ParseError> {
+        let mut input = PsbtInput::default();
+        for (key, value) in read_map(reader)? {
+            match (key[0], key.len()) {
+                (IN_NON_WITNESS_UTXO, 1) => {
+                    input.non_witness_utxo = Some(parse_complete(&value, |reader| Tx::parse(reader, testnet))?);
+                }
+                (IN_WITNESS_UTXO, 1) => input.witness_utxo = Some(parse_complete(&value, |reader| TxOut::parse(reader))?),
+                (IN_PARTIAL_SIG, _) => {
+                    S256Point::parse(&key[1..])?;
+                    input.partial_sigs.insert(key[1..].to_vec(), value);
+                }
+                (IN_SIGHASH_TYPE, 1) => {
+                    let sighash_type = parse_complete(&value, |reader| Ok(read_u32(reader)?))?;
+                    let sighash_type = u8::try_from(sighash_type).ok().and_then(SighashType::from_byte);
+                    match sighash_type {
+                        Some(sighash_type) => input.sighash_type = Some(sighash_type),
+                        None => return Err(ParseError::InvalidFormat("unknown sighash type")),
+                    }
+                }
+                (IN_REDEEM_SCRIPT, 1) => input.redeem_script = Some(Script::parse_raw(&value)?),
+                (IN_WITNESS_SCRIPT, 1) => input.witness_script = Some(Script::parse_raw(&value)?),
+                (IN_FINAL_SCRIPTSIG, 1) => input.final_script_sig = Some(Script::parse_raw(&value)?),
+                (IN_FINAL_SCRIPTWITNESS, 1) => {
+                    input.final_script_witness = Some(parse_complete(&value, |reader| {
+                        let num_items = read_varint(reader)?;
+                        let mut witness = Vec::new();
+                        for _ in 0..num_items {
+                            let item_len = read_varint(reader)?;
+                            witness.push(read_bytes(reader, item_len)?);
+                        }
+                        Ok(witness)
+                    })?);
+                }
+                (
+                    IN_NON_WITNESS_UTXO | IN_WITNESS_UTXO | IN_SIGHASH_TYPE | IN_REDEEM_SCRIPT | IN_WITNESS_SCRIPT
+                    | IN_FINAL_SCRIPTSIG | IN_FINAL_SCRIPTWITNESS,
+                    _,
+                ) => return Err(ParseError::InvalidFormat("psbt key has trailing data")),
+                _ => {
+                    input.unknown.insert(key, value);
+                }
+            }
+        }
+        Ok(input)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut map = self.unknown.clone();
+        if let Some(prev_tx) = &self.non_witness_utxo {
+            map.insert(vec![IN_NON_WITNESS_UTXO], prev_tx.serialize());
+        }
+        if let Some(prevout) = &self.witness_utxo {
+            map.insert(vec![IN_WITNESS_UTXO], prevout.serialize());
+        }
+        for (sec, sig) in &self.partial_sigs {
+            let mut key = vec![IN_PARTIAL_SIG];
+            key.extend_from_slice(sec);
+            map.insert(key, sig.clone());
+        }
+        if let Some(sighash_type) = self.sighash_type {
+            map.insert(vec![IN_SIGHASH_TYPE], (sighash_type.to_byte() as u32).to_le_bytes().to_vec());
+        }
+        if let Some(redeem_script) = &self.redeem_script {
+            map.insert(vec![IN_REDEEM_SCRIPT], redeem_script.raw_serialize());
+        }
+        if let Some(witness_script) = &self.witness_script {
+            map.insert(vec![IN_WITNESS_SCRIPT], witness_script.raw_serialize());
+        }
+        if let Some(script_sig) = &self.final_script_sig {
+            map.insert(vec![IN_FINAL_SCRIPTSIG], script_sig.raw_serialize());
+        }
+        if let Some(witness) = &self.final_script_witness {
+            let mut value = encode_varint(witness.len() as u64);
+            for item in witness {
+                value.extend(encode_varint(item.len() as u64));
+                value.extend_from_slice(item);
+            }
+            map.insert(vec![IN_FINAL_SCRIPTWITNESS], value);
+        }
+        serialize_map(&map)
+    }
+}
+
+impl PsbtOutput {
+    fn parse<R: Read>(reader: &mut R) -> Result<PsbtOutput, ParseError> {
+        let mut output = PsbtOutput::default();
+        for (key, value) in read_map(reader)? {
+            match (key[0], key.len()) {
+                (OUT_REDEEM_SCRIPT, 1) => output.redeem_script = Some(Script::parse_raw(&value)?),
+                (OUT_WITNESS_SCRIPT, 1) => output.witness_script = Some(Script::parse_raw(&value)?),
+                (OUT_REDEEM_SCRIPT | OUT_WITNESS_SCRIPT, _) => {
+                    return Err(ParseError::InvalidFormat("psbt key has trailing data"));
+                }
+                _ => {
+                    output.unknown.insert(key, value);
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut map = self.unknown.clone();
+        if let Some(redeem_script) = &self.redeem_script {
+            map.insert(vec![OUT_REDEEM_SCRIPT], redeem_script.raw_serialize());
+        }
+        if let Some(witness_script) = &self.witness_script {
+            map.insert(vec![OUT_WITNESS_SCRIPT], witness_script.raw_serialize());
+        }
+        serialize_map(&map)
+    }
+}
+
+// Length-prefixed keys and values up to a zero-length key. Keys must
+// not repeat within a map.
+fn read_map<R: Read>(reader: &mut R) -> Result<KeyValueMap, ParseError> {
+    let mut map = BTreeMap::new();
+    loop {
+        let key_len = read_varint(reader)?;
+        if key_len == 0 {
+            return Ok(map);
+        }
+        let key = read_bytes(reader, key_len)?;
+        let value_len = read_varint(reader)?;
+        let value = read_bytes(reader, value_len)?;
+        if map.insert(key, value).is_some() {
+            return Err(ParseError::InvalidFormat("duplicate psbt key"));
+        }
+    }
+}
+
+// Pairs go out sorted by key, which puts the known fields in type order
+fn serialize_map(map: &KeyValueMap) -> Vec<u8> {
+    let mut result = Vec::new();
+    for (key, value) in map {
+        result.extend(encode_varint(key.len() as u64));
+        result.extend_from_slice(key);
+        result.extend(encode_varint(value.len() as u64));
+        result.extend_from_slice(value);
+    }
+    result.push(0);
+    result
+}
+
+// A value has to be used up exactly by what it encodes
+fn parse_complete<T, F>(value: &[u8], parse: F) -> Result<T, ParseError>
+where
+    F: FnOnce(&mut &[u8]) -> Result<T, ParseError>,
+{
+    let mut reader = value;
+    let parsed = parse(&mut reader)?;
+    if !reader.is_empty() {
+        return Err(ParseError::InvalidFormat("psbt value has trailing data"));
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::TxBuilder;
+    use crate::script::{p2pkh_script, p2sh_script};
+    use crate::tx::OutPoint;
+    use crate::u256::U256;
+    use crate::util::decode_hex;
+
+    // Valid test vectors from BIP174
+    const PSBT_HEXES: [&str; 2] = [
+        "70736274ff0100750200000001268171371edff285e937adeea4b37b78000c0566cbb3ad64641713ca42171bf60000000000feffffff02d3dff505000000001976a914d0c59903c5bac2868760e90fd521a4665aa7652088ac00e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787b32e1300000100fda5010100000000010289a3c71eab4d20e0371bbba4cc698fa295c9463afa2e397f8533ccb62f9567e50100000017160014be18d152a9b012039daf3da7de4f53349eecb985ffffffff86f8aa43a71dff1448893a530a7237ef6b4608bbb2dd2d0171e63aec6a4890b40100000017160014fe3e9ef1a745e974d902c4355943abcb34bd5353ffffffff0200c2eb0b000000001976a91485cff1097fd9e008bb34af709c62197b38978a4888ac72fef84e2c00000017a914339725ba21efd62ac753a9bc1f1d1482b1800df9870247304402202712be22e0270f394f568311dc7ca9a68970b8025fdd3b240229f07f8a5f3a240220018b38d7dcd314e734c9276bd6fb40f673325bc4baa144c800d2f2f02db2765c012103d2e15674941bad4a996372cb87e1856d3652606d98562fe39c5e9e7e413f210502483045022100d12b852d85dcd961d2f5f4ab660654df6eedcc794c0c33ce5cc309ffb5fce58d022067338a8e0e1725c197fb1a88af59f51e44e4255b20167c8684031c05d1f2592a01210223b72beef0965d10be0778efecd61fcac6f79a4ea169393380734464f84f2ab300000000000000",
+        "70736274ff01003f0200000001ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff0000000000ffffffff010000000000000000036a0100000000000a0f0102030405060708090f0102030405060708090a0b0c0d0e0f000a0f0102030405060708090f0102030405060708090a0b0c0d0e0f000a0f0102030405060708090f0102030405060708090a0b0c0d0e0f00",
+    ];
+
+    #[test]
+    fn parse_and_serialize_vectors() {
+        for hex in PSBT_HEXES {
+            let raw = decode_hex(hex).unwrap();
+            let psbt = Psbt::parse(&mut &raw[..], false).unwrap();
+            assert_eq!(psbt.serialize(), raw);
+        }
+        let raw = decode_hex(PSBT_HEXES[0]).unwrap();
+        for len in 0..raw.len() {
+            assert!(Psbt::parse(&mut &raw[..len], false).is_err(), "prefix of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn sign_combine_finalize_extract() {
+        let key1 = PrivateKey::new(U256::from_u64(8675309));
+        let key2 = PrivateKey::new(U256::from_u64(12345));
+        let h160 = hash160(&key1.point.sec(true));
+        let p2wpkh = Script::new(vec![Cmd::Op(OpCode::OP_0), Cmd::Data(h160.to_vec())]);
+        let p2sh_p2wpkh = p2sh_script(&hash160(&p2wpkh.raw_serialize()));
+        let p2pkh = p2pkh_script(&hash160(&key2.point.sec(false)));
+        let mut builder = TxBuilder::new(true);
+        for (i, script_pubkey) in [p2wpkh, p2pkh, p2sh_p2wpkh].into_iter().enumerate() {
+            builder.add_input(OutPoint::new([i as u8 + 1; 32], 0), TxOut { amount: 100000, script_pubkey });
+        }
+        builder.add_output(key1.point.address(true, true).as_str(), 50000).fee_rate(2.0);
+        let mut psbt = Psbt::from_unsigned_tx(builder.build().unwrap()).unwrap();
+        assert!(matches!(psbt.sign(&key1), Err(PsbtError::MissingUtxo(0))));
+        for (input, prevout) in psbt.inputs.iter_mut().zip(builder.prevouts()) {
+            input.witness_utxo = Some(prevout);
+        }
+        psbt.inputs[1].unknown.insert(vec![0xfc, 1], vec![9]);
+        let mut other = psbt.clone();
+        assert_eq!(psbt.sign(&key1).unwrap(), 2);
+        assert_eq!(other.sign(&key2).unwrap(), 1);
+        assert_eq!(Psbt::parse(&mut &psbt.serialize()[..], true).unwrap(), psbt);
+        assert!(matches!(psbt.finalize(), Err(PsbtError::CannotFinalize(1))));
+        psbt.combine(other.clone()).unwrap();
+        assert!(matches!(psbt.extract_tx(), Err(PsbtError::NotFinalized(_))));
+        psbt.finalize().unwrap();
+        let parsed = Psbt::parse(&mut &psbt.serialize()[..], true).unwrap();
+        assert_eq!(parsed, psbt);
+        assert_eq!(parsed.inputs[1].unknown.len(), 1);
+        let tx = parsed.extract_tx().unwrap();
+        assert!(tx.verify(&builder.prevouts()).unwrap());
+        assert_eq!(tx, builder.sign(&[key1, key2]).unwrap());
+        other.unsigned_tx.locktime = 5;
+        assert!(matches!(psbt.combine(other), Err(PsbtError::DifferentTx)));
+    }
+}
@@ -0,0 +1,149 @@
+use crate::ecc::{S256Field, S256Point, G, N, P};
+use crate::hashes::tagged_hash;
+use crate::u256::U256;
+
+// The point with this x and an even y, if there is one
+fn lift_x(x: &[u8; 32]) -> Option<S256Point> {
+    let x = U256::from_be_bytes(*x);
+    if x >= P {
+        return None;
+    }
+    let x = S256Field::new(x);
+    let y = (x * x * x + S256Field::new(U256::from_u64(7))).sqrt();
+    let y = if y.num().bit(0) { S256Field::new(P - y.num()) } else { y };
+    // Fails when x^3 + 7 has no square root
+    S256Point::new(x, y).ok()
+}
+
+fn coords(point: &S256Point) -> (U256, bool) {
+    let (x, y) = (point.x().expect("not infinity"), point.y().expect("not infinity"));
+    (x.num(), !y.num().bit(0))
+}
+
+fn challenge(r: &[u8], pubkey_x: &[u8; 32], msg: &[u8]) -> U256 {
+    let mut data = r.to_vec();
+    data.extend_from_slice(pubkey_x);
+    data.extend_from_slice(msg);
+    U256::from_be_bytes(tagged_hash("BIP0340/challenge", &data)) % N
+}
+
+// The x-only public key for a secret, which is the same for secret and
+// N - secret
+pub fn public_key(secret: U256) -> [u8; 32] {
+    coords(&(G * secret)).0.to_be_bytes()
+}
+
+// BIP340 signing. secret must be between 1 and N - 1, and aux_rand is
+// fresh randomness mixed into the nonce, though all zeros is still safe.
+pub fn sign(secret: U256, msg: &[u8], aux_rand: &[u8; 32]) -> [u8; 64] {
+    assert!(!secret.is_zero() && secret < N, "secret out of range");
+    let (pubkey_x, even) = coords(&(G * secret));
+    // Sign with whichever of secret and N - secret has the even-y key
+    let d = if even { secret } else { N - secret };
+    let pubkey_x = pubkey_x.to_be_bytes();
+    let mask = tagged_hash("BIP0340/aux", aux_rand);
+    let mut data: Vec<u8> = d.to_be_bytes().iter().zip(mask).map(|(a, b)| a ^ b).collect();
+    data.extend_from_slice(&pubkey_x);
+    data.extend_from_slice(msg);
+    let k = U256::from_be_bytes(tagged_hash("BIP0340/nonce", &data)) % N;
+    assert!(!k.is_zero(), "nonce is zero");
+    // Likewise the nonce is negated if R has an odd y
+    let (r, even) = coords(&(G * k));
+    let k = if even { k } else { N - k };
+    let r = r.to_be_bytes();
+    let e = challenge(&r, &pubkey_x, msg);
+    let s = k.add_mod(e.mul_mod(d, N), N);
+    let mut sig = [0u8; 64];
+    sig[..32].copy_from_slice(&r);
+    sig[32..].copy_from_slice(&s.to_be_bytes());
+    sig
+}
+
+// s*G - e*P must be a point with an even y and x equal to r
+pub fn verify(pubkey_x: &[u8; 32], msg: &[u8], sig: &[u8; 64]) -> bool {
+    let Some(point) = lift_x(pubkey_x) else {
+        return false;
+    };
+    let r = U256::from_be_slice(&sig[..32]).expect("32 bytes");
+    let s = U256::from_be_slice(&sig[32..]).expect("32 bytes");
+    if r >= P || s >= N {
+        return false;
+    }
+    let e = challenge(&sig[..32], pubkey_x, msg);
+    let total = S256Point::multi_scalar_mul(&[(s, G), (U256::ZERO.sub_mod(e, N), point)]);
+    if total.is_infinity() {
+        return false;
+    }
+    let (x, even) = coords(&total);
+    even && x == r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::decode_hex;
+
+    fn bytes<const LEN: usize>(hex: &str) -> [u8; LEN] {
+        decode_hex(&hex.to_lowercase()).unwrap().try_into().unwrap()
+    }
+
+    const PUBKEY: &str = "DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659";
+    const MSG: &str = "243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89";
+    const SIG: &str = "6896BD60EEAE296DB48A229FF71DFE071BDE413E6D43F917DC8DCF8C78DE33418906D11AC976ABCCB20B091292BFF4EA897EFCB639EA871CFA95F6DE339E4B0A";
+
+    // Signing vectors from BIP340, the last with an empty message
+    #[test]
+    fn sign_vectors() {
+        for (secret, pubkey, aux_rand, msg, sig) in [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000003",
+                "F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA821525F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0",
+            ),
+            (
+                "B7E151628AED2A6ABF7158809CF4F3C762E7160F38B4DA56A784D9045190CFEF",
+                PUBKEY,
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                MSG,
+                SIG,
+            ),
+            (
+                "0340034003400340034003400340034003400340034003400340034003400340",
+                "778CAA53B4393AC467774D09497A87224BF9FAB6F6E68B23086497324D6FD117",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "",
+                "71535DB165ECD9FBBC046E5FFAEA61186BB6AD436732FCCC25291A55895464CF6069CE26BF03466228F19A3A62DB8A649F2D560FAC652827D1AF0574E427AB63",
+            ),
+        ] {
+            let secret = U256::from_be_bytes(bytes(secret));
+            let msg = decode_hex(&msg.to_lowercase()).unwrap();
+            assert_eq!(public_key(secret), bytes(pubkey));
+            assert_eq!(sign(secret, &msg, &bytes(aux_rand)), bytes(sig));
+            assert!(verify(&bytes(pubkey), &msg, &bytes(sig)));
+        }
+    }
+
+    #[test]
+    fn verify_rejects() {
+        let (pubkey, sig) = (bytes(PUBKEY), bytes(SIG));
+        let msg = decode_hex(&MSG.to_lowercase()).unwrap();
+        let mut tampered_msg = msg.clone();
+        tampered_msg[31] ^= 3;
+        assert!(!verify(&pubkey, &tampered_msg, &sig));
+        // s equal to the group order, r equal to the field prime
+        let mut s_too_big = sig;
+        s_too_big[32..].copy_from_slice(&N.to_be_bytes());
+        assert!(!verify(&pubkey, &msg, &s_too_big));
+        let mut r_too_big = sig;
+        r_too_big[..32].copy_from_slice(&P.to_be_bytes());
+        assert!(!verify(&pubkey, &msg, &r_too_big));
+        // A key off the curve, and one at the field prime
+        assert!(!verify(&bytes("EEFDEA4CDB677750A420FEE807EACF21EB9898AE79B9768766E4FAA04A2D4A34"), &msg, &sig));
+        assert!(!verify(&P.to_be_bytes(), &msg, &sig));
+        // R with an odd y
+        let wrong_sig = "FFF97BD5755EEEA420453A14355235D382F6472F8568A18B2F057A14602975563CC27944640AC607CD107AE10923D9EF7A73C643E166BE5EBEAFA34B1AC553E2";
+        assert!(!verify(&pubkey, &msg, &bytes(wrong_sig)));
+    }
+}
@@ -0,0 +1,985 @@
+use std::fmt;
+use std::io::Read;
+use std::ops::Add;
+
+use crate::bech32::{decode_segwit_address, encode_segwit_address};
+use crate::ecc::S256Point;
+use crate::error::ParseError;
+use crate::op::{
+    cast_to_bool, encode_num, op_0, op_add, op_checklocktimeverify, op_checkmultisig, op_checkmultisigverify,
+    op_checksequenceverify, op_checksig, op_checksigverify, op_drop, op_dup, op_equal, op_equalverify, op_fromaltstack,
+    op_hash160, op_hash256, op_not, op_num, op_sha256, op_sub, op_swap, op_toaltstack, op_verify, ExecutionContext,
+    OpCode, SigHasher, Stack,
+};
+use crate::util::{
+    decode_base58_checksum, decode_hex, encode_hex, encode_varint, h160_to_p2pkh_address, h160_to_p2sh_address,
+    read_bytes, read_varint,
+};
+
+// Consensus limit on a single pushed element
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+// Standardness limit on an OP_RETURN payload
+pub const MAX_OP_RETURN_SIZE: usize = 80;
+
+#[derive(Debug)]
+pub enum ScriptError {
+    PushTooLarge(usize),
+    InvalidAddress(String),
+    WrongNetwork(String),
+    InvalidMultisig { m: usize, n: usize },
+    InvalidAsm(String),
+    DataTooLarge(usize),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::PushTooLarge(len) => {
+                write!(f, "Push of {} bytes exceeds {} bytes", len, MAX_SCRIPT_ELEMENT_SIZE)
+            }
+            ScriptError::InvalidAddress(address) => write!(f, "Invalid address {}", address),
+            ScriptError::WrongNetwork(address) => write!(f, "Address {} is for the other network", address),
+            ScriptError::InvalidMultisig { m, n } => write!(f, "Cannot require {} of {} keys", m, n),
+            ScriptError::InvalidAsm(token) => write!(f, "Cannot parse {:?} as an opcode, number or hex push", token),
+            ScriptError::DataTooLarge(len) => {
+                write!(f, "OP_RETURN data of {} bytes exceeds {} bytes", len, MAX_OP_RETURN_SIZE)
+            }
+        }
+    }
+}
+
+// The standard output templates
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ScriptType {
+    P2pk,
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    OpReturn,
+    Multisig { m: usize, n: usize },
+    NonStandard,
+}
+
+impl fmt::Display for ScriptType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptType::P2pk => write!(f, "p2pk"),
+            ScriptType::P2pkh => write!(f, "p2pkh"),
+            ScriptType::P2sh => write!(f, "p2sh"),
+            ScriptType::P2wpkh => write!(f, "p2wpkh"),
+            ScriptType::P2wsh => write!(f, "p2wsh"),
+            ScriptType::P2tr => write!(f, "p2tr"),
+            ScriptType::OpReturn => write!(f, "op_return"),
+            ScriptType::Multisig { m, n } => write!(f, "multisig({} of {})", m, n),
+            ScriptType::NonStandard => write!(f, "nonstandard"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Cmd {
+    Op(OpCode),
+    Data(Vec<u8>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Script {
+    pub cmds: Vec<Cmd>,
+}
+
+impl Script {
+    pub fn new(cmds: Vec<Cmd>) -> Script {
+        Script { cmds }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cmds.is_empty()
+    }
+
+    // OP_RETURN <data>, or a bare OP_RETURN for no data. Payloads over 80
+    // bytes are non-standard and only allowed with allow_nonstandard.
+    pub fn op_return(data: &[u8], allow_nonstandard: bool) -> Result<Script, ScriptError> {
+        if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+            return Err(ScriptError::PushTooLarge(data.len()));
+        }
+        if data.len() > MAX_OP_RETURN_SIZE && !allow_nonstandard {
+            return Err(ScriptError::DataTooLarge(data.len()));
+        }
+        let mut cmds = vec![Cmd::Op(OpCode::OP_RETURN)];
+        if !data.is_empty() {
+            cmds.push(Cmd::Data(data.to_vec()));
+        }
+        Ok(Script { cmds })
+    }
+
+    // The inverse of Display. Also takes names without the OP_ prefix,
+    // OP_FALSE and OP_TRUE, and decimal numbers, which become OP_1NEGATE
+    // to OP_16 or else a minimal push. An even-length hex token is always
+    // a push, so "10" is a byte rather than OP_10.
+    pub fn from_asm(asm: &str) -> Result<Script, ScriptError> {
+        let mut cmds = Vec::new();
+        for token in asm.split_whitespace() {
+            let op = match token {
+                "OP_FALSE" | "FALSE" => Some(OpCode::OP_0),
+                "OP_TRUE" | "TRUE" => Some(OpCode::OP_1),
+                _ => OpCode::from_name(token).or_else(|| OpCode::from_name(&format!("OP_{}", token))),
+            };
+            let cmd = if let Some(op) = op {
+                // A bare PUSHDATA would swallow whatever follows it
+                if matches!(op, OpCode::OP_PUSHDATA1 | OpCode::OP_PUSHDATA2 | OpCode::OP_PUSHDATA4) {
+                    return Err(ScriptError::InvalidAsm(token.to_string()));
+                }
+                Cmd::Op(op)
+            } else if let Some(byte) = token.strip_prefix("0x") {
+                // A raw opcode byte; push lengths would change how the rest parses
+                match u8::from_str_radix(byte, 16) {
+                    Ok(byte @ 0x4f..) => Cmd::Op(OpCode::from_byte(byte)),
+                    _ => return Err(ScriptError::InvalidAsm(token.to_string())),
+                }
+            } else if let (true, Ok(data)) = (token.len().is_multiple_of(2), decode_hex(token)) {
+                Cmd::Data(data)
+            } else if let Ok(num) = token.parse::<i64>() {
+                num_cmd(num)
+            } else {
+                return Err(ScriptError::InvalidAsm(token.to_string()));
+            };
+            cmds.push(cmd);
+        }
+        Ok(Script { cmds })
+    }
+
+    // Reads the varint length prefix and then exactly that many bytes,
+    // so a push running past the end of the script is an error rather
+    // than a read into whatever follows it
+    pub fn parse<R: Read>(reader: &mut R) -> Result<Script, ParseError> {
+        let length = read_varint(reader)?;
+        let raw = read_bytes(reader, length)?;
+        Script::parse_raw(&raw)
+    }
+
+    // A push or push length running past the end of raw is reported as
+    // malformed, since the script's own length said it would fit
+    pub fn parse_raw(raw: &[u8]) -> Result<Script, ParseError> {
+        const TRUNCATED_PUSH: ParseError = ParseError::InvalidFormat("push runs past the end of the script");
+        let mut reader = raw;
+        let mut cmds = Vec::new();
+        while let [current, rest @ ..] = reader {
+            reader = rest;
+            let length_bytes = match current {
+                0x4c => 1,
+                0x4d => 2,
+                0x4e => 4,
+                _ => 0,
+            };
+            let push_len = match current {
+                len @ 0x01..=0x4b => Some(*len as usize),
+                0x4c..=0x4e => {
+                    if reader.len() < length_bytes {
+                        return Err(TRUNCATED_PUSH);
+                    }
+                    let (len, rest) = reader.split_at(length_bytes);
+                    reader = rest;
+                    let mut le = [0u8; 4];
+                    le[..length_bytes].copy_from_slice(len);
+                    Some(u32::from_le_bytes(le) as usize)
+                }
+                _ => None,
+            };
+            match push_len {
+                Some(len) if len > reader.len() => return Err(TRUNCATED_PUSH),
+                Some(len) => {
+                    let (data, rest) = reader.split_at(len);
+                    reader = rest;
+                    cmds.push(Cmd::Data(data.to_vec()));
+                }
+                None => cmds.push(Cmd::Op(OpCode::from_byte(*current))),
+            }
+        }
+        Ok(Script { cmds })
+    }
+
+    // Serialization without the length prefix
+    pub fn raw_serialize(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        for cmd in &self.cmds {
+            match cmd {
+                Cmd::Op(op) => result.push(op.to_byte()),
+                Cmd::Data(data) => {
+                    let len = data.len();
+                    if len <= 0x4b {
+                        result.push(len as u8);
+                    } else if len <= 0xff {
+                        result.push(OpCode::OP_PUSHDATA1.to_byte());
+                        result.push(len as u8);
+                    } else if len <= 0xffff {
+                        result.push(OpCode::OP_PUSHDATA2.to_byte());
+                        result.extend_from_slice(&(len as u16).to_le_bytes());
+                    } else {
+                        result.push(OpCode::OP_PUSHDATA4.to_byte());
+                        result.extend_from_slice(&(len as u32).to_le_bytes());
+                    }
+                    result.extend_from_slice(data);
+                }
+            }
+        }
+        result
+    }
+
+    // Scripts already on chain may carry oversized pushes in outputs that
+    // can never be spent, so serialize still writes those and evaluate
+    // rejects them. This is for scripts being built.
+    pub fn try_serialize(&self) -> Result<Vec<u8>, ScriptError> {
+        for cmd in &self.cmds {
+            if let Cmd::Data(data) = cmd {
+                if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(ScriptError::PushTooLarge(data.len()));
+                }
+            }
+        }
+        Ok(self.serialize())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let raw = self.raw_serialize();
+        let mut result = encode_varint(raw.len() as u64);
+        result.extend(raw);
+        result
+    }
+
+    // OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG
+    pub fn is_p2pkh_script_pubkey(&self) -> bool {
+        matches!(
+            self.cmds.as_slice(),
+            [
+                Cmd::Op(OpCode::OP_DUP),
+                Cmd::Op(OpCode::OP_HASH160),
+                Cmd::Data(h160),
+                Cmd::Op(OpCode::OP_EQUALVERIFY),
+                Cmd::Op(OpCode::OP_CHECKSIG),
+            ] if h160.len() == 20
+        )
+    }
+
+    // OP_HASH160 <20 bytes> OP_EQUAL
+    pub fn is_p2sh_script_pubkey(&self) -> bool {
+        matches!(
+            self.cmds.as_slice(),
+            [Cmd::Op(OpCode::OP_HASH160), Cmd::Data(h160), Cmd::Op(OpCode::OP_EQUAL)] if h160.len() == 20
+        )
+    }
+
+    // OP_0 <20 bytes>, a version 0 witness program paying to a key hash
+    pub fn is_p2wpkh_script_pubkey(&self) -> bool {
+        matches!(
+            self.cmds.as_slice(),
+            [Cmd::Op(OpCode::OP_0), Cmd::Data(h160)] if h160.len() == 20
+        )
+    }
+
+    // OP_0 <32 bytes>, a version 0 witness program paying to a script hash
+    pub fn is_p2wsh_script_pubkey(&self) -> bool {
+        matches!(
+            self.cmds.as_slice(),
+            [Cmd::Op(OpCode::OP_0), Cmd::Data(h256)] if h256.len() == 32
+        )
+    }
+
+    // OP_RETURN outputs are provably unspendable, so nodes can drop them
+    // from the UTXO set
+    pub fn is_op_return(&self) -> bool {
+        matches!(self.cmds.first(), Some(Cmd::Op(OpCode::OP_RETURN)))
+    }
+
+    // The payload of OP_RETURN or OP_RETURN <data>. Parsing has already
+    // turned OP_PUSHDATA1 pushes into data, and an empty push is OP_0.
+    pub fn op_return_data(&self) -> Option<Vec<u8>> {
+        match self.cmds.as_slice() {
+            [Cmd::Op(OpCode::OP_RETURN)] | [Cmd::Op(OpCode::OP_RETURN), Cmd::Op(OpCode::OP_0)] => Some(Vec::new()),
+            [Cmd::Op(OpCode::OP_RETURN), Cmd::Data(data)] => Some(data.clone()),
+            _ => None,
+        }
+    }
+
+    // Matches the cmds against each template. Pubkeys must look like SEC
+    // encodings but are not checked to be on the curve.
+    pub fn script_type(&self) -> ScriptType {
+        let is_pubkey = |cmd: &Cmd| match cmd {
+            Cmd::Data(sec) => matches!((sec.len(), sec.first()), (33, Some(0x02 | 0x03)) | (65, Some(0x04))),
+            Cmd::Op(_) => false,
+        };
+        // Opcodes up to OP_16 only push
+        let is_push = |cmd: &Cmd| match cmd {
+            Cmd::Data(_) => true,
+            Cmd::Op(op) => op.to_byte() <= 0x60,
+        };
+        match self.cmds.as_slice() {
+            _ if self.is_p2pkh_script_pubkey() => ScriptType::P2pkh,
+            _ if self.is_p2sh_script_pubkey() => ScriptType::P2sh,
+            _ if self.is_p2wpkh_script_pubkey() => ScriptType::P2wpkh,
+            _ if self.is_p2wsh_script_pubkey() => ScriptType::P2wsh,
+            [Cmd::Op(OpCode::OP_1), Cmd::Data(x_only)] if x_only.len() == 32 => ScriptType::P2tr,
+            [sec, Cmd::Op(OpCode::OP_CHECKSIG)] if is_pubkey(sec) => ScriptType::P2pk,
+            [Cmd::Op(OpCode::OP_RETURN), rest @ ..] if rest.iter().all(is_push) => ScriptType::OpReturn,
+            [m, pubkeys @ .., n, Cmd::Op(OpCode::OP_CHECKMULTISIG)] => match (small_num(m), small_num(n)) {
+                (Some(m), Some(n)) if m <= n && n == pubkeys.len() && pubkeys.iter().all(is_pubkey) => {
+                    ScriptType::Multisig { m, n }
+                }
+                _ => ScriptType::NonStandard,
+            },
+            _ => ScriptType::NonStandard,
+        }
+    }
+
+    // Whether nodes relay outputs with this script_pubkey by default,
+    // which also caps OP_RETURN payloads and bare multisig at 3 keys
+    pub fn is_standard(&self) -> bool {
+        match self.script_type() {
+            ScriptType::NonStandard => false,
+            // OP_RETURN, and a push of up to 80 bytes behind OP_PUSHDATA1
+            ScriptType::OpReturn => self.raw_serialize().len() <= MAX_OP_RETURN_SIZE + 3,
+            ScriptType::Multisig { n, .. } => n <= 3,
+            _ => true,
+        }
+    }
+
+    // Version and program of a witness output, OP_0 to OP_16 followed by a
+    // 2 to 40 byte push, 20 or 32 bytes for version 0
+    fn witness_program(&self) -> Option<(u8, &[u8])> {
+        let [Cmd::Op(op), Cmd::Data(program)] = self.cmds.as_slice() else {
+            return None;
+        };
+        let version = match op.to_byte() {
+            0x00 => 0,
+            n @ 0x51..=0x60 => n - 0x50,
+            _ => return None,
+        };
+        let valid = match version {
+            0 => program.len() == 20 || program.len() == 32,
+            _ => (2..=40).contains(&program.len()),
+        };
+        valid.then_some((version, program.as_slice()))
+    }
+
+    // The address paying to this script_pubkey, for p2pkh, p2sh and
+    // witness outputs
+    pub fn address(&self, testnet: bool) -> Option<String> {
+        if self.is_p2pkh_script_pubkey() {
+            Some(h160_to_p2pkh_address(data_h160(&self.cmds[2])?, testnet))
+        } else if self.is_p2sh_script_pubkey() {
+            Some(h160_to_p2sh_address(data_h160(&self.cmds[1])?, testnet))
+        } else {
+            let (version, program) = self.witness_program()?;
+            Some(encode_segwit_address(version, program, testnet))
+        }
+    }
+
+    // sig_hash gives the digest for each signature checked along the way,
+    // context the transaction fields checked by the timelock opcodes
+    pub fn evaluate(&self, sig_hash: &SigHasher, context: &ExecutionContext) -> bool {
+        match self.run(Vec::new(), sig_hash, context) {
+            Some(stack) => stack.last().is_some_and(|top| cast_to_bool(top)),
+            None => false,
+        }
+    }
+
+    // Runs the script on top of elements already on the stack, as witness
+    // scripts are. Those elements are never taken for a p2sh redeem script.
+    // BIP141 also requires a clean stack: exactly one true element left.
+    pub fn evaluate_with_stack(&self, stack: Stack, sig_hash: &SigHasher, context: &ExecutionContext) -> bool {
+        match self.run(stack, sig_hash, context) {
+            Some(stack) => matches!(stack.as_slice(), [top] if cast_to_bool(top)),
+            None => false,
+        }
+    }
+
+    // The stack left once every command has run, None if one failed
+    fn run(&self, mut stack: Stack, sig_hash: &SigHasher, context: &ExecutionContext) -> Option<Stack> {
+        if has_disabled(&self.cmds) {
+            return None;
+        }
+        // Commands are consumed from the back, so keep them reversed
+        let mut cmds: Vec<Cmd> = self.cmds.iter().rev().cloned().collect();
+        let mut altstack: Stack = Vec::new();
+        while let Some(cmd) = cmds.pop() {
+            match cmd {
+                Cmd::Op(op @ (OpCode::OP_IF | OpCode::OP_NOTIF)) => {
+                    let top = stack.pop()?;
+                    if !take_branch(&mut cmds, cast_to_bool(&top) == (op == OpCode::OP_IF)) {
+                        return None;
+                    }
+                }
+                Cmd::Op(op) => {
+                    if !execute(op, &mut stack, &mut altstack, sig_hash, context) {
+                        return None;
+                    }
+                }
+                Cmd::Data(element) if element.len() > MAX_SCRIPT_ELEMENT_SIZE => return None,
+                Cmd::Data(element) if is_p2sh_tail(&cmds) => {
+                    // BIP16: the last push of the script_sig is the redeem
+                    // script, which must hash to the committed h160 and is
+                    // then run against the rest of the stack
+                    stack.push(element.clone());
+                    cmds.pop();
+                    op_hash160(&mut stack);
+                    if let Some(Cmd::Data(h160)) = cmds.pop() {
+                        stack.push(h160);
+                    }
+                    cmds.pop();
+                    if !op_equalverify(&mut stack) {
+                        return None;
+                    }
+                    match Script::parse_raw(&element) {
+                        Ok(redeem_script) if !has_disabled(&redeem_script.cmds) => {
+                            cmds.extend(redeem_script.cmds.into_iter().rev())
+                        }
+                        _ => return None,
+                    }
+                }
+                Cmd::Data(element) => stack.push(element),
+            }
+        }
+        Some(stack)
+    }
+}
+
+// OP_DUP OP_HASH160 <h160> OP_EQUALVERIFY OP_CHECKSIG
+pub fn p2pkh_script(h160: &[u8; 20]) -> Script {
+    Script::new(vec![
+        Cmd::Op(OpCode::OP_DUP),
+        Cmd::Op(OpCode::OP_HASH160),
+        Cmd::Data(h160.to_vec()),
+        Cmd::Op(OpCode::OP_EQUALVERIFY),
+        Cmd::Op(OpCode::OP_CHECKSIG),
+    ])
+}
+
+// OP_HASH160 <h160> OP_EQUAL, where h160 is the hash160 of the redeem script
+pub fn p2sh_script(h160: &[u8; 20]) -> Script {
+    Script::new(vec![Cmd::Op(OpCode::OP_HASH160), Cmd::Data(h160.to_vec()), Cmd::Op(OpCode::OP_EQUAL)])
+}
+
+// OP_m <pubkeys> OP_n OP_CHECKMULTISIG with compressed SECs. m signatures
+// must then be given in the same order as their keys.
+pub fn multisig_script(m: usize, pubkeys: &[S256Point]) -> Result<Script, ScriptError> {
+    let n = pubkeys.len();
+    if m == 0 || m > n || n > MAX_PUBKEYS_PER_MULTISIG {
+        return Err(ScriptError::InvalidMultisig { m, n });
+    }
+    let mut cmds = vec![num_cmd(m as i64)];
+    cmds.extend(pubkeys.iter().map(|pubkey| Cmd::Data(pubkey.sec(true))));
+    cmds.push(num_cmd(n as i64));
+    cmds.push(Cmd::Op(OpCode::OP_CHECKMULTISIG));
+    Ok(Script::new(cmds))
+}
+
+// OP_0, OP_1NEGATE and OP_1 to OP_16, or a minimal push for anything else
+fn num_cmd(num: i64) -> Cmd {
+    match num {
+        0 => Cmd::Op(OpCode::OP_0),
+        -1 => Cmd::Op(OpCode::OP_1NEGATE),
+        1..=16 => Cmd::Op(OpCode::from_byte(0x50 + num as u8)),
+        _ => Cmd::Data(encode_num(num)),
+    }
+}
+
+// The value of OP_1 to OP_16
+fn small_num(cmd: &Cmd) -> Option<usize> {
+    match cmd {
+        Cmd::Op(op) => match op.to_byte() {
+            n @ 0x51..=0x60 => Some((n - 0x50) as usize),
+            _ => None,
+        },
+        Cmd::Data(_) => None,
+    }
+}
+
+fn data_h160(cmd: &Cmd) -> Option<&[u8; 20]> {
+    match cmd {
+        Cmd::Data(data) => data.as_slice().try_into().ok(),
+        Cmd::Op(_) => None,
+    }
+}
+
+// Base58 p2pkh and p2sh addresses and bech32 witness addresses, checked
+// against the network
+pub fn address_to_script_pubkey(address: &str, testnet: bool) -> Result<Script, ScriptError> {
+    let invalid = || ScriptError::InvalidAddress(address.to_string());
+    let (address_testnet, script_pubkey) = match decode_base58_checksum(address) {
+        Ok(payload) => {
+            let [version, h160 @ ..] = payload.as_slice() else {
+                return Err(invalid());
+            };
+            let h160: &[u8; 20] = h160.try_into().map_err(|_| invalid())?;
+            match version {
+                0x00 | 0x6f => (*version == 0x6f, p2pkh_script(h160)),
+                0x05 | 0xc4 => (*version == 0xc4, p2sh_script(h160)),
+                _ => return Err(invalid()),
+            }
+        }
+        Err(_) => {
+            let (address_testnet, version, program) = decode_segwit_address(address).map_err(|_| invalid())?;
+            let version_op = if version == 0 { OpCode::OP_0 } else { OpCode::from_byte(0x50 + version) };
+            (address_testnet, Script::new(vec![Cmd::Op(version_op), Cmd::Data(program)]))
+        }
+    };
+    if address_testnet != testnet {
+        return Err(ScriptError::WrongNetwork(address.to_string()));
+    }
+    Ok(script_pubkey)
+}
+
+// asm: opcodes by name and pushes as hex, separated by spaces. Opcode
+// bytes with no name are written as 0x followed by the byte.
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, cmd) in self.cmds.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            match cmd {
+                Cmd::Op(op) => match op.name() {
+                    Some(name) => write!(f, "{}", name)?,
+                    None => write!(f, "0x{:02x}", op.to_byte())?,
+                },
+                // An empty push is the same byte as OP_0
+                Cmd::Data(data) if data.is_empty() => write!(f, "OP_0")?,
+                Cmd::Data(data) => write!(f, "{}", encode_hex(data))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// script_sig + script_pubkey is the combined script that verification runs
+impl Add for Script {
+    type Output = Script;
+
+    fn add(mut self, other: Script) -> Script {
+        self.cmds.extend(other.cmds);
+        self
+    }
+}
+
+// The remaining (reversed) commands are exactly OP_HASH160 <20 bytes> OP_EQUAL
+fn is_p2sh_tail(cmds: &[Cmd]) -> bool {
+    match cmds {
+        [Cmd::Op(OpCode::OP_EQUAL), Cmd::Data(h160), Cmd::Op(OpCode::OP_HASH160)] => h160.len() == 20,
+        _ => false,
+    }
+}
+
+fn has_disabled(cmds: &[Cmd]) -> bool {
+    cmds.iter().any(|cmd| matches!(cmd, Cmd::Op(op) if op.is_disabled()))
+}
+
+// Called just after OP_IF or OP_NOTIF with the (reversed) commands that
+// follow it. Drops the branch not taken along with the matching OP_ENDIF,
+// each OP_ELSE switching branches. Fails if there is no OP_ENDIF.
+fn take_branch(cmds: &mut Vec<Cmd>, condition: bool) -> bool {
+    let mut executing = condition;
+    let mut depth = 0;
+    let mut kept = Vec::new();
+    loop {
+        let Some(cmd) = cmds.pop() else {
+            return false;
+        };
+        match cmd {
+            Cmd::Op(OpCode::OP_ENDIF) if depth == 0 => break,
+            Cmd::Op(OpCode::OP_ELSE) if depth == 0 => {
+                executing = !executing;
+                continue;
+            }
+            Cmd::Op(OpCode::OP_IF | OpCode::OP_NOTIF) => depth += 1,
+            Cmd::Op(OpCode::OP_ENDIF) => depth -= 1,
+            _ => {}
+        }
+        if executing {
+            kept.push(cmd);
+        }
+    }
+    cmds.extend(kept.into_iter().rev());
+    true
+}
+
+// OP_ELSE and OP_ENDIF only get here when there is no open OP_IF, so they
+// fail like any other opcode that isn't implemented
+fn execute(
+    op: OpCode,
+    stack: &mut Stack,
+    altstack: &mut Stack,
+    sig_hash: &SigHasher,
+    context: &ExecutionContext,
+) -> bool {
+    match op {
+        OpCode::OP_0 => op_0(stack),
+        OpCode::OP_1NEGATE => op_num(stack, -1),
+        OpCode::OP_DUP => op_dup(stack),
+        OpCode::OP_DROP => op_drop(stack),
+        OpCode::OP_SWAP => op_swap(stack),
+        OpCode::OP_EQUAL => op_equal(stack),
+        OpCode::OP_EQUALVERIFY => op_equalverify(stack),
+        OpCode::OP_VERIFY => op_verify(stack),
+        // Provably unspendable: any script reaching it fails
+        OpCode::OP_RETURN => false,
+        OpCode::OP_TOALTSTACK => op_toaltstack(stack, altstack),
+        OpCode::OP_FROMALTSTACK => op_fromaltstack(stack, altstack),
+        OpCode::OP_ADD => op_add(stack),
+        OpCode::OP_SUB => op_sub(stack),
+        OpCode::OP_NOT => op_not(stack),
+        OpCode::OP_SHA256 => op_sha256(stack),
+        OpCode::OP_HASH160 => op_hash160(stack),
+        OpCode::OP_HASH256 => op_hash256(stack),
+        OpCode::OP_CHECKSIG => op_checksig(stack, sig_hash),
+        OpCode::OP_CHECKSIGVERIFY => op_checksigverify(stack, sig_hash),
+        OpCode::OP_CHECKMULTISIG => op_checkmultisig(stack, sig_hash),
+        OpCode::OP_CHECKMULTISIGVERIFY => op_checkmultisigverify(stack, sig_hash),
+        OpCode::OP_CHECKLOCKTIMEVERIFY => op_checklocktimeverify(stack, context),
+        OpCode::OP_CHECKSEQUENCEVERIFY => op_checksequenceverify(stack, context),
+        _ => match op.to_byte() {
+            n @ 0x51..=0x60 => op_num(stack, (n - 0x50) as i64),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::u256::U256;
+
+    #[test]
+    fn parse_and_serialize_p2pkh() {
+        let raw = decode_hex("1976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac").unwrap();
+        let script = Script::parse(&mut &raw[..]).unwrap();
+        assert_eq!(script.cmds.len(), 5);
+        assert_eq!(script.cmds[0], Cmd::Op(OpCode::OP_DUP));
+        assert_eq!(script.cmds[2], Cmd::Data(decode_hex("bc3b654dca7e56b04dca18f2566cdaf02e8d9ada").unwrap()));
+        assert_eq!(script.cmds[4], Cmd::Op(OpCode::OP_CHECKSIG));
+        assert_eq!(script.serialize(), raw);
+    }
+
+    #[test]
+    fn pushdata_round_trip() {
+        let script = Script::new(vec![Cmd::Data(vec![1; 76]), Cmd::Data(vec![2; 300])]);
+        assert_eq!(Script::parse(&mut &script.serialize()[..]).unwrap(), script);
+        assert!(Script::parse(&mut &decode_hex("034c05aa").unwrap()[..]).is_err());
+    }
+
+    #[test]
+    fn p2sh_redeem_script() {
+        // OP_1 as the redeem script always succeeds once its hash matches
+        let redeem_script = Script::new(vec![Cmd::Op(OpCode::OP_1)]).raw_serialize();
+        let script_pubkey = vec![
+            Cmd::Op(OpCode::OP_HASH160),
+            Cmd::Data(crate::hashes::hash160(&redeem_script).to_vec()),
+            Cmd::Op(OpCode::OP_EQUAL),
+        ];
+        let mut cmds = vec![Cmd::Data(redeem_script)];
+        cmds.extend(script_pubkey.clone());
+        assert!(Script::new(cmds).evaluate(&|_| None, &ExecutionContext::default()));
+        let mut cmds = vec![Cmd::Data(vec![0x00])];
+        cmds.extend(script_pubkey);
+        assert!(!Script::new(cmds).evaluate(&|_| None, &ExecutionContext::default()));
+    }
+
+    // A p2pkh script_sig: a signature then a compressed SEC key
+    const SCRIPT_SIG_HEX: &str = "6a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937";
+
+    #[test]
+    fn parse_script_sig() {
+        let raw = decode_hex(SCRIPT_SIG_HEX).unwrap();
+        let script = Script::parse(&mut &raw[..]).unwrap();
+        let lengths: Vec<usize> = script.cmds.iter().map(|cmd| match cmd { Cmd::Data(data) => data.len(), _ => 0 }).collect();
+        assert_eq!(lengths, vec![71, 33]);
+        assert_eq!(script.serialize(), raw);
+    }
+
+    #[test]
+    fn parse_pushdata_boundaries() {
+        assert_eq!(Script::parse_raw(&decode_hex("4c00").unwrap()).unwrap().cmds, vec![Cmd::Data(vec![])]);
+        let raw = [&[0x4c, 0x50][..], &[7; 0x50]].concat();
+        assert_eq!(Script::parse_raw(&raw).unwrap().cmds, vec![Cmd::Data(vec![7; 0x50])]);
+        let raw = [&[0x4d, 0x00, 0x01][..], &[7; 256]].concat();
+        assert_eq!(Script::parse_raw(&raw).unwrap().cmds, vec![Cmd::Data(vec![7; 256])]);
+    }
+
+    #[test]
+    fn parse_rejects_overrunning_pushes() {
+        for hex in ["4c", "4d01", "4e010000", "4c05aabb", "05aabb", "4d0500aa"] {
+            let raw = decode_hex(hex).unwrap();
+            assert!(matches!(Script::parse_raw(&raw), Err(ParseError::InvalidFormat(_))), "{}", hex);
+        }
+        assert!(matches!(Script::parse(&mut &decode_hex("056a").unwrap()[..]), Err(ParseError::UnexpectedEof)));
+        assert!(matches!(Script::parse(&mut &decode_hex("0205aa").unwrap()[..]), Err(ParseError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn serialize_round_trips() {
+        for hex in [
+            "1976a914a802fc56c704ce87c42d7c92eb75e7896bdc41ae88ac",
+            "17a91474d691da1574e6b3c192ecfb52cc8984ee7b6c5687",
+            "47522102e577d441d501cace792c02bfe2cc15e59672199e2195770a61fd3288fc9f934f2102ac58f7f3b5cc8125e2ec8a72d8ef8db3fcf0979c35a1b2b5b08b1f1fee1d06ec52ae",
+            "0e6a0c68656c6c6f20776f726c6421",
+            SCRIPT_SIG_HEX,
+        ] {
+            let raw = decode_hex(hex).unwrap();
+            let script = Script::parse(&mut &raw[..]).unwrap();
+            assert_eq!(script.serialize(), raw);
+            assert_eq!(script.try_serialize().unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn minimal_push_opcodes() {
+        for (len, header) in [(75, 1), (76, 2), (255, 2), (256, 3), (520, 3)] {
+            let script = Script::new(vec![Cmd::Data(vec![1; len])]);
+            let raw = script.raw_serialize();
+            assert_eq!(raw.len(), len + header);
+            assert_eq!(Script::parse_raw(&raw).unwrap(), script);
+        }
+    }
+
+    #[test]
+    fn oversized_push() {
+        let too_big = Script::new(vec![Cmd::Data(vec![1; 521])]);
+        assert!(matches!(too_big.try_serialize(), Err(ScriptError::PushTooLarge(521))));
+        assert!(!too_big.evaluate(&|_| None, &ExecutionContext::default()));
+        assert!(Script::new(vec![Cmd::Data(vec![1; 520])]).evaluate(&|_| None, &ExecutionContext::default()));
+    }
+
+    fn evaluates(ops: &[OpCode]) -> bool {
+        let cmds = ops.iter().map(|&op| Cmd::Op(op)).collect();
+        Script::new(cmds).evaluate(&|_| None, &ExecutionContext::default())
+    }
+
+    #[test]
+    fn evaluate_p2pkh() {
+        let z = U256::from_be_slice(&decode_hex("7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d").unwrap()).unwrap();
+        let sec = decode_hex("04887387e452b8eacc4acfde10d9aaf7f6d9a0f975aabb10d006e4da568744d06c61de6d95231cd89026e286df3b6ae4a894a3378e393e93a0f45b666329a0ae34").unwrap();
+        let sig = decode_hex("3045022000eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c022100c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab601").unwrap();
+        let h160 = crate::hashes::hash160(&sec);
+        let script = Script::new(vec![Cmd::Data(sig), Cmd::Data(sec)]) + p2pkh_script(&h160);
+        assert!(script.evaluate(&|_| Some(z), &ExecutionContext::default()));
+        assert!(!script.evaluate(&|_| Some(U256::ONE), &ExecutionContext::default()));
+    }
+
+    #[test]
+    fn evaluate_arithmetic() {
+        use OpCode::*;
+        assert!(evaluates(&[OP_3, OP_4, OP_ADD, OP_7, OP_EQUAL]));
+        assert!(!evaluates(&[OP_3, OP_5, OP_ADD, OP_7, OP_EQUAL]));
+        assert!(evaluates(&[OP_2, OP_3, OP_SWAP, OP_SUB, OP_1, OP_EQUAL]));
+        assert!(evaluates(&[OP_0, OP_NOT, OP_1, OP_DROP]));
+    }
+
+    #[test]
+    fn evaluate_conditionals() {
+        use OpCode::*;
+        assert!(evaluates(&[OP_1, OP_IF, OP_2, OP_ELSE, OP_0, OP_ENDIF]));
+        assert!(!evaluates(&[OP_0, OP_IF, OP_2, OP_ELSE, OP_0, OP_ENDIF]));
+        assert!(evaluates(&[OP_0, OP_NOTIF, OP_2, OP_ELSE, OP_0, OP_ENDIF]));
+        assert!(evaluates(&[
+            OP_1, OP_0, OP_IF, OP_0, OP_IF, OP_0, OP_ENDIF, OP_ELSE, OP_1, OP_IF, OP_5, OP_ENDIF, OP_ENDIF, OP_5, OP_EQUAL,
+        ]));
+        // Each OP_ELSE flips the branch again
+        assert!(evaluates(&[OP_1, OP_IF, OP_0, OP_ELSE, OP_DROP, OP_ELSE, OP_1, OP_ENDIF]));
+        // Unbalanced conditionals fail
+        assert!(!evaluates(&[OP_1, OP_IF, OP_2]));
+        assert!(!evaluates(&[OP_1, OP_ENDIF]));
+        assert!(!evaluates(&[OP_1, OP_ELSE]));
+        assert!(!evaluates(&[OP_IF]));
+    }
+
+    #[test]
+    fn evaluate_disabled_and_altstack() {
+        use OpCode::*;
+        // A disabled opcode fails even in a branch that is not taken
+        assert!(!evaluates(&[OP_1, OP_0, OP_IF, OP_CAT, OP_ENDIF]));
+        assert!(!evaluates(&[OP_1, OP_1, OP_CAT]));
+        assert!(evaluates(&[OP_2, OP_TOALTSTACK, OP_3, OP_DROP, OP_FROMALTSTACK, OP_2, OP_EQUAL]));
+        assert!(!evaluates(&[OP_FROMALTSTACK]));
+    }
+
+    #[test]
+    fn add_combines_scripts() {
+        let z = U256::from_be_slice(&decode_hex("7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d").unwrap()).unwrap();
+        let sec = decode_hex("04887387e452b8eacc4acfde10d9aaf7f6d9a0f975aabb10d006e4da568744d06c61de6d95231cd89026e286df3b6ae4a894a3378e393e93a0f45b666329a0ae34").unwrap();
+        let sig = decode_hex("3045022000eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c022100c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab601").unwrap();
+        let script_pubkey = Script::new(vec![Cmd::Data(sec), Cmd::Op(OpCode::OP_CHECKSIG)]);
+        let script_sig = Script::new(vec![Cmd::Data(sig)]);
+        let combined = script_sig.clone() + script_pubkey.clone();
+        assert_eq!(combined.cmds.len(), 3);
+        assert!(combined.evaluate(&|_| Some(z), &ExecutionContext::default()));
+        assert!(!script_pubkey.evaluate(&|_| Some(z), &ExecutionContext::default()));
+        assert!(Script::default().is_empty());
+        assert!(!(Script::default() + script_sig).is_empty());
+    }
+
+    #[test]
+    fn address_round_trips() {
+        let point = crate::ecc::PrivateKey::new(U256::ONE).point;
+        for testnet in [false, true] {
+            for address in [point.address(true, testnet), point.address(false, testnet), point.segwit_address(testnet)] {
+                let script_pubkey = address_to_script_pubkey(&address, testnet).unwrap();
+                assert_eq!(script_pubkey.address(testnet).unwrap(), address);
+                assert!(matches!(address_to_script_pubkey(&address, !testnet), Err(ScriptError::WrongNetwork(_))));
+            }
+        }
+        let taproot = "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y";
+        assert_eq!(address_to_script_pubkey(taproot, false).unwrap().address(false).unwrap(), taproot);
+        assert!(matches!(address_to_script_pubkey("garbage", false), Err(ScriptError::InvalidAddress(_))));
+    }
+
+    #[test]
+    fn address_script_pubkey_types() {
+        let script_pubkey = address_to_script_pubkey("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH", false).unwrap();
+        let h160 = decode_hex("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        assert_eq!(script_pubkey, p2pkh_script(&h160.try_into().unwrap()));
+        assert!(script_pubkey.is_p2pkh_script_pubkey());
+        let script_pubkey = address_to_script_pubkey("3CLoMMyuoDQTPRD3XYZtCvgvkadrAdvdXh", false).unwrap();
+        assert!(script_pubkey.is_p2sh_script_pubkey());
+        assert_eq!(script_pubkey.address(false).unwrap(), "3CLoMMyuoDQTPRD3XYZtCvgvkadrAdvdXh");
+    }
+
+    fn multisig_keys() -> Vec<crate::ecc::PrivateKey> {
+        (1..=3).map(|i| crate::ecc::PrivateKey::new(U256::from_u64(i * 1000 + 7))).collect()
+    }
+
+    fn multisig_sig(key: &crate::ecc::PrivateKey, z: U256) -> Cmd {
+        let mut der = key.sign(z).der();
+        der.push(0x01);
+        Cmd::Data(der)
+    }
+
+    #[test]
+    fn multisig_script_evaluation() {
+        let keys = multisig_keys();
+        let points: Vec<S256Point> = keys.iter().map(|key| key.point).collect();
+        let redeem_script = multisig_script(2, &points).unwrap();
+        let z = U256::from_u64(99);
+        let context = ExecutionContext::default();
+        let signed = |sigs: Vec<Cmd>| Script::new(sigs) + redeem_script.clone();
+        let (dummy, sig0, sig1, sig2) =
+            (Cmd::Op(OpCode::OP_0), multisig_sig(&keys[0], z), multisig_sig(&keys[1], z), multisig_sig(&keys[2], z));
+        assert!(signed(vec![dummy.clone(), sig0.clone(), sig2.clone()]).evaluate(&|_| Some(z), &context));
+        // Signatures have to follow the key order
+        assert!(!signed(vec![dummy.clone(), sig2.clone(), sig0.clone()]).evaluate(&|_| Some(z), &context));
+        // The extra element popped by OP_CHECKMULTISIG is required
+        assert!(!signed(vec![sig0.clone(), sig2]).evaluate(&|_| Some(z), &context));
+        let mut verify = signed(vec![dummy, sig0, sig1]);
+        *verify.cmds.last_mut().unwrap() = Cmd::Op(OpCode::OP_CHECKMULTISIGVERIFY);
+        verify.cmds.push(Cmd::Op(OpCode::OP_1));
+        assert!(verify.evaluate(&|_| Some(z), &context));
+    }
+
+    #[test]
+    fn multisig_script_limits() {
+        let points: Vec<S256Point> = multisig_keys().iter().map(|key| key.point).collect();
+        assert!(multisig_script(3, &points[..2]).is_err());
+        assert!(multisig_script(0, &points).is_err());
+        assert!(multisig_script(1, &vec![points[0]; 21]).is_err());
+        // 17 keys no longer fit a small-number opcode
+        let keys = multisig_keys();
+        let z = U256::from_u64(99);
+        let redeem_script = multisig_script(1, &vec![points[0]; 17]).unwrap();
+        let script = Script::new(vec![Cmd::Op(OpCode::OP_0), multisig_sig(&keys[0], z)]) + redeem_script;
+        assert!(script.evaluate(&|_| Some(z), &ExecutionContext::default()));
+    }
+
+    #[test]
+    fn asm_round_trip() {
+        let raw = decode_hex("76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac").unwrap();
+        let script = Script::parse_raw(&raw).unwrap();
+        assert_eq!(script.to_string(), "OP_DUP OP_HASH160 bc3b654dca7e56b04dca18f2566cdaf02e8d9ada OP_EQUALVERIFY OP_CHECKSIG");
+        for hex in [
+            "a91474d691da1574e6b3c192ecfb52cc8984ee7b6c5687",
+            "6a0b68656c6c6f20776f726c64",
+            "5221022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb702103b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb7152ae",
+        ] {
+            let script = Script::parse_raw(&decode_hex(hex).unwrap()).unwrap();
+            assert_eq!(Script::from_asm(&script.to_string()).unwrap(), script);
+        }
+    }
+
+    #[test]
+    fn asm_shorthands() {
+        let script = Script::from_asm("2 DUP 0 -1 17 0xba OP_TRUE").unwrap();
+        assert_eq!(
+            script.cmds,
+            vec![
+                Cmd::Op(OpCode::OP_2),
+                Cmd::Op(OpCode::OP_DUP),
+                Cmd::Op(OpCode::OP_0),
+                Cmd::Op(OpCode::OP_1NEGATE),
+                Cmd::Data(vec![0x17]),
+                Cmd::Op(OpCode::from_byte(0xba)),
+                Cmd::Op(OpCode::OP_1),
+            ]
+        );
+        // Even-length digits are hex, so only odd-length ones are numbers
+        assert_eq!(Script::from_asm("1000").unwrap().cmds, vec![Cmd::Data(vec![0x10, 0x00])]);
+        assert_eq!(Script::from_asm("100").unwrap().cmds, vec![Cmd::Data(encode_num(100))]);
+        for bad in ["OP_FOO", "0x01", "OP_PUSHDATA1"] {
+            assert!(matches!(Script::from_asm(bad), Err(ScriptError::InvalidAsm(_))), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn op_return_outputs() {
+        for len in [0, 1, 75, 76, 80] {
+            let data = vec![7; len];
+            let script = Script::op_return(&data, false).unwrap();
+            assert!(script.is_op_return());
+            let serialized = script.serialize();
+            if len == 76 {
+                assert_eq!(serialized[2], 0x4c);
+            }
+            assert_eq!(Script::parse(&mut &serialized[..]).unwrap().op_return_data().unwrap(), data);
+        }
+        assert!(matches!(Script::op_return(&[0; 81], false), Err(ScriptError::DataTooLarge(81))));
+        assert!(Script::op_return(&[0; 81], true).is_ok());
+        assert!(Script::op_return(&[0; 521], true).is_err());
+        let hello = Script::parse_raw(&decode_hex("6a0b68656c6c6f20776f726c64").unwrap()).unwrap();
+        assert_eq!(hello.op_return_data().unwrap(), b"hello world");
+        assert!(p2sh_script(&[0; 20]).op_return_data().is_none());
+    }
+
+    #[test]
+    fn script_types() {
+        let script_type = |hex: &str| Script::parse_raw(&decode_hex(hex).unwrap()).unwrap().script_type();
+        let p2pk = "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac";
+        assert_eq!(script_type(p2pk), ScriptType::P2pk);
+        assert_eq!(script_type("76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac"), ScriptType::P2pkh);
+        assert_eq!(script_type("a91474d691da1574e6b3c192ecfb52cc8984ee7b6c5687"), ScriptType::P2sh);
+        assert_eq!(script_type("0014751e76e8199196d454941c45d1b3a323f1433bd6"), ScriptType::P2wpkh);
+        let p2wsh = "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262";
+        assert_eq!(script_type(p2wsh), ScriptType::P2wsh);
+        let p2tr = "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        assert_eq!(script_type(p2tr), ScriptType::P2tr);
+        assert_eq!(script_type("6a0b68656c6c6f20776f726c64"), ScriptType::OpReturn);
+        let keys = "21022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb702103b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb71";
+        assert_eq!(script_type(&format!("52{}52ae", keys)), ScriptType::Multisig { m: 2, n: 2 });
+        // m above n
+        assert_eq!(script_type(&format!("53{}52ae", keys)), ScriptType::NonStandard);
+        assert_eq!(script_type("0015751e76e8199196d454941c45d1b3a323f1433bd6aa"), ScriptType::NonStandard);
+        assert_eq!(script_type("76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88"), ScriptType::NonStandard);
+        assert_eq!(script_type("6a76"), ScriptType::NonStandard);
+        assert!(Script::op_return(&[1; 80], false).unwrap().is_standard());
+        assert!(!Script::op_return(&[1; 81], true).unwrap().is_standard());
+        assert_eq!(ScriptType::Multisig { m: 1, n: 3 }.to_string(), "multisig(1 of 3)");
+    }
+
+    #[test]
+    fn evaluate_op_return() {
+        use OpCode::*;
+        assert!(!evaluates(&[OP_1, OP_RETURN]));
+        assert!(!evaluates(&[OP_RETURN, OP_1]));
+        // Only an executed OP_RETURN fails
+        assert!(evaluates(&[OP_0, OP_IF, OP_RETURN, OP_ENDIF, OP_1]));
+        let data_output = Script::op_return(b"hello world", false).unwrap();
+        assert!(!(Script::new(vec![Cmd::Op(OP_1)]) + data_output).evaluate(&|_| None, &ExecutionContext::default()));
+    }
+}
@@ -0,0 +1,1782 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::ecc::{PrivateKey, S256Point, Signature};
+use crate::error::ParseError;
+use crate::hashes::{hash160, hash256, sha256};
+use crate::op::{signature_and_digest, ExecutionContext, OpCode};
+use crate::script::{p2pkh_script, Cmd, Script, ScriptType, MAX_SCRIPT_ELEMENT_SIZE};
+use crate::u256::U256;
+use crate::util::{decode_hex, encode_hex, encode_varint, read_bytes, read_u32, read_u64, read_varint, HexError};
+
+// Locktimes below this are block heights, from it on unix timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+pub const SEQUENCE_FINAL: u32 = 0xffffffff;
+// The highest sequence that still lets the locktime apply
+pub const SEQUENCE_LOCKTIME: u32 = 0xfffffffe;
+// The highest sequence that signals replaceability (BIP125)
+pub const SEQUENCE_RBF: u32 = 0xfffffffd;
+
+// BIP68 relative locktime fields of the sequence
+pub(crate) const SEQUENCE_DISABLE_FLAG: u32 = 1 << 31;
+pub(crate) const SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
+pub(crate) const SEQUENCE_MASK: u32 = 0x0000ffff;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LockTime {
+    // A zero locktime, or every input has a final sequence
+    Disabled,
+    BlockHeight(u32),
+    UnixTime(u32),
+}
+
+// Time is counted in units of 512 seconds
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RelativeLockTime {
+    Blocks(u16),
+    Time(u16),
+}
+
+// The byte appended to a signature, saying which parts of the spending
+// transaction it commits to. ANYONECANPAY signs only the input itself.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SighashType {
+    All,
+    None,
+    Single,
+    AllAnyoneCanPay,
+    NoneAnyoneCanPay,
+    SingleAnyoneCanPay,
+}
+
+impl SighashType {
+    pub fn from_byte(byte: u8) -> Option<SighashType> {
+        match byte {
+            0x01 => Some(SighashType::All),
+            0x02 => Some(SighashType::None),
+            0x03 => Some(SighashType::Single),
+            0x81 => Some(SighashType::AllAnyoneCanPay),
+            0x82 => Some(SighashType::NoneAnyoneCanPay),
+            0x83 => Some(SighashType::SingleAnyoneCanPay),
+            _ => None,
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            SighashType::All => 0x01,
+            SighashType::None => 0x02,
+            SighashType::Single => 0x03,
+            SighashType::AllAnyoneCanPay => 0x81,
+            SighashType::NoneAnyoneCanPay => 0x82,
+            SighashType::SingleAnyoneCanPay => 0x83,
+        }
+    }
+
+    pub fn anyone_can_pay(self) -> bool {
+        self.to_byte() & 0x80 != 0
+    }
+
+    // The output commitment without the ANYONECANPAY flag
+    pub fn base(self) -> SighashType {
+        match self {
+            SighashType::All | SighashType::AllAnyoneCanPay => SighashType::All,
+            SighashType::None | SighashType::NoneAnyoneCanPay => SighashType::None,
+            SighashType::Single | SighashType::SingleAnyoneCanPay => SighashType::Single,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Tx {
+    pub version: u32,
+    pub tx_ins: Vec<TxIn>,
+    pub tx_outs: Vec<TxOut>,
+    pub locktime: u32,
+    pub testnet: bool,
+}
+
+// prev_tx is kept in display order, the wire format stores it reversed.
+// The witness is serialized separately, after all the outputs.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TxIn {
+    pub prev_tx: [u8; 32],
+    pub prev_index: u32,
+    pub script_sig: Script,
+    pub sequence: u32,
+    pub witness: Vec<Vec<u8>>,
+}
+
+// A reference to a previous output, tx_id in display order
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct OutPoint {
+    pub tx_id: [u8; 32],
+    pub index: u32,
+}
+
+impl OutPoint {
+    pub fn new(tx_id: [u8; 32], index: u32) -> OutPoint {
+        OutPoint { tx_id, index }
+    }
+
+    // The output this points to, from the transaction that created it
+    pub fn fetch_output(&self, fetcher: &mut dyn TxFetcher, testnet: bool) -> Result<TxOut, FetchError> {
+        let tx_id = encode_hex(&self.tx_id);
+        let tx = fetcher.fetch(&tx_id, testnet)?;
+        tx.tx_outs
+            .get(self.index as usize)
+            .cloned()
+            .ok_or(FetchError::MissingOutput { tx_id, index: self.index })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TxOut {
+    pub amount: u64,
+    pub script_pubkey: Script,
+}
+
+#[derive(Debug)]
+pub enum TxError {
+    Hex(HexError),
+    Parse(ParseError),
+    Fetch(Box<FetchError>),
+    PrevoutCount { expected: usize, actual: usize },
+    InputIndex(usize),
+    AmountOverflow,
+    NegativeFee { input_amount: u64, output_amount: u64 },
+    CannotSign(usize),
+    MissingScriptCode(usize),
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::Hex(err) => write!(f, "{}", err),
+            TxError::Parse(err) => write!(f, "{}", err),
+            TxError::Fetch(err) => write!(f, "Failed to fetch previous transaction: {}", err),
+            TxError::PrevoutCount { expected, actual } => {
+                write!(f, "Expected {} previous outputs, got {}", expected, actual)
+            }
+            TxError::InputIndex(index) => write!(f, "Input index {} out of range", index),
+            TxError::AmountOverflow => write!(f, "Amounts overflow a u64"),
+            TxError::NegativeFee { input_amount, output_amount } => {
+                write!(f, "Outputs ({}) exceed inputs ({})", output_amount, input_amount)
+            }
+            TxError::CannotSign(index) => write!(f, "Input {} is not spendable by this key", index),
+            TxError::MissingScriptCode(index) => write!(f, "Input {} has no witness script code", index),
+        }
+    }
+}
+
+impl From<HexError> for TxError {
+    fn from(err: HexError) -> TxError {
+        TxError::Hex(err)
+    }
+}
+
+impl From<ParseError> for TxError {
+    fn from(err: ParseError) -> TxError {
+        TxError::Parse(err)
+    }
+}
+
+impl From<FetchError> for TxError {
+    fn from(err: FetchError) -> TxError {
+        TxError::Fetch(Box::new(err))
+    }
+}
+
+impl Tx {
+    // BIP144: a zero byte where the input count would be is the segwit
+    // marker, followed by a 0x01 flag, and the witnesses come after the
+    // outputs. No legacy transaction has zero inputs, so this is unambiguous.
+    pub fn parse<R: Read>(reader: &mut R, testnet: bool) -> Result<Tx, ParseError> {
+        let version = read_u32(reader)?;
+        let mut marker = [0u8; 1];
+        reader.read_exact(&mut marker)?;
+        let segwit = marker[0] == 0x00;
+        let num_inputs = if segwit {
+            let mut flag = [0u8; 1];
+            reader.read_exact(&mut flag)?;
+            if flag[0] != 0x01 {
+                return Err(ParseError::InvalidFormat("invalid segwit flag"));
+            }
+            read_varint(reader)?
+        } else {
+            read_varint(&mut (&marker[..]).chain(reader.by_ref()))?
+        };
+        let mut tx_ins = Vec::new();
+        for _ in 0..num_inputs {
+            tx_ins.push(TxIn::parse(reader)?);
+        }
+        let num_outputs = read_varint(reader)?;
+        let mut tx_outs = Vec::new();
+        for _ in 0..num_outputs {
+            tx_outs.push(TxOut::parse(reader)?);
+        }
+        if segwit {
+            for tx_in in &mut tx_ins {
+                let num_items = read_varint(reader)?;
+                for _ in 0..num_items {
+                    let len = read_varint(reader)?;
+                    tx_in.witness.push(read_bytes(reader, len)?);
+                }
+            }
+        }
+        let locktime = read_u32(reader)?;
+        Ok(Tx { version, tx_ins, tx_outs, locktime, testnet })
+    }
+
+    // The outputs this transaction spends, in the same order as tx_ins
+    pub fn prevouts(&self, fetcher: &mut dyn TxFetcher) -> Result<Vec<TxOut>, FetchError> {
+        self.tx_ins.iter().map(|tx_in| tx_in.prev_output(fetcher, self.testnet)).collect()
+    }
+
+    fn check_prevouts(&self, prevouts: &[TxOut]) -> Result<(), TxError> {
+        if prevouts.len() != self.tx_ins.len() {
+            return Err(TxError::PrevoutCount { expected: self.tx_ins.len(), actual: prevouts.len() });
+        }
+        Ok(())
+    }
+
+    pub fn fee(&self, fetcher: &mut dyn TxFetcher) -> Result<u64, TxError> {
+        self.fee_with_prevouts(&self.prevouts(fetcher)?)
+    }
+
+    pub fn fee_with_prevouts(&self, prevouts: &[TxOut]) -> Result<u64, TxError> {
+        self.check_prevouts(prevouts)?;
+        let input_amount = sum_amounts(prevouts)?;
+        let output_amount = sum_amounts(&self.tx_outs)?;
+        input_amount
+            .checked_sub(output_amount)
+            .ok_or(TxError::NegativeFee { input_amount, output_amount })
+    }
+
+    // Legacy digest: the signed input carries the previous script_pubkey
+    // (or the redeem script for p2sh), every other input an empty
+    // script_sig. NONE and SINGLE also zero the other inputs' sequences.
+    pub fn sig_hash(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOut],
+        redeem_script: Option<&Script>,
+        sighash_type: SighashType,
+    ) -> Result<U256, TxError> {
+        self.check_prevouts(prevouts)?;
+        if input_index >= self.tx_ins.len() {
+            return Err(TxError::InputIndex(input_index));
+        }
+        let base = sighash_type.base();
+        // Consensus quirk: SINGLE without a matching output signs the
+        // number one, stored little-endian, instead of failing
+        if base == SighashType::Single && input_index >= self.tx_outs.len() {
+            let mut one = [0u8; 32];
+            one[0] = 1;
+            return Ok(U256::from_be_bytes(one));
+        }
+        let signed_inputs: Vec<(usize, &TxIn)> = if sighash_type.anyone_can_pay() {
+            vec![(input_index, &self.tx_ins[input_index])]
+        } else {
+            self.tx_ins.iter().enumerate().collect()
+        };
+        let mut s = self.version.to_le_bytes().to_vec();
+        s.extend(encode_varint(signed_inputs.len() as u64));
+        for (i, tx_in) in signed_inputs {
+            let tx_in = if i == input_index {
+                let script_sig = redeem_script.unwrap_or(&prevouts[i].script_pubkey).clone();
+                TxIn { script_sig, ..tx_in.clone() }
+            } else {
+                let sequence = if base == SighashType::All { tx_in.sequence } else { 0 };
+                TxIn { script_sig: Script::new(vec![]), sequence, ..tx_in.clone() }
+            };
+            s.extend(tx_in.serialize());
+        }
+        let signed_outputs = match base {
+            SighashType::None => 0,
+            SighashType::Single => input_index + 1,
+            _ => self.tx_outs.len(),
+        };
+        s.extend(encode_varint(signed_outputs as u64));
+        for (i, tx_out) in self.tx_outs.iter().take(signed_outputs).enumerate() {
+            if base == SighashType::Single && i != input_index {
+                // Earlier outputs are blanked to an amount of -1 and an empty script
+                s.extend(TxOut { amount: u64::MAX, script_pubkey: Script::new(vec![]) }.serialize());
+            } else {
+                s.extend(tx_out.serialize());
+            }
+        }
+        s.extend_from_slice(&self.locktime.to_le_bytes());
+        s.extend_from_slice(&(sighash_type.to_byte() as u32).to_le_bytes());
+        Ok(U256::from_be_bytes(hash256(&s)))
+    }
+
+    // BIP143 digest for a witness input. The script code is the witness
+    // script for p2wsh, otherwise the p2pkh script of the p2wpkh program
+    // found in the redeem script or the previous script_pubkey.
+    pub fn sig_hash_bip143(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOut],
+        redeem_script: Option<&Script>,
+        witness_script: Option<&Script>,
+        sighash_type: SighashType,
+    ) -> Result<U256, TxError> {
+        let hashes = Bip143Hashes::new(self);
+        self.sig_hash_bip143_with(&hashes, input_index, prevouts, redeem_script, witness_script, sighash_type)
+    }
+
+    // Same as sig_hash_bip143 with the per-transaction hashes computed once
+    // by the caller, for signing or verifying many inputs
+    pub fn sig_hash_bip143_with(
+        &self,
+        hashes: &Bip143Hashes,
+        input_index: usize,
+        prevouts: &[TxOut],
+        redeem_script: Option<&Script>,
+        witness_script: Option<&Script>,
+        sighash_type: SighashType,
+    ) -> Result<U256, TxError> {
+        self.check_prevouts(prevouts)?;
+        let tx_in = self.tx_ins.get(input_index).ok_or(TxError::InputIndex(input_index))?;
+        let script_code = match witness_script {
+            Some(witness_script) => witness_script.clone(),
+            None => {
+                let program = redeem_script.unwrap_or(&prevouts[input_index].script_pubkey);
+                let h160 = match program.cmds.as_slice() {
+                    [Cmd::Op(OpCode::OP_0), Cmd::Data(h160)] => <&[u8; 20]>::try_from(h160.as_slice()).ok(),
+                    _ => None,
+                };
+                p2pkh_script(h160.ok_or(TxError::MissingScriptCode(input_index))?)
+            }
+        };
+        // ANYONECANPAY, NONE and SINGLE zero out what they do not commit to
+        let base = sighash_type.base();
+        let anyone_can_pay = sighash_type.anyone_can_pay();
+        let hash_prevouts = if anyone_can_pay { [0u8; 32] } else { hashes.hash_prevouts };
+        let hash_sequence =
+            if anyone_can_pay || base != SighashType::All { [0u8; 32] } else { hashes.hash_sequence };
+        let hash_outputs = match base {
+            SighashType::All => hashes.hash_outputs,
+            SighashType::Single if input_index < self.tx_outs.len() => hash256(&self.tx_outs[input_index].serialize()),
+            _ => [0u8; 32],
+        };
+        let mut s = self.version.to_le_bytes().to_vec();
+        s.extend_from_slice(&hash_prevouts);
+        s.extend_from_slice(&hash_sequence);
+        s.extend(tx_in.prev_tx.iter().rev());
+        s.extend_from_slice(&tx_in.prev_index.to_le_bytes());
+        s.extend(script_code.serialize());
+        s.extend_from_slice(&prevouts[input_index].amount.to_le_bytes());
+        s.extend_from_slice(&tx_in.sequence.to_le_bytes());
+        s.extend_from_slice(&hash_outputs);
+        s.extend_from_slice(&self.locktime.to_le_bytes());
+        s.extend_from_slice(&(sighash_type.to_byte() as u32).to_le_bytes());
+        Ok(U256::from_be_bytes(hash256(&s)))
+    }
+
+    pub fn verify_input(&self, input_index: usize, prevouts: &[TxOut]) -> Result<bool, TxError> {
+        self.verify_input_with(&Bip143Hashes::new(self), input_index, prevouts)
+    }
+
+    fn verify_input_with(&self, hashes: &Bip143Hashes, input_index: usize, prevouts: &[TxOut]) -> Result<bool, TxError> {
+        self.check_prevouts(prevouts)?;
+        let tx_in = self.tx_ins.get(input_index).ok_or(TxError::InputIndex(input_index))?;
+        let script_pubkey = &prevouts[input_index].script_pubkey;
+        // For p2sh the digest commits to the redeem script, the last script_sig
+        // push. One that does not parse is an invalid script like any other.
+        let redeem_script = match tx_in.script_sig.cmds.last() {
+            Some(Cmd::Data(raw)) if script_pubkey.is_p2sh_script_pubkey() => match Script::parse_raw(raw) {
+                Ok(redeem_script) => Some(redeem_script),
+                Err(_) => return Ok(false),
+            },
+            _ => None,
+        };
+        let combined = tx_in.script_sig.clone() + script_pubkey.clone();
+        let context = ExecutionContext { locktime: self.locktime, sequence: tx_in.sequence, version: self.version };
+        let is_witness_program = |script: &Script| script.is_p2wpkh_script_pubkey() || script.is_p2wsh_script_pubkey();
+        let witness_program = match &redeem_script {
+            Some(redeem_script) if is_witness_program(redeem_script) => redeem_script,
+            None if is_witness_program(script_pubkey) => script_pubkey,
+            _ => {
+                let sig_hash =
+                    |sighash_type| self.sig_hash(input_index, prevouts, redeem_script.as_ref(), sighash_type).ok();
+                return Ok(combined.evaluate(&sig_hash, &context));
+            }
+        };
+        // A wrapped program must still match the p2sh hash, while a native
+        // one must come with an empty script_sig
+        if redeem_script.is_some() {
+            if tx_in.script_sig.cmds.len() != 1 || !combined.evaluate(&|_| None, &context) {
+                return Ok(false);
+            }
+        } else if !tx_in.script_sig.is_empty() {
+            return Ok(false);
+        }
+        let Cmd::Data(program) = &witness_program.cmds[1] else {
+            return Ok(false);
+        };
+        // p2wpkh runs the implied p2pkh script against [sig, pubkey], p2wsh
+        // the last witness item, committed to by its sha256, against the rest
+        let (witness_script, stack) = if let Ok(h160) = program.as_slice().try_into() {
+            if tx_in.witness.len() != 2 {
+                return Ok(false);
+            }
+            (p2pkh_script(h160), tx_in.witness.clone())
+        } else {
+            let Some((raw, stack)) = tx_in.witness.split_last() else {
+                return Ok(false);
+            };
+            if sha256(raw)[..] != program[..] {
+                return Ok(false);
+            }
+            match Script::parse_raw(raw) {
+                Ok(witness_script) => (witness_script, stack.to_vec()),
+                Err(_) => return Ok(false),
+            }
+        };
+        if stack.iter().any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+            return Ok(false);
+        }
+        // The p2wpkh script code is the p2pkh script, which sig_hash_bip143
+        // derives from the program itself
+        let script_code = witness_program.is_p2wsh_script_pubkey().then_some(&witness_script);
+        let sig_hash = |sighash_type| {
+            self.sig_hash_bip143_with(hashes, input_index, prevouts, redeem_script.as_ref(), script_code, sighash_type)
+                .ok()
+        };
+        Ok(witness_script.evaluate_with_stack(stack, &sig_hash, &context))
+    }
+
+    // sign_input with the spent outputs looked up through the fetcher
+    pub fn sign_input_with_fetcher(
+        &mut self,
+        input_index: usize,
+        private_key: &PrivateKey,
+        sighash_type: SighashType,
+        fetcher: &mut dyn TxFetcher,
+    ) -> Result<bool, TxError> {
+        let prevouts = self.prevouts(fetcher)?;
+        self.sign_input(input_index, &prevouts, private_key, sighash_type)
+    }
+
+    // Signs p2pkh, p2wpkh and p2sh-p2wpkh outputs locked to this key's
+    // hash160. For p2pkh the compressed or uncompressed SEC is chosen to
+    // match the committed hash, witness outputs only take compressed keys.
+    pub fn sign_input(
+        &mut self,
+        input_index: usize,
+        prevouts: &[TxOut],
+        private_key: &PrivateKey,
+        sighash_type: SighashType,
+    ) -> Result<bool, TxError> {
+        self.check_prevouts(prevouts)?;
+        if input_index >= self.tx_ins.len() {
+            return Err(TxError::InputIndex(input_index));
+        }
+        let compressed_sec = private_key.point.sec(true);
+        let program = Script::new(vec![Cmd::Op(OpCode::OP_0), Cmd::Data(hash160(&compressed_sec).to_vec())]);
+        let script_pubkey = &prevouts[input_index].script_pubkey;
+        if *script_pubkey == program {
+            let z = self.sig_hash_bip143(input_index, prevouts, None, None, sighash_type)?;
+            let tx_in = &mut self.tx_ins[input_index];
+            tx_in.script_sig = Script::new(vec![]);
+            tx_in.witness = vec![signature_with_sighash(private_key, z, sighash_type), compressed_sec];
+            return self.verify_input(input_index, prevouts);
+        }
+        if let [Cmd::Op(OpCode::OP_HASH160), Cmd::Data(h160), Cmd::Op(OpCode::OP_EQUAL)] = script_pubkey.cmds.as_slice() {
+            let raw_program = program.raw_serialize();
+            if hash160(&raw_program)[..] != h160[..] {
+                return Err(TxError::CannotSign(input_index));
+            }
+            let z = self.sig_hash_bip143(input_index, prevouts, Some(&program), None, sighash_type)?;
+            let tx_in = &mut self.tx_ins[input_index];
+            tx_in.script_sig = Script::new(vec![Cmd::Data(raw_program)]);
+            tx_in.witness = vec![signature_with_sighash(private_key, z, sighash_type), compressed_sec];
+            return self.verify_input(input_index, prevouts);
+        }
+        let sec = match script_pubkey.cmds.as_slice() {
+            [
+                Cmd::Op(OpCode::OP_DUP),
+                Cmd::Op(OpCode::OP_HASH160),
+                Cmd::Data(h160),
+                Cmd::Op(OpCode::OP_EQUALVERIFY),
+                Cmd::Op(OpCode::OP_CHECKSIG),
+            ] => [true, false]
+                .into_iter()
+                .map(|compressed| private_key.point.sec(compressed))
+                .find(|sec| hash160(sec)[..] == h160[..]),
+            _ => None,
+        };
+        let sec = sec.ok_or(TxError::CannotSign(input_index))?;
+        let z = self.sig_hash(input_index, prevouts, None, sighash_type)?;
+        let sig = signature_with_sighash(private_key, z, sighash_type);
+        self.tx_ins[input_index].script_sig = Script::new(vec![Cmd::Data(sig), Cmd::Data(sec)]);
+        self.verify_input(input_index, prevouts)
+    }
+
+    pub fn verify(&self, prevouts: &[TxOut]) -> Result<bool, TxError> {
+        match self.fee_with_prevouts(prevouts) {
+            Ok(_) => {}
+            Err(TxError::NegativeFee { .. }) => return Ok(false),
+            Err(err) => return Err(err),
+        }
+        let hashes = Bip143Hashes::new(self);
+        for input_index in 0..self.tx_ins.len() {
+            if !self.verify_input_with(&hashes, input_index, prevouts)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // verify with the spent outputs looked up through the fetcher
+    pub fn verify_with_fetcher(&self, fetcher: &mut dyn TxFetcher) -> Result<bool, TxError> {
+        self.verify(&self.prevouts(fetcher)?)
+    }
+
+    // Same decision as verify, with the signatures of standard p2pkh and
+    // p2wpkh inputs checked together by S256Point::verify_batch
+    pub fn verify_batched(&self, prevouts: &[TxOut]) -> Result<bool, TxError> {
+        match self.fee_with_prevouts(prevouts) {
+            Ok(_) => {}
+            Err(TxError::NegativeFee { .. }) => return Ok(false),
+            Err(err) => return Err(err),
+        }
+        let hashes = Bip143Hashes::new(self);
+        let mut batch = Vec::new();
+        for input_index in 0..self.tx_ins.len() {
+            match self.batch_item(&hashes, input_index, prevouts) {
+                Some(item) => batch.push(item),
+                None => {
+                    if !self.verify_input_with(&hashes, input_index, prevouts)? {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        Ok(S256Point::verify_batch(&batch))
+    }
+
+    // The first input that fails verify_input, to find the culprit once
+    // a batch is rejected
+    pub fn first_invalid_input(&self, prevouts: &[TxOut]) -> Result<Option<usize>, TxError> {
+        self.check_prevouts(prevouts)?;
+        let hashes = Bip143Hashes::new(self);
+        for input_index in 0..self.tx_ins.len() {
+            if !self.verify_input_with(&hashes, input_index, prevouts)? {
+                return Ok(Some(input_index));
+            }
+        }
+        Ok(None)
+    }
+
+    // The pubkey, digest and signature of a p2pkh or p2wpkh input laid out
+    // so that verify_input comes down to exactly that one check. Anything
+    // else, malformed pushes included, is left to verify_input.
+    fn batch_item(
+        &self,
+        hashes: &Bip143Hashes,
+        input_index: usize,
+        prevouts: &[TxOut],
+    ) -> Option<(S256Point, U256, Signature)> {
+        let tx_in = &self.tx_ins[input_index];
+        let (sig, sec, h160, is_witness) = match (prevouts[input_index].script_pubkey.cmds.as_slice(), tx_in) {
+            (
+                [
+                    Cmd::Op(OpCode::OP_DUP),
+                    Cmd::Op(OpCode::OP_HASH160),
+                    Cmd::Data(h160),
+                    Cmd::Op(OpCode::OP_EQUALVERIFY),
+                    Cmd::Op(OpCode::OP_CHECKSIG),
+                ],
+                TxIn { script_sig, .. },
+            ) => match script_sig.cmds.as_slice() {
+                [Cmd::Data(sig), Cmd::Data(sec)] => (sig, sec, h160, false),
+                _ => return None,
+            },
+            ([Cmd::Op(OpCode::OP_0), Cmd::Data(h160)], TxIn { script_sig, witness, .. })
+                if h160.len() == 20 && script_sig.cmds.is_empty() =>
+            {
+                match witness.as_slice() {
+                    [sig, sec] => (sig, sec, h160, true),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        if hash160(sec)[..] != h160[..] {
+            return None;
+        }
+        let point = S256Point::parse(sec).ok()?;
+        let sig_hash = |sighash_type| {
+            if is_witness {
+                self.sig_hash_bip143_with(hashes, input_index, prevouts, None, None, sighash_type).ok()
+            } else {
+                self.sig_hash(input_index, prevouts, None, sighash_type).ok()
+            }
+        };
+        let (sig, z) = signature_and_digest(sig, &sig_hash)?;
+        Some((point, z, sig))
+    }
+
+    // A single input spending the null outpoint: all zero txid, index 0xffffffff
+    pub fn is_coinbase(&self) -> bool {
+        match self.tx_ins.as_slice() {
+            [tx_in] => tx_in.prev_tx == [0u8; 32] && tx_in.prev_index == 0xffffffff,
+            _ => false,
+        }
+    }
+
+    // BIP34 puts the block height in the first script_sig push, little-endian.
+    // Whatever follows it is miner data and is ignored. Coinbases from before
+    // BIP34 give whatever their first push decodes to.
+    pub fn coinbase_height(&self) -> Option<u64> {
+        if !self.is_coinbase() {
+            return None;
+        }
+        match self.tx_ins[0].script_sig.cmds.first()? {
+            Cmd::Data(height) if height.len() <= 8 => {
+                Some(height.iter().rev().fold(0u64, |acc, &byte| acc << 8 | byte as u64))
+            }
+            Cmd::Op(OpCode::OP_0) => Some(0),
+            Cmd::Op(op) => match op.to_byte() {
+                n @ 0x51..=0x60 => Some((n - 0x50) as u64),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn locktime_kind(&self) -> LockTime {
+        if self.locktime == 0 || self.tx_ins.iter().all(|tx_in| tx_in.sequence == SEQUENCE_FINAL) {
+            LockTime::Disabled
+        } else if self.locktime < LOCKTIME_THRESHOLD {
+            LockTime::BlockHeight(self.locktime)
+        } else {
+            LockTime::UnixTime(self.locktime)
+        }
+    }
+
+    pub fn signals_rbf(&self) -> bool {
+        self.tx_ins.iter().any(TxIn::is_rbf_signaling)
+    }
+
+    // Serialized size in bytes, witnesses included
+    pub fn size(&self) -> usize {
+        self.serialize().len()
+    }
+
+    // BIP141: witness bytes count once, everything else four times
+    pub fn weight(&self) -> usize {
+        self.serialize_legacy().len() * 3 + self.size()
+    }
+
+    pub fn vsize(&self) -> usize {
+        self.weight().div_ceil(4)
+    }
+
+    // In satoshis per virtual byte
+    pub fn fee_rate(&self, fee: u64) -> f64 {
+        fee as f64 / self.vsize() as f64
+    }
+
+    pub fn is_segwit(&self) -> bool {
+        self.tx_ins.iter().any(|tx_in| !tx_in.witness.is_empty())
+    }
+
+    // Transaction hash in display order, over the witness-stripped form
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hash = hash256(&self.serialize_legacy());
+        hash.reverse();
+        hash
+    }
+
+    pub fn id(&self) -> String {
+        encode_hex(&self.hash())
+    }
+
+    // Same as id() for a transaction without witnesses
+    pub fn wtxid(&self) -> String {
+        let mut hash = hash256(&self.serialize());
+        hash.reverse();
+        encode_hex(&hash)
+    }
+
+    // The whole string must be the one transaction
+    pub fn parse_hex(hex: &str, testnet: bool) -> Result<Tx, TxError> {
+        let raw = decode_hex(hex)?;
+        let mut reader = &raw[..];
+        let tx = Tx::parse(&mut reader, testnet)?;
+        if !reader.is_empty() {
+            return Err(ParseError::InvalidFormat("trailing data after transaction").into());
+        }
+        Ok(tx)
+    }
+
+    pub fn to_hex(&self) -> String {
+        encode_hex(&self.serialize())
+    }
+
+    // Segwit format whenever any input carries a witness
+    pub fn serialize(&self) -> Vec<u8> {
+        if self.is_segwit() {
+            self.serialize_segwit()
+        } else {
+            self.serialize_legacy()
+        }
+    }
+
+    pub fn serialize_legacy(&self) -> Vec<u8> {
+        let mut result = self.version.to_le_bytes().to_vec();
+        result.extend(self.serialize_ins_outs());
+        result.extend_from_slice(&self.locktime.to_le_bytes());
+        result
+    }
+
+    fn serialize_segwit(&self) -> Vec<u8> {
+        let mut result = self.version.to_le_bytes().to_vec();
+        result.extend_from_slice(&[0x00, 0x01]);
+        result.extend(self.serialize_ins_outs());
+        for tx_in in &self.tx_ins {
+            result.extend(encode_varint(tx_in.witness.len() as u64));
+            for item in &tx_in.witness {
+                result.extend(encode_varint(item.len() as u64));
+                result.extend_from_slice(item);
+            }
+        }
+        result.extend_from_slice(&self.locktime.to_le_bytes());
+        result
+    }
+
+    fn serialize_ins_outs(&self) -> Vec<u8> {
+        let mut result = encode_varint(self.tx_ins.len() as u64);
+        for tx_in in &self.tx_ins {
+            result.extend(tx_in.serialize());
+        }
+        result.extend(encode_varint(self.tx_outs.len() as u64));
+        for tx_out in &self.tx_outs {
+            result.extend(tx_out.serialize());
+        }
+        result
+    }
+}
+
+// The BIP143 hashes shared by every input of a transaction
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Bip143Hashes {
+    pub hash_prevouts: [u8; 32],
+    pub hash_sequence: [u8; 32],
+    pub hash_outputs: [u8; 32],
+}
+
+impl Bip143Hashes {
+    pub fn new(tx: &Tx) -> Bip143Hashes {
+        let mut prevouts = Vec::new();
+        let mut sequences = Vec::new();
+        for tx_in in &tx.tx_ins {
+            prevouts.extend(tx_in.prev_tx.iter().rev());
+            prevouts.extend_from_slice(&tx_in.prev_index.to_le_bytes());
+            sequences.extend_from_slice(&tx_in.sequence.to_le_bytes());
+        }
+        let outputs: Vec<u8> = tx.tx_outs.iter().flat_map(|tx_out| tx_out.serialize()).collect();
+        Bip143Hashes {
+            hash_prevouts: hash256(&prevouts),
+            hash_sequence: hash256(&sequences),
+            hash_outputs: hash256(&outputs),
+        }
+    }
+}
+
+fn signature_with_sighash(private_key: &PrivateKey, z: U256, sighash_type: SighashType) -> Vec<u8> {
+    let mut sig = private_key.sign(z).der();
+    sig.push(sighash_type.to_byte());
+    sig
+}
+
+fn sum_amounts(tx_outs: &[TxOut]) -> Result<u64, TxError> {
+    tx_outs
+        .iter()
+        .try_fold(0u64, |total, tx_out| total.checked_add(tx_out.amount))
+        .ok_or(TxError::AmountOverflow)
+}
+
+impl TxIn {
+    pub fn parse<R: Read>(reader: &mut R) -> Result<TxIn, ParseError> {
+        let mut prev_tx = [0u8; 32];
+        reader.read_exact(&mut prev_tx)?;
+        prev_tx.reverse();
+        let prev_index = read_u32(reader)?;
+        let script_sig = Script::parse(reader)?;
+        let sequence = read_u32(reader)?;
+        Ok(TxIn { prev_tx, prev_index, script_sig, sequence, witness: Vec::new() })
+    }
+
+    pub fn is_rbf_signaling(&self) -> bool {
+        self.sequence < SEQUENCE_LOCKTIME
+    }
+
+    // BIP68, which only applies to transactions of version 2 or above.
+    // None when the disable flag is set.
+    pub fn relative_locktime(&self) -> Option<RelativeLockTime> {
+        if self.sequence & SEQUENCE_DISABLE_FLAG != 0 {
+            return None;
+        }
+        let value = (self.sequence & SEQUENCE_MASK) as u16;
+        if self.sequence & SEQUENCE_TYPE_FLAG != 0 {
+            Some(RelativeLockTime::Time(value))
+        } else {
+            Some(RelativeLockTime::Blocks(value))
+        }
+    }
+
+    fn prev_output(&self, fetcher: &mut dyn TxFetcher, testnet: bool) -> Result<TxOut, FetchError> {
+        OutPoint::new(self.prev_tx, self.prev_index).fetch_output(fetcher, testnet)
+    }
+
+    pub fn value(&self, fetcher: &mut dyn TxFetcher, testnet: bool) -> Result<u64, FetchError> {
+        Ok(self.prev_output(fetcher, testnet)?.amount)
+    }
+
+    pub fn script_pubkey(&self, fetcher: &mut dyn TxFetcher, testnet: bool) -> Result<Script, FetchError> {
+        Ok(self.prev_output(fetcher, testnet)?.script_pubkey)
+    }
+
+    // The witness is not part of this serialization
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut prev_tx = self.prev_tx;
+        prev_tx.reverse();
+        let mut result = prev_tx.to_vec();
+        result.extend_from_slice(&self.prev_index.to_le_bytes());
+        result.extend(self.script_sig.serialize());
+        result.extend_from_slice(&self.sequence.to_le_bytes());
+        result
+    }
+}
+
+impl fmt::Display for TxIn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", encode_hex(&self.prev_tx), self.prev_index)
+    }
+}
+
+impl TxOut {
+    pub fn parse<R: Read>(reader: &mut R) -> Result<TxOut, ParseError> {
+        let amount = read_u64(reader)?;
+        let script_pubkey = Script::parse(reader)?;
+        Ok(TxOut { amount, script_pubkey })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.amount.to_le_bytes().to_vec();
+        result.extend(self.script_pubkey.serialize());
+        result
+    }
+
+    pub fn script_type(&self) -> ScriptType {
+        self.script_pubkey.script_type()
+    }
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    Io(io::Error),
+    Http(String),
+    Hex(HexError),
+    Json(serde_json::Error),
+    Parse(ParseError),
+    IdMismatch { expected: String, actual: String },
+    NotCached(String),
+    MissingOutput { tx_id: String, index: u32 },
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Io(err) => write!(f, "Cache file error: {}", err),
+            FetchError::Http(err) => write!(f, "HTTP request failed: {}", err),
+            FetchError::Hex(err) => write!(f, "Invalid transaction hex: {}", err),
+            FetchError::Json(err) => write!(f, "Invalid cache file: {}", err),
+            FetchError::Parse(err) => write!(f, "{}", err),
+            FetchError::IdMismatch { expected, actual } => {
+                write!(f, "Fetched transaction {} but requested {}", actual, expected)
+            }
+            FetchError::NotCached(tx_id) => write!(f, "Transaction {} is not in the cache", tx_id),
+            FetchError::MissingOutput { tx_id, index } => {
+                write!(f, "Transaction {} has no output {}", tx_id, index)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for FetchError {
+    fn from(err: io::Error) -> FetchError {
+        FetchError::Io(err)
+    }
+}
+
+impl From<HexError> for FetchError {
+    fn from(err: HexError) -> FetchError {
+        FetchError::Hex(err)
+    }
+}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(err: serde_json::Error) -> FetchError {
+        FetchError::Json(err)
+    }
+}
+
+impl From<ParseError> for FetchError {
+    fn from(err: ParseError) -> FetchError {
+        FetchError::Parse(err)
+    }
+}
+
+// Where previous transactions come from, looked up by txid in display
+// order. Implementations should check the transaction they return has
+// the requested id.
+pub trait TxFetcher {
+    fn fetch(&mut self, tx_id: &str, testnet: bool) -> Result<Tx, FetchError>;
+}
+
+// Looks up previous transactions from a blockstream.info style API,
+// keeping every transaction it has seen in memory keyed by txid. Without
+// the http feature it only serves what is in the cache.
+pub struct HttpFetcher {
+    pub mainnet_url: String,
+    pub testnet_url: String,
+    cache: HashMap<String, Tx>,
+}
+
+impl Default for HttpFetcher {
+    fn default() -> HttpFetcher {
+        HttpFetcher::new()
+    }
+}
+
+impl TxFetcher for HttpFetcher {
+    fn fetch(&mut self, tx_id: &str, testnet: bool) -> Result<Tx, FetchError> {
+        if !self.cache.contains_key(tx_id) {
+            return self.fetch_fresh(tx_id, testnet);
+        }
+        let mut tx = self.cache[tx_id].clone();
+        tx.testnet = testnet;
+        Ok(tx)
+    }
+}
+
+impl HttpFetcher {
+    pub fn new() -> HttpFetcher {
+        HttpFetcher {
+            mainnet_url: "https://blockstream.info/api".to_string(),
+            testnet_url: "https://blockstream.info/testnet/api".to_string(),
+            cache: HashMap::new(),
+        }
+    }
+
+    // Skips the cache, replacing whatever it held for tx_id
+    pub fn fetch_fresh(&mut self, tx_id: &str, testnet: bool) -> Result<Tx, FetchError> {
+        let tx = self.fetch_remote(tx_id, testnet)?;
+        self.cache.insert(tx_id.to_string(), tx.clone());
+        Ok(tx)
+    }
+
+    #[cfg(feature = "http")]
+    fn fetch_remote(&self, tx_id: &str, testnet: bool) -> Result<Tx, FetchError> {
+        let base_url = if testnet { &self.testnet_url } else { &self.mainnet_url };
+        let url = format!("{}/tx/{}/hex", base_url, tx_id);
+        let body = ureq::get(&url)
+            .call()
+            .map_err(|err| FetchError::Http(err.to_string()))?
+            .into_string()?;
+        parse_checked(tx_id, body.trim(), testnet)
+    }
+
+    #[cfg(not(feature = "http"))]
+    fn fetch_remote(&self, tx_id: &str, _testnet: bool) -> Result<Tx, FetchError> {
+        Err(FetchError::NotCached(tx_id.to_string()))
+    }
+
+    // The cache file is a JSON object mapping txid to raw transaction hex
+    pub fn load_cache<P: AsRef<Path>>(&mut self, path: P) -> Result<(), FetchError> {
+        let contents = fs::read_to_string(path)?;
+        let entries: HashMap<String, String> = serde_json::from_str(&contents)?;
+        for (tx_id, raw) in entries {
+            let tx = parse_checked(&tx_id, &raw, false)?;
+            self.cache.insert(tx_id, tx);
+        }
+        Ok(())
+    }
+
+    pub fn dump_cache<P: AsRef<Path>>(&self, path: P) -> Result<(), FetchError> {
+        let entries: BTreeMap<&String, String> =
+            self.cache.iter().map(|(tx_id, tx)| (tx_id, encode_hex(&tx.serialize()))).collect();
+        fs::write(path, serde_json::to_string_pretty(&entries)?)?;
+        Ok(())
+    }
+}
+
+// Never trust a server (or a cache file) to hand back the transaction we asked for
+fn parse_checked(tx_id: &str, raw_hex: &str, testnet: bool) -> Result<Tx, FetchError> {
+    let raw = decode_hex(raw_hex)?;
+    let tx = Tx::parse(&mut &raw[..], testnet)?;
+    if tx.id() != tx_id {
+        return Err(FetchError::IdMismatch { expected: tx_id.to_string(), actual: tx.id() });
+    }
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The chapter 5 transaction from Programming Bitcoin
+    const TX_HEX: &str = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
+
+    fn tx() -> Tx {
+        Tx::parse(&mut &decode_hex(TX_HEX).unwrap()[..], false).unwrap()
+    }
+
+    // The four-input transaction from the chapter 5 exercises
+    const MULTI_INPUT_TX_HEX: &str = "010000000456919960ac691763688d3d3bcea9ad6ecaf875df5339e148a1fc61c6ed7a069e010000006a47304402204585bcdef85e6b1c6af5c2669d4830ff86e42dd205c0e089bc2a821657e951c002201024a10366077f87d6bce1f7100ad8cfa8a064b39d4e8fe4ea13a7b71aa8180f012102f0da57e85eec2934a82a585ea337ce2f4998b50ae699dd79f5880e253dafafb7feffffffeb8f51f4038dc17e6313cf831d4f02281c2a468bde0fafd37f1bf882729e7fd3000000006a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937feffffff567bf40595119d1bb8a3037c356efd56170b64cbcc160fb028fa10704b45d775000000006a47304402204c7c7818424c7f7911da6cddc59655a70af1cb5eaf17c69dadbfc74ffa0b662f02207599e08bc8023693ad4e9527dc42c34210f7a7d1d1ddfc8492b654a11e7620a0012102158b46fbdff65d0172b7989aec8850aa0dae49abfb84c81ae6e5b251a58ace5cfeffffffd63a5e6c16e620f86f375925b21cabaf736c779f88fd04dcad51d26690f7f345010000006a47304402200633ea0d3314bea0d95b3cd8dadb2ef79ea8331ffe1e61f762c0f6daea0fabde022029f23b3e9c30f080446150b23852028751635dcee2be669c2a1686a4b5edf304012103ffd6f4a67e94aba353a00882e563ff2722eb4cff0ad6006e86ee20dfe7520d55feffffff0251430f00000000001976a914ab0c0b2e98b1ab6dbf67d4750b0a56244948a87988ac005a6202000000001976a9143c82d7df364eb6c75be8c80df2b3eda8db57397088ac46430600";
+
+    fn multi_input_tx() -> Tx {
+        Tx::parse(&mut &decode_hex(MULTI_INPUT_TX_HEX).unwrap()[..], false).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let tx = tx();
+        assert_eq!(tx.version, 1);
+        assert_eq!(tx.tx_ins.len(), 1);
+        assert_eq!(tx.tx_ins[0].prev_index, 0);
+        assert_eq!(tx.tx_ins[0].sequence, 0xfffffffe);
+        assert_eq!(tx.tx_outs.len(), 2);
+        assert_eq!(tx.tx_outs[0].amount, 32454049);
+        assert_eq!(tx.tx_outs[1].amount, 10011545);
+        assert_eq!(tx.locktime, 410393);
+        let tx = multi_input_tx();
+        assert_eq!(tx.tx_ins.len(), 4);
+        let script_sig = "6a47304402207899531a52d59a6de200179928ca900254a36b8dff8bb75f5f5d71b1cdc26125022008b422690b8461cb52c3cc30330b23d574351872b7c361e9aae3649071c1a7160121035d5c93d9ac96881f19ba1f686f15f009ded7c62efe85a872e6a19b43c15a2937";
+        assert_eq!(encode_hex(&tx.tx_ins[1].script_sig.serialize()), script_sig);
+        assert_eq!(tx.tx_ins[1].prev_index, 0);
+        assert_eq!(tx.tx_ins[3].prev_index, 1);
+        let script_pubkey = "1976a9143c82d7df364eb6c75be8c80df2b3eda8db57397088ac";
+        assert_eq!(encode_hex(&tx.tx_outs[1].script_pubkey.serialize()), script_pubkey);
+        assert_eq!(tx.tx_outs[0].amount, 1000273);
+        assert_eq!(tx.tx_outs[1].amount, 40000000);
+        assert_eq!(tx.id(), "ee51510d7bbabe28052038d1deb10c03ec74f06a79e21913c6fcf48d56217c87");
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let raw = decode_hex(TX_HEX).unwrap();
+        for len in 0..raw.len() {
+            assert!(Tx::parse(&mut &raw[..len], false).is_err(), "prefix of {} bytes", len);
+        }
+    }
+
+    // The first payment between two people, in mainnet block 170
+    const BLOCK170_TX_HEX: &str = "0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000";
+
+    #[test]
+    fn serialize_round_trip() {
+        let raw = decode_hex(TX_HEX).unwrap();
+        assert_eq!(tx().serialize(), raw);
+        let ids = [
+            (MULTI_INPUT_TX_HEX, "ee51510d7bbabe28052038d1deb10c03ec74f06a79e21913c6fcf48d56217c87"),
+            (BLOCK170_TX_HEX, "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16"),
+        ];
+        for (hex, id) in ids {
+            let raw = decode_hex(hex).unwrap();
+            let tx = Tx::parse(&mut &raw[..], false).unwrap();
+            assert_eq!(tx.serialize(), raw);
+            assert_eq!(tx.serialize_legacy(), raw);
+            assert_eq!(tx.id(), id);
+        }
+        let tx_out = TxOut { amount: 21_000_000 * 100_000_000, script_pubkey: Script::new(vec![]) };
+        assert_eq!(TxOut::parse(&mut &tx_out.serialize()[..]).unwrap(), tx_out);
+    }
+
+    #[test]
+    fn id_and_input_display() {
+        let tx = tx();
+        assert_eq!(tx.id(), "452c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03");
+        assert_eq!(encode_hex(&tx.hash()), tx.id());
+        assert_eq!(tx.tx_ins[0].to_string(), "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81:0");
+    }
+
+    fn cache_file(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ff-{}-{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn fetcher_cache_round_trip() {
+        let path = cache_file("cache");
+        fs::write(&path, format!("{{\"{}\": \"{}\"}}", tx().id(), TX_HEX)).unwrap();
+        let mut fetcher = HttpFetcher::new();
+        fetcher.load_cache(&path).unwrap();
+        assert_eq!(fetcher.fetch(&tx().id(), false).unwrap(), tx());
+        fetcher.dump_cache(&path).unwrap();
+        let mut reloaded = HttpFetcher::new();
+        reloaded.load_cache(&path).unwrap();
+        assert_eq!(reloaded.fetch(&tx().id(), false).unwrap(), tx());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fetcher_cache_checks_ids() {
+        let path = cache_file("mismatch");
+        let wrong_id = "552c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03";
+        fs::write(&path, format!("{{\"{}\": \"{}\"}}", wrong_id, TX_HEX)).unwrap();
+        assert!(matches!(HttpFetcher::new().load_cache(&path), Err(FetchError::IdMismatch { .. })));
+        fs::remove_file(&path).unwrap();
+    }
+
+    // The output the chapter 5 transaction spends
+    fn ch5_prevout() -> TxOut {
+        let script_pubkey = decode_hex("76a914a802fc56c704ce87c42d7c92eb75e7896bdc41ae88ac").unwrap();
+        TxOut { amount: 42505594, script_pubkey: Script::parse_raw(&script_pubkey).unwrap() }
+    }
+
+    // Serves a fixed set of transactions, never touching the network
+    struct MockFetcher(Vec<Tx>);
+
+    impl TxFetcher for MockFetcher {
+        fn fetch(&mut self, tx_id: &str, _testnet: bool) -> Result<Tx, FetchError> {
+            self.0.iter().find(|tx| tx.id() == tx_id).cloned().ok_or(FetchError::NotCached(tx_id.to_string()))
+        }
+    }
+
+    #[test]
+    fn fee() {
+        let tx = tx();
+        assert_eq!(tx.fee_with_prevouts(&[ch5_prevout()]).unwrap(), 40000);
+        let low = TxOut { amount: 1, ..ch5_prevout() };
+        assert!(matches!(tx.fee_with_prevouts(&[low]), Err(TxError::NegativeFee { .. })));
+        assert!(matches!(tx.fee_with_prevouts(&[]), Err(TxError::PrevoutCount { expected: 1, actual: 0 })));
+        assert!(matches!(tx.fee(&mut MockFetcher(vec![])), Err(TxError::Fetch(_))));
+    }
+
+    #[test]
+    fn legacy_sig_hash() {
+        let tx = tx();
+        let z = tx.sig_hash(0, &[ch5_prevout()], None, SighashType::All).unwrap();
+        assert_eq!(format!("{:x}", z), "27e0c5994dec7824e56dec6b2fcb342eb7cdb0d0957c2fce9882f715e85d81a6");
+        assert!(matches!(tx.sig_hash(1, &[ch5_prevout()], None, SighashType::All), Err(TxError::InputIndex(1))));
+    }
+
+    #[test]
+    fn legacy_sig_hash_multiple_inputs() {
+        let tx = multi_input_tx();
+        // Every input is p2pkh, so its script_pubkey follows from the revealed key
+        let prevouts: Vec<TxOut> = tx
+            .tx_ins
+            .iter()
+            .map(|tx_in| match &tx_in.script_sig.cmds[..] {
+                [_, Cmd::Data(sec)] => TxOut { amount: 0, script_pubkey: p2pkh_script(&hash160(sec)) },
+                _ => panic!("not a p2pkh script_sig"),
+            })
+            .collect();
+        let mut unsigned = tx.clone();
+        for tx_in in &mut unsigned.tx_ins {
+            tx_in.script_sig = Script::new(vec![]);
+        }
+        for (index, tx_in) in tx.tx_ins.iter().enumerate() {
+            let z = tx.sig_hash(index, &prevouts, None, SighashType::All).unwrap();
+            let [Cmd::Data(der), Cmd::Data(sec)] = &tx_in.script_sig.cmds[..] else {
+                panic!("not a p2pkh script_sig");
+            };
+            let sig = Signature::parse(&der[..der.len() - 1]).unwrap();
+            assert!(S256Point::parse(sec).unwrap().verify(z, &sig));
+            // The other inputs go in with empty script_sigs, whatever they
+            // hold or spend
+            assert_eq!(unsigned.sig_hash(index, &prevouts, None, SighashType::All).unwrap(), z);
+            let mut others = prevouts.clone();
+            for (other, prevout) in others.iter_mut().enumerate() {
+                if other != index {
+                    prevout.script_pubkey = Script::new(vec![]);
+                }
+            }
+            assert_eq!(tx.sig_hash(index, &others, None, SighashType::All).unwrap(), z);
+            let mut swapped = prevouts.clone();
+            swapped[index] = prevouts[(index + 1) % prevouts.len()].clone();
+            assert_ne!(tx.sig_hash(index, &swapped, None, SighashType::All).unwrap(), z);
+        }
+    }
+
+    #[test]
+    fn verify_p2pkh() {
+        let tx = tx();
+        assert!(tx.verify_input(0, &[ch5_prevout()]).unwrap());
+        assert!(tx.verify(&[ch5_prevout()]).unwrap());
+        let tampered = Tx { locktime: tx.locktime + 1, ..tx.clone() };
+        assert!(!tampered.verify(&[ch5_prevout()]).unwrap());
+        assert!(matches!(tx.verify_input(1, &[ch5_prevout()]), Err(TxError::InputIndex(1))));
+    }
+
+    #[test]
+    fn verify_malformed_redeem_script() {
+        // OP_PUSHDATA1 with no length byte does not parse
+        let redeem_script = vec![0x4c];
+        let h160 = hash160(&redeem_script);
+        let prevouts = [TxOut { amount: 1000, script_pubkey: crate::script::p2sh_script(&h160) }];
+        let mut tx = tx();
+        tx.tx_ins[0].script_sig = Script::new(vec![Cmd::Data(redeem_script)]);
+        assert!(!tx.verify_input(0, &prevouts).unwrap());
+        assert!(!tx.verify(&prevouts).unwrap());
+    }
+
+    #[test]
+    fn sign_p2pkh_input() {
+        // The testnet transaction from chapter 7
+        let key = PrivateKey::new(U256::from_u64(8675309));
+        let unsigned = decode_hex("010000000199a24308080ab26e6fb65c4eccfadf76749bb5bfa8cb08f291320b3c21e56f0d0d00000000ffffffff02408af701000000001976a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac80969800000000001976a914507b27411ccf7f16f10297de6cef3f291623eddf88ac00000000").unwrap();
+        let mut tx = Tx::parse(&mut &unsigned[..], true).unwrap();
+        let prevouts = [TxOut { amount: 100_000_000, script_pubkey: p2pkh_script(&hash160(&key.point.sec(true))) }];
+        assert!(tx.sign_input(0, &prevouts, &key, SighashType::All).unwrap());
+        assert_eq!(encode_hex(&tx.serialize()), "010000000199a24308080ab26e6fb65c4eccfadf76749bb5bfa8cb08f291320b3c21e56f0d0d0000006b4830450221008ed46aa2cf12d6d81065bfabe903670165b538f65ee9a3385e6327d80c66d3b502203124f804410527497329ec4715e18558082d489b218677bd029e7fa306a72236012103935581e52c354cd2f484fe8ed83af7a3097005b2f9c60bff71d35bd795f54b67ffffffff02408af701000000001976a914d52ad7ca9b3d096a38e752c2018e6fbc40cdf26f88ac80969800000000001976a914507b27411ccf7f16f10297de6cef3f291623eddf88ac00000000");
+        assert!(tx.verify(&prevouts).unwrap());
+        let other_key = PrivateKey::new(U256::from_u64(5));
+        assert!(tx.sign_input(0, &prevouts, &other_key, SighashType::All).is_err());
+    }
+
+    // The signed native p2wpkh example from BIP143
+    const SEGWIT_TX_HEX: &str = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
+
+    #[test]
+    fn segwit_round_trip() {
+        let raw = decode_hex(SEGWIT_TX_HEX).unwrap();
+        let segwit = Tx::parse(&mut &raw[..], false).unwrap();
+        assert_eq!(segwit.tx_ins.len(), 2);
+        assert!(segwit.tx_ins[0].witness.is_empty());
+        assert_eq!(segwit.tx_ins[1].witness.len(), 2);
+        assert_eq!(segwit.serialize(), raw);
+        assert_ne!(segwit.id(), segwit.wtxid());
+        let legacy = segwit.serialize_legacy();
+        assert_eq!(Tx::parse(&mut &legacy[..], false).unwrap().id(), segwit.id());
+        assert_eq!(tx().id(), tx().wtxid());
+        let raw = decode_hex(P2SH_P2WSH_TX_HEX).unwrap();
+        let p2sh_p2wsh = Tx::parse(&mut &raw[..], false).unwrap();
+        // A dummy, six signatures and the witness script
+        assert_eq!(p2sh_p2wsh.tx_ins[0].witness.len(), 8);
+        assert_eq!(p2sh_p2wsh.serialize(), raw);
+        assert_eq!(p2sh_p2wsh.id(), "27eae69aff1dd4388c0fa05cbbfe9a3983d1b0b5811ebcd4199b86f299370aac");
+        assert_eq!(p2sh_p2wsh.wtxid(), "65dab5dd46a501fc695822c73d779067f2feb7c49dc47d39f86fdb2e3960b3bd");
+    }
+
+    // The signed 6-of-6 p2sh-p2wsh example from BIP143, one signature of
+    // each sighash type
+    const P2SH_P2WSH_TX_HEX: &str = "0100000000010136641869ca081e70f394c6948e8af409e18b619df2ed74aa106c1ca29787b96e0100000023220020a16b5755f7f6f96dbd65f5f0d6ab9418b89af4b1f14a1bb8a09062c35f0dcb54ffffffff0200e9a435000000001976a914389ffce9cd9ae88dcc0631e88a821ffdbe9bfe2688acc0832f05000000001976a9147480a33f950689af511e6e84c138dbbd3c3ee41588ac080047304402206ac44d672dac41f9b00e28f4df20c52eeb087207e8d758d76d92c6fab3b73e2b0220367750dbbe19290069cba53d096f44530e4f98acaa594810388cf7409a1870ce01473044022068c7946a43232757cbdf9176f009a928e1cd9a1a8c212f15c1e11ac9f2925d9002205b75f937ff2f9f3c1246e547e54f62e027f64eefa2695578cc6432cdabce271502473044022059ebf56d98010a932cf8ecfec54c48e6139ed6adb0728c09cbe1e4fa0915302e022007cd986c8fa870ff5d2b3a89139c9fe7e499259875357e20fcbb15571c76795403483045022100fbefd94bd0a488d50b79102b5dad4ab6ced30c4069f1eaa69a4b5a763414067e02203156c6a5c9cf88f91265f5a942e96213afae16d83321c8b31bb342142a14d16381483045022100a5263ea0553ba89221984bd7f0b13613db16e7a70c549a86de0cc0444141a407022005c360ef0ae5a5d4f9f2f87a56c1546cc8268cab08c73501d6b3be2e1e1a8a08824730440220525406a1482936d5a21888260dc165497a90a15669636d8edca6b9fe490d309c022032af0c646a34a44d1f4576bf6a4a74b67940f8faa84c7df9abe12a01a11e2b4783cf56210307b8ae49ac90a048e9b53357a2354b3334e9c8bee813ecb98e99a7e07e8c3ba32103b28f0c28bfab54554ae8c658ac5c3e0ce6e79ad336331f78c428dd43eea8449b21034b8113d703413d57761b8b9781957b8c0ac1dfe69f492580ca4195f50376ba4a21033400f6afecb833092a9a21cfdf1ed1376e58c5d1f47de74683123987e967a8f42103a6d48b1131e94ba04d9737d61acdaa1322008af9602b3b14862c07a1789aac162102d8b661b0b3302ee2f162b09e07a55ad5dfbe673a9f01d9f0c19617681024306b56ae00000000";
+    // The signed p2sh-p2wpkh example from BIP143
+    const P2SH_P2WPKH_TX_HEX: &str = "01000000000101db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a5477010000001716001479091972186c449eb1ded22b78e40d009bdf0089feffffff02b8b4eb0b000000001976a914a457b684d7f0d539a46a45bbc043f35b59d0d96388ac0008af2f000000001976a914fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c88ac02473044022047ac8e878352d3ebbde1c94ce3a10d057c24175747116f8288e5d794d12d482f0220217f36a485cae903c713331d877c1f64677e3622ad4010726870540656fe9dcb012103ad1d8e89212f0b92c74d23bb710c00662ad1470198ac48c43f7d6f93a2a2687392040000";
+
+    fn script(hex: &str) -> Script {
+        Script::parse_raw(&decode_hex(hex).unwrap()).unwrap()
+    }
+
+    fn key(hex: &str) -> PrivateKey {
+        PrivateKey::new(U256::from_be_slice(&decode_hex(hex).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn bip143_native_p2wpkh() {
+        let unsigned = decode_hex("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").unwrap();
+        let mut tx = Tx::parse(&mut &unsigned[..], false).unwrap();
+        let prevouts = [
+            TxOut { amount: 625000000, script_pubkey: script("2103c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432ac") },
+            TxOut { amount: 600000000, script_pubkey: script("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1") },
+        ];
+        let hashes = Bip143Hashes::new(&tx);
+        assert_eq!(encode_hex(&hashes.hash_prevouts), "96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37");
+        assert_eq!(encode_hex(&hashes.hash_sequence), "52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b");
+        assert_eq!(encode_hex(&hashes.hash_outputs), "863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5");
+        let z = tx.sig_hash_bip143(1, &prevouts, None, None, SighashType::All).unwrap();
+        assert_eq!(format!("{:x}", z), "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670");
+        let key = key("619c335025c7f4012e556c2a58b2506e30b8511b53ade95ea316fd8c3286feb9");
+        assert!(tx.sign_input(1, &prevouts, &key, SighashType::All).unwrap());
+        assert_eq!(encode_hex(&tx.tx_ins[1].witness[0]), "304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee01");
+        let signed = Tx::parse(&mut &decode_hex(SEGWIT_TX_HEX).unwrap()[..], false).unwrap();
+        assert!(signed.verify_input(0, &prevouts).unwrap());
+        assert!(signed.verify_input(1, &prevouts).unwrap());
+        assert!(signed.verify(&prevouts).unwrap());
+    }
+
+    #[test]
+    fn bip143_p2sh_p2wpkh() {
+        let unsigned = decode_hex("0100000001db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a54770100000000feffffff02b8b4eb0b000000001976a914a457b684d7f0d539a46a45bbc043f35b59d0d96388ac0008af2f000000001976a914fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c88ac92040000").unwrap();
+        let mut tx = Tx::parse(&mut &unsigned[..], false).unwrap();
+        let prevouts = [TxOut { amount: 1000000000, script_pubkey: script("a9144733f37cf4db86fbc2efed2500b4f4e49f31202387") }];
+        let redeem_script = script("001479091972186c449eb1ded22b78e40d009bdf0089");
+        let z = tx.sig_hash_bip143(0, &prevouts, Some(&redeem_script), None, SighashType::All).unwrap();
+        assert_eq!(format!("{:x}", z), "64f3b0f4dd2bb3aa1ce8566d220cc74dda9df97d8490cc81d89d735c92e59fb6");
+        let key = key("eb696a065ef48a2192da5b28b694f87544b30fae8327c4510137a922f32c6dcf");
+        assert!(tx.sign_input(0, &prevouts, &key, SighashType::All).unwrap());
+        assert_eq!(encode_hex(&tx.serialize()), P2SH_P2WPKH_TX_HEX);
+        let mut tampered = tx.clone();
+        tampered.tx_outs[0].amount += 1;
+        assert!(!tampered.verify_input(0, &prevouts).unwrap());
+    }
+
+    #[test]
+    fn truncated_prefixes_are_eof() {
+        for hex in [TX_HEX, P2SH_P2WPKH_TX_HEX] {
+            let raw = decode_hex(hex).unwrap();
+            for len in 0..raw.len() {
+                assert!(matches!(Tx::parse(&mut &raw[..len], false), Err(ParseError::UnexpectedEof)), "prefix of {} bytes", len);
+            }
+            assert!(Tx::parse(&mut &raw[..], false).is_ok());
+        }
+    }
+
+    #[test]
+    fn corrupt_bytes_do_not_panic() {
+        let raw = decode_hex(TX_HEX).unwrap();
+        for i in 0..raw.len() {
+            for byte in [0x00, 0xff, 0x4e, 0xfe] {
+                let mut corrupt = raw.clone();
+                corrupt[i] = byte;
+                let _ = Tx::parse(&mut &corrupt[..], false);
+            }
+        }
+    }
+
+    // Two p2pkh inputs under different keys paying one output
+    fn two_input_builder(key1: &PrivateKey, key2: &PrivateKey) -> crate::builder::TxBuilder {
+        let mut builder = crate::builder::TxBuilder::new(true);
+        let prevout = |key: &PrivateKey, amount| TxOut { amount, script_pubkey: p2pkh_script(&hash160(&key.point.sec(true))) };
+        builder
+            .add_input(OutPoint::new([1; 32], 0), prevout(key1, 100000))
+            .add_input(OutPoint::new([2; 32], 3), prevout(key2, 50000))
+            .add_output(key1.point.address(true, true).as_str(), 120000);
+        builder
+    }
+
+    #[test]
+    fn legacy_sighash_types() {
+        let key1 = PrivateKey::new(U256::from_u64(8675309));
+        let key2 = PrivateKey::new(U256::from_u64(12345));
+        let builder = two_input_builder(&key1, &key2);
+        let prevouts = builder.prevouts();
+        for sighash_type in [
+            SighashType::None,
+            SighashType::Single,
+            SighashType::AllAnyoneCanPay,
+            SighashType::NoneAnyoneCanPay,
+            SighashType::SingleAnyoneCanPay,
+        ] {
+            let mut tx = builder.build().unwrap();
+            assert!(tx.sign_input(0, &prevouts, &key1, sighash_type).unwrap());
+            assert!(tx.sign_input(1, &prevouts, &key2, sighash_type).unwrap());
+            assert!(tx.verify(&prevouts).unwrap());
+            // NONE leaves the outputs free, SINGLE commits input 1 to nothing
+            let mut changed_output = tx.clone();
+            changed_output.tx_outs[0].amount -= 1;
+            match sighash_type.base() {
+                SighashType::None => assert!(changed_output.verify(&prevouts).unwrap()),
+                SighashType::Single => {
+                    assert!(!changed_output.verify_input(0, &prevouts).unwrap());
+                    assert!(changed_output.verify_input(1, &prevouts).unwrap());
+                }
+                _ => assert!(!changed_output.verify(&prevouts).unwrap()),
+            }
+            // ANYONECANPAY lets the other inputs go
+            if sighash_type.anyone_can_pay() {
+                let mut dropped_input = tx.clone();
+                dropped_input.tx_ins.remove(1);
+                assert!(dropped_input.verify_input(0, &prevouts[..1]).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn bip143_example_sighash_types() {
+        let tx = Tx::parse(&mut &decode_hex(P2SH_P2WSH_TX_HEX).unwrap()[..], false).unwrap();
+        let witness = &tx.tx_ins[0].witness;
+        let witness_script = Script::parse_raw(&witness[7]).unwrap();
+        let redeem_script = decode_hex("0020a16b5755f7f6f96dbd65f5f0d6ab9418b89af4b1f14a1bb8a09062c35f0dcb54").unwrap();
+        let script_pubkey = crate::script::p2sh_script(&hash160(&redeem_script));
+        let prevouts = [TxOut { amount: 987654321, script_pubkey }];
+        let signatures = &witness[1..7];
+        let sighash_types: Vec<SighashType> =
+            signatures.iter().map(|der| SighashType::from_byte(*der.last().unwrap()).unwrap()).collect();
+        let expected = [
+            SighashType::All,
+            SighashType::None,
+            SighashType::Single,
+            SighashType::AllAnyoneCanPay,
+            SighashType::NoneAnyoneCanPay,
+            SighashType::SingleAnyoneCanPay,
+        ];
+        assert_eq!(sighash_types, expected);
+        // Signatures are in the same order as the keys
+        for ((der, sighash_type), sec) in signatures.iter().zip(sighash_types).zip(&witness_script.cmds[1..7]) {
+            let Cmd::Data(sec) = sec else {
+                panic!("not a key");
+            };
+            let z = tx.sig_hash_bip143(0, &prevouts, None, Some(&witness_script), sighash_type).unwrap();
+            let sig = Signature::parse(&der[..der.len() - 1]).unwrap();
+            assert!(S256Point::parse(sec).unwrap().verify(z, &sig));
+            let z = tx.sig_hash_bip143(0, &prevouts, None, Some(&witness_script), SighashType::All).unwrap();
+            assert_eq!(S256Point::parse(sec).unwrap().verify(z, &sig), sighash_type == SighashType::All);
+        }
+    }
+
+    #[test]
+    fn sighash_single_without_output() {
+        let key1 = PrivateKey::new(U256::from_u64(8675309));
+        let key2 = PrivateKey::new(U256::from_u64(12345));
+        let builder = two_input_builder(&key1, &key2);
+        let prevouts = builder.prevouts();
+        let mut tx = builder.build().unwrap();
+        assert_eq!(tx.sig_hash(1, &prevouts, None, SighashType::Single).unwrap(), U256::ONE << 248);
+        assert!(tx.sign_input(1, &prevouts, &key2, SighashType::Single).unwrap());
+    }
+
+    #[test]
+    fn bip143_sighash_types() {
+        let key = PrivateKey::new(U256::from_u64(8675309));
+        let builder = two_input_builder(&key, &key);
+        let program = Script::new(vec![Cmd::Op(OpCode::OP_0), Cmd::Data(hash160(&key.point.sec(true)).to_vec())]);
+        let prevouts = [
+            TxOut { amount: 100000, script_pubkey: program.clone() },
+            TxOut { amount: 50000, script_pubkey: program },
+        ];
+        for sighash_type in [
+            SighashType::All,
+            SighashType::None,
+            SighashType::Single,
+            SighashType::AllAnyoneCanPay,
+            SighashType::NoneAnyoneCanPay,
+            SighashType::SingleAnyoneCanPay,
+        ] {
+            let mut tx = builder.build().unwrap();
+            assert!(tx.sign_input(0, &prevouts, &key, sighash_type).unwrap());
+            assert!(tx.sign_input(1, &prevouts, &key, sighash_type).unwrap());
+            assert!(tx.verify(&prevouts).unwrap());
+        }
+    }
+
+    #[test]
+    fn coinbase_height() {
+        let raw = decode_hex("01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff5e03d71b07254d696e656420627920416e74506f6f6c20626a31312f4542312f4144362f43205914293101fabe6d6d678e2c8c34afc36896e7d9402824ed38e856676ee94bfdb0c6c4bcd8b2e5666a0400000000000000c7270000a5e00e00ffffffff01faf20b58000000001976a914338c84849423992471bffb1a54a8d9b1d69dc28a88ac00000000").unwrap();
+        let coinbase = Tx::parse(&mut &raw[..], false).unwrap();
+        assert!(coinbase.is_coinbase());
+        assert_eq!(coinbase.coinbase_height(), Some(465879));
+        assert!(!tx().is_coinbase());
+        assert_eq!(tx().coinbase_height(), None);
+        // The genesis coinbase predates BIP34, so its first push is not a height
+        let raw = decode_hex("01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000").unwrap();
+        let genesis = Tx::parse(&mut &raw[..], false).unwrap();
+        assert_eq!(genesis.id(), "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b");
+        assert_eq!(genesis.coinbase_height(), Some(486604799));
+    }
+
+    #[test]
+    fn locktimes_and_rbf() {
+        let mut tx = tx();
+        assert_eq!(tx.tx_ins[0].sequence, 0xfffffffe);
+        assert_eq!(tx.locktime_kind(), LockTime::BlockHeight(410393));
+        assert!(!tx.signals_rbf());
+        tx.tx_ins[0].sequence = 0xfffffffd;
+        assert!(tx.signals_rbf());
+        tx.tx_ins[0].sequence = 0xffffffff;
+        assert_eq!(tx.locktime_kind(), LockTime::Disabled);
+        tx.tx_ins[0].sequence = 0;
+        tx.locktime = 1_600_000_000;
+        assert_eq!(tx.locktime_kind(), LockTime::UnixTime(1_600_000_000));
+        let multi_input = multi_input_tx();
+        assert_eq!(multi_input.locktime_kind(), LockTime::BlockHeight(410438));
+        assert!(!multi_input.signals_rbf());
+        // The first input of the BIP143 p2wpkh example opts in to replacement
+        let segwit = Tx::parse(&mut &decode_hex(SEGWIT_TX_HEX).unwrap()[..], false).unwrap();
+        assert_eq!(segwit.tx_ins[0].sequence, 0xffffffee);
+        assert!(segwit.signals_rbf());
+        assert_eq!(segwit.locktime_kind(), LockTime::BlockHeight(17));
+        assert_eq!(segwit.tx_ins[0].relative_locktime(), None);
+    }
+
+    #[test]
+    fn relative_locktimes() {
+        let mut tx_in = tx().tx_ins[0].clone();
+        tx_in.sequence = 144;
+        assert_eq!(tx_in.relative_locktime(), Some(RelativeLockTime::Blocks(144)));
+        tx_in.sequence = (1 << 22) | 10;
+        assert_eq!(tx_in.relative_locktime(), Some(RelativeLockTime::Time(10)));
+        tx_in.sequence = 0xffffffff;
+        assert_eq!(tx_in.relative_locktime(), None);
+    }
+
+    #[test]
+    fn size_weight_vsize() {
+        let legacy = tx();
+        assert_eq!(legacy.size(), 226);
+        assert_eq!(legacy.weight(), 904);
+        assert_eq!(legacy.vsize(), 226);
+        assert_eq!(legacy.fee_rate(40000), 40000.0 / 226.0);
+        let segwit = Tx::parse(&mut &decode_hex(P2SH_P2WPKH_TX_HEX).unwrap()[..], false).unwrap();
+        assert_eq!(segwit.size(), 251);
+        assert_eq!(segwit.serialize_legacy().len(), 142);
+        assert_eq!(segwit.weight(), 677);
+        assert_eq!(segwit.vsize(), 170);
+    }
+
+    // Ten inputs alternating p2wpkh and p2pkh, some of the p2pkh keys
+    // uncompressed, all signed
+    fn batch_tx() -> (Tx, Vec<TxOut>, Vec<PrivateKey>) {
+        let keys: Vec<PrivateKey> = (1..=10).map(|i| PrivateKey::new(U256::from_u64(1000 + i * 7919))).collect();
+        let mut builder = crate::builder::TxBuilder::new(true);
+        for (i, key) in keys.iter().enumerate() {
+            let script_pubkey = if i % 2 == 0 {
+                Script::new(vec![Cmd::Op(OpCode::OP_0), Cmd::Data(hash160(&key.point.sec(true)).to_vec())])
+            } else {
+                p2pkh_script(&hash160(&key.point.sec(i % 3 == 0)))
+            };
+            builder.add_input(OutPoint::new([i as u8 + 1; 32], 0), TxOut { amount: 10000, script_pubkey });
+        }
+        builder.add_output(keys[0].point.address(true, true).as_str(), 50000).fee_rate(1.0);
+        (builder.sign(&keys).unwrap(), builder.prevouts(), keys)
+    }
+
+    #[test]
+    fn batched_verification() {
+        let (tx, prevouts, keys) = batch_tx();
+        assert!(tx.verify(&prevouts).unwrap());
+        assert!(tx.verify_batched(&prevouts).unwrap());
+        assert_eq!(tx.first_invalid_input(&prevouts).unwrap(), None);
+        for bad in [3, 6] {
+            let mut der = keys[bad].sign(U256::from_u64(42)).der();
+            der.push(SighashType::All.to_byte());
+            let mut tampered = tx.clone();
+            if bad % 2 == 0 {
+                tampered.tx_ins[bad].witness[0] = der;
+            } else {
+                tampered.tx_ins[bad].script_sig.cmds[0] = Cmd::Data(der);
+            }
+            assert!(!tampered.verify(&prevouts).unwrap());
+            assert!(!tampered.verify_batched(&prevouts).unwrap());
+            assert_eq!(tampered.first_invalid_input(&prevouts).unwrap(), Some(bad));
+        }
+    }
+
+    #[test]
+    fn batched_verification_accepts_high_s() {
+        let (mut tx, prevouts, _) = batch_tx();
+        let Cmd::Data(der) = &tx.tx_ins[1].script_sig.cmds[0] else { panic!("p2pkh script_sig starts with a push") };
+        let mut sig = Signature::parse(&der[..der.len() - 1]).unwrap();
+        sig.s = crate::ecc::N - sig.s;
+        let mut der = sig.der();
+        der.push(SighashType::All.to_byte());
+        tx.tx_ins[1].script_sig.cmds[0] = Cmd::Data(der);
+        assert!(tx.verify(&prevouts).unwrap());
+        assert!(tx.verify_batched(&prevouts).unwrap());
+    }
+
+    #[test]
+    fn fee_through_fetcher() {
+        let prev_tx = Tx { version: 1, tx_ins: vec![], tx_outs: vec![ch5_prevout()], locktime: 0, testnet: false };
+        let mut spend = tx();
+        spend.tx_ins[0].prev_tx = decode_hex(&prev_tx.id()).unwrap().try_into().unwrap();
+        spend.tx_ins[0].prev_index = 0;
+        assert_eq!(spend.fee(&mut MockFetcher(vec![prev_tx.clone()])).unwrap(), 40000);
+        match tx().fee(&mut MockFetcher(vec![prev_tx])) {
+            Err(TxError::Fetch(err)) => assert!(matches!(*err, FetchError::NotCached(_))),
+            other => panic!("expected a fetch error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn verify_p2sh_multisig() {
+        // The 2-of-2 p2sh spend from chapter 8
+        let raw = decode_hex("0100000001868278ed6ddfb6c1ed3ad5f8181eb0c7a385aa0836f01d5e4789e6bd304d87221a000000db00483045022100dc92655fe37036f47756db8102e0d7d5e28b3beb83a8fef4f5dc0559bddfb94e02205a36d4e4e6c7fcd16658c50783e00c341609977aed3ad00937bf4ee942a8993701483045022100da6bee3c93766232079a01639d07fa869598749729ae323eab8eef53577d611b02207bef15429dcadce2121ea07f233115c6f09034c0be68db99980b9a6c5e75402201475221022626e955ea6ea6d98850c994f9107b036b1334f18ca8830bfff1295d21cfdb702103b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb7152aeffffffff04d3b11400000000001976a914904a49878c0adfc3aa05de7afad2cc15f483a56a88ac7f400900000000001976a914418327e3f3dda4cf5b9089325a4b95abdfa0334088ac722c0c00000000001976a914ba35042cfe9fc66fd35ac2224eebdafd1028ad2788acdc4ace020000000017a91474d691da1574e6b3c192ecfb52cc8984ee7b6c568700000000").unwrap();
+        let tx = Tx::parse(&mut &raw[..], false).unwrap();
+        let mut h160: [u8; 20] = decode_hex("74d691da1574e6b3c192ecfb52cc8984ee7b6c56").unwrap().try_into().unwrap();
+        assert_eq!(crate::util::h160_to_p2sh_address(&h160, false), "3CLoMMyuoDQTPRD3XYZtCvgvkadrAdvdXh");
+        let prevouts = [TxOut { amount: 0, script_pubkey: crate::script::p2sh_script(&h160) }];
+        assert!(tx.verify_input(0, &prevouts).unwrap());
+        h160[0] ^= 1;
+        let prevouts = [TxOut { amount: 0, script_pubkey: crate::script::p2sh_script(&h160) }];
+        assert!(!tx.verify_input(0, &prevouts).unwrap());
+    }
+
+    #[test]
+    fn verify_with_fetcher() {
+        let key = PrivateKey::new(U256::from_u64(12345));
+        let script_pubkey = p2pkh_script(&hash160(&key.point.sec(true)));
+        let prev_tx = Tx {
+            version: 1,
+            tx_ins: vec![],
+            tx_outs: vec![TxOut { amount: 10000, script_pubkey: script_pubkey.clone() }],
+            locktime: 0,
+            testnet: false,
+        };
+        let tx_in = TxIn {
+            prev_tx: decode_hex(&prev_tx.id()).unwrap().try_into().unwrap(),
+            prev_index: 0,
+            script_sig: Script::default(),
+            sequence: 0xffffffff,
+            witness: vec![],
+        };
+        let mut tx = Tx {
+            version: 1,
+            tx_ins: vec![tx_in],
+            tx_outs: vec![TxOut { amount: 9000, script_pubkey }],
+            locktime: 0,
+            testnet: false,
+        };
+        let mut fetcher = MockFetcher(vec![prev_tx]);
+        let prevouts = tx.prevouts(&mut fetcher).unwrap();
+        assert!(tx.sign_input(0, &prevouts, &key, SighashType::All).unwrap());
+        assert_eq!(tx.fee(&mut fetcher).unwrap(), 1000);
+        assert!(tx.verify_with_fetcher(&mut fetcher).unwrap());
+        tx.tx_outs[0].amount = 20000;
+        assert!(matches!(tx.fee(&mut fetcher), Err(TxError::NegativeFee { .. })));
+        assert!(!tx.verify_with_fetcher(&mut fetcher).unwrap());
+        assert!(tx.verify_with_fetcher(&mut MockFetcher(vec![])).is_err());
+    }
+
+    // Spends a single output of 50000, paying 40000 to an empty script
+    fn spending_tx() -> Tx {
+        let tx_in =
+            TxIn { prev_tx: [7; 32], prev_index: 1, script_sig: Script::default(), sequence: 0xffffffff, witness: vec![] };
+        let tx_out = TxOut { amount: 40000, script_pubkey: Script::default() };
+        Tx { version: 2, tx_ins: vec![tx_in], tx_outs: vec![tx_out], locktime: 0, testnet: false }
+    }
+
+    fn p2wsh(witness_script: &Script) -> Script {
+        Script::new(vec![Cmd::Op(OpCode::OP_0), Cmd::Data(sha256(&witness_script.raw_serialize()).to_vec())])
+    }
+
+    #[test]
+    fn verify_p2wsh_multisig() {
+        let keys: Vec<PrivateKey> = (1..=3).map(|i| PrivateKey::new(U256::from_u64(i * 31337))).collect();
+        let points: Vec<S256Point> = keys.iter().map(|key| key.point).collect();
+        let witness_script = crate::script::multisig_script(2, &points).unwrap();
+        let native = p2wsh(&witness_script);
+        assert!(native.is_p2wsh_script_pubkey());
+        let wrapped = crate::script::p2sh_script(&hash160(&native.raw_serialize()));
+        for (script_pubkey, redeem_script) in [(native.clone(), None), (wrapped, Some(&native))] {
+            let prevouts = [TxOut { amount: 50000, script_pubkey }];
+            let mut tx = spending_tx();
+            if let Some(redeem_script) = redeem_script {
+                tx.tx_ins[0].script_sig = Script::new(vec![Cmd::Data(redeem_script.raw_serialize())]);
+            }
+            let z = tx.sig_hash_bip143(0, &prevouts, redeem_script, Some(&witness_script), SighashType::All).unwrap();
+            let sig = |key: &PrivateKey| [key.sign(z).der(), vec![SighashType::All.to_byte()]].concat();
+            tx.tx_ins[0].witness = vec![vec![], sig(&keys[0]), sig(&keys[2]), witness_script.raw_serialize()];
+            assert!(tx.verify_input(0, &prevouts).unwrap());
+            assert!(tx.verify(&prevouts).unwrap());
+            assert!(tx.verify_batched(&prevouts).unwrap());
+            let mut out_of_order = tx.clone();
+            out_of_order.tx_ins[0].witness = vec![vec![], sig(&keys[2]), sig(&keys[0]), witness_script.raw_serialize()];
+            assert!(!out_of_order.verify_input(0, &prevouts).unwrap());
+            let mut wrong_script = tx.clone();
+            wrong_script.tx_ins[0].witness.last_mut().unwrap()[0] ^= 1;
+            assert!(!wrong_script.verify_input(0, &prevouts).unwrap());
+            let mut no_witness = tx.clone();
+            no_witness.tx_ins[0].witness.clear();
+            assert!(!no_witness.verify_input(0, &prevouts).unwrap());
+        }
+    }
+
+    #[test]
+    fn verify_bip143_p2sh_p2wsh() {
+        let tx = Tx::parse(&mut &decode_hex(P2SH_P2WSH_TX_HEX).unwrap()[..], false).unwrap();
+        let redeem_script = script("0020a16b5755f7f6f96dbd65f5f0d6ab9418b89af4b1f14a1bb8a09062c35f0dcb54");
+        assert!(redeem_script.is_p2wsh_script_pubkey());
+        let script_pubkey = crate::script::p2sh_script(&hash160(&redeem_script.raw_serialize()));
+        let prevouts = [TxOut { amount: 987654321, script_pubkey }];
+        assert!(tx.verify_input(0, &prevouts).unwrap());
+        assert!(tx.verify(&prevouts).unwrap());
+        let mut corrupt = tx.clone();
+        corrupt.tx_ins[0].witness[6][10] ^= 1;
+        assert!(!corrupt.verify_input(0, &prevouts).unwrap());
+        let wrong_amount = [TxOut { amount: 987654320, ..prevouts[0].clone() }];
+        assert!(!tx.verify_input(0, &wrong_amount).unwrap());
+    }
+
+    #[test]
+    fn witness_needs_clean_stack() {
+        // OP_1 leaves the extra witness item underneath it
+        let witness_script = Script::new(vec![Cmd::Op(OpCode::OP_1)]);
+        let prevouts = [TxOut { amount: 50000, script_pubkey: p2wsh(&witness_script) }];
+        let mut tx = spending_tx();
+        tx.tx_ins[0].witness = vec![witness_script.raw_serialize()];
+        assert!(tx.verify_input(0, &prevouts).unwrap());
+        tx.tx_ins[0].witness = vec![vec![1], witness_script.raw_serialize()];
+        assert!(!tx.verify_input(0, &prevouts).unwrap());
+    }
+
+    #[test]
+    fn p2wpkh_needs_two_witness_items() {
+        let segwit = Tx::parse(&mut &decode_hex(SEGWIT_TX_HEX).unwrap()[..], false).unwrap();
+        let prevouts = [
+            TxOut { amount: 625000000, script_pubkey: script("2103c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432ac") },
+            TxOut { amount: 600000000, script_pubkey: script("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1") },
+        ];
+        assert!(segwit.verify_input(1, &prevouts).unwrap());
+        let mut extra_item = segwit.clone();
+        extra_item.tx_ins[1].witness.insert(0, vec![0xaa]);
+        assert!(!extra_item.verify_input(1, &prevouts).unwrap());
+        let mut missing_item = segwit;
+        missing_item.tx_ins[1].witness.pop();
+        assert!(!missing_item.verify_input(1, &prevouts).unwrap());
+    }
+
+    #[test]
+    fn verify_p2sh_checklocktimeverify() {
+        let key = PrivateKey::new(U256::from_u64(12345));
+        let redeem_script = Script::new(vec![
+            Cmd::Data(crate::op::encode_num(1000)),
+            Cmd::Op(OpCode::OP_CHECKLOCKTIMEVERIFY),
+            Cmd::Op(OpCode::OP_DROP),
+            Cmd::Data(key.point.sec(true)),
+            Cmd::Op(OpCode::OP_CHECKSIG),
+        ]);
+        let prevouts =
+            [TxOut { amount: 50000, script_pubkey: crate::script::p2sh_script(&hash160(&redeem_script.raw_serialize())) }];
+        let verifies = |locktime, sequence| {
+            let mut tx = spending_tx();
+            tx.locktime = locktime;
+            tx.tx_ins[0].sequence = sequence;
+            let z = tx.sig_hash(0, &prevouts, Some(&redeem_script), SighashType::All).unwrap();
+            let sig = [key.sign(z).der(), vec![SighashType::All.to_byte()]].concat();
+            tx.tx_ins[0].script_sig = Script::new(vec![Cmd::Data(sig), Cmd::Data(redeem_script.raw_serialize())]);
+            tx.verify_input(0, &prevouts).unwrap()
+        };
+        assert!(verifies(1000, 0xfffffffe));
+        assert!(!verifies(999, 0xfffffffe));
+        assert!(!verifies(1000, 0xffffffff));
+    }
+
+    #[test]
+    fn coinbase_small_heights() {
+        let coinbase = |script_sig: Script| {
+            let tx_in = TxIn { prev_tx: [0; 32], prev_index: 0xffffffff, script_sig, ..spending_tx().tx_ins[0].clone() };
+            Tx { tx_ins: vec![tx_in], ..spending_tx() }
+        };
+        // Early regtest heights are pushed as small-number opcodes
+        assert_eq!(coinbase(Script::new(vec![Cmd::Op(OpCode::OP_0)])).coinbase_height(), Some(0));
+        assert_eq!(coinbase(Script::new(vec![Cmd::Op(OpCode::OP_16)])).coinbase_height(), Some(16));
+        assert_eq!(coinbase(Script::new(vec![Cmd::Data(vec![0x11, 0x01])])).coinbase_height(), Some(0x0111));
+        assert_eq!(coinbase(Script::default()).coinbase_height(), None);
+        let two_inputs = Tx { tx_ins: vec![coinbase(Script::default()).tx_ins[0].clone(); 2], ..spending_tx() };
+        assert!(!two_inputs.is_coinbase());
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let parsed = Tx::parse_hex(TX_HEX, false).unwrap();
+        assert_eq!(parsed.to_hex(), TX_HEX);
+        assert_eq!(parsed, tx());
+        assert!(matches!(Tx::parse_hex(&TX_HEX[1..], false), Err(TxError::Hex(_))));
+        assert!(matches!(Tx::parse_hex("zz", false), Err(TxError::Hex(_))));
+        assert!(matches!(Tx::parse_hex(&format!("{}00", TX_HEX), false), Err(TxError::Parse(_))));
+        assert!(matches!(Tx::parse_hex(&TX_HEX[..20], false), Err(TxError::Parse(_))));
+    }
+}
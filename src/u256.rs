@@ -0,0 +1,392 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Rem, Shl, Shr, Sub};
+
+// 256-bit unsigned integer stored as four little-endian u64 limbs
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0, 0, 0, 0]);
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub const fn from_limbs(limbs: [u64; 4]) -> U256 {
+        U256(limbs)
+    }
+
+    pub fn from_u64(n: u64) -> U256 {
+        U256([n, 0, 0, 0])
+    }
+
+    // Big-endian bytes of up to 32 bytes, shorter inputs are zero-extended
+    pub fn from_be_slice(bytes: &[u8]) -> Option<U256> {
+        if bytes.len() > 32 {
+            return None;
+        }
+        let mut padded = [0u8; 32];
+        padded[32 - bytes.len()..].copy_from_slice(bytes);
+        Some(U256::from_be_bytes(padded))
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 32]) -> U256 {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let start = 32 - (i + 1) * 8;
+            let mut chunk = [0u8; 8];
+            chunk.copy_from_slice(&bytes[start..start + 8]);
+            *limb = u64::from_be_bytes(chunk);
+        }
+        U256(limbs)
+    }
+
+    pub fn from_le_bytes(bytes: [u8; 32]) -> U256 {
+        let mut reversed = bytes;
+        reversed.reverse();
+        U256::from_be_bytes(reversed)
+    }
+
+    // A hash256 digest as a number, the way block hashes are compared
+    // with their target: the digest bytes are little-endian
+    pub fn from_hash(hash: [u8; 32]) -> U256 {
+        U256::from_le_bytes(hash)
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            let start = 32 - (i + 1) * 8;
+            bytes[start..start + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        let mut bytes = self.to_be_bytes();
+        bytes.reverse();
+        bytes
+    }
+
+    pub fn less_than(&self, other: &U256) -> bool {
+        self < other
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&limb| limb == 0)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.0.iter().rev().fold(0.0, |acc, &limb| acc * 18446744073709551616.0 + limb as f64)
+    }
+
+    pub fn overflowing_add(self, other: U256) -> (U256, bool) {
+        let mut limbs = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c1 || c2;
+        }
+        (U256(limbs), carry)
+    }
+
+    pub fn overflowing_sub(self, other: U256) -> (U256, bool) {
+        let mut limbs = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = b1 || b2;
+        }
+        (U256(limbs), borrow)
+    }
+
+    // Full 512-bit product as (low, high) halves
+    pub fn full_mul(self, other: U256) -> (U256, U256) {
+        let mut limbs = [0u64; 8];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let product = self.0[i] as u128 * other.0[j] as u128 + limbs[i + j] as u128 + carry;
+                limbs[i + j] = product as u64;
+                carry = product >> 64;
+            }
+            limbs[i + 4] = carry as u64;
+        }
+        (U256([limbs[0], limbs[1], limbs[2], limbs[3]]), U256([limbs[4], limbs[5], limbs[6], limbs[7]]))
+    }
+
+    // Product with a u64 as (low 256 bits, overflow limb)
+    pub fn mul_u64(self, n: u64) -> (U256, u64) {
+        let mut limbs = [0u64; 4];
+        let mut carry = 0u128;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let product = self.0[i] as u128 * n as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        (U256(limbs), carry as u64)
+    }
+
+    pub fn checked_mul(self, other: U256) -> Option<U256> {
+        let (low, high) = self.full_mul(other);
+        if high.is_zero() {
+            Some(low)
+        } else {
+            None
+        }
+    }
+
+    // Schoolbook binary long division, returns (quotient, remainder)
+    pub fn div_rem(self, divisor: U256) -> (U256, U256) {
+        if divisor.is_zero() {
+            panic!("attempt to divide by zero");
+        }
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for i in (0..self.bits()).rev() {
+            let overflow = remainder.bit(255);
+            remainder = remainder << 1;
+            if self.bit(i) {
+                remainder.0[0] |= 1;
+            }
+            if overflow || remainder >= divisor {
+                remainder = remainder.overflowing_sub(divisor).0;
+                quotient.0[(i / 64) as usize] |= 1 << (i % 64);
+            }
+        }
+        (quotient, remainder)
+    }
+
+    // (high * 2^256 + low) mod m, one bit at a time
+    fn reduce_wide(low: U256, high: U256, m: U256) -> U256 {
+        let mut remainder = high % m;
+        for i in (0..256).rev() {
+            let overflow = remainder.bit(255);
+            remainder = remainder << 1;
+            if low.bit(i) {
+                remainder.0[0] |= 1;
+            }
+            if overflow || remainder >= m {
+                remainder = remainder.overflowing_sub(m).0;
+            }
+        }
+        remainder
+    }
+
+    pub fn add_mod(self, other: U256, m: U256) -> U256 {
+        let (a, b) = (self % m, other % m);
+        let (sum, overflow) = a.overflowing_add(b);
+        if overflow || sum >= m {
+            sum.overflowing_sub(m).0
+        } else {
+            sum
+        }
+    }
+
+    pub fn sub_mod(self, other: U256, m: U256) -> U256 {
+        let (a, b) = (self % m, other % m);
+        if a >= b {
+            a - b
+        } else {
+            m - (b - a)
+        }
+    }
+
+    pub fn mul_mod(self, other: U256, m: U256) -> U256 {
+        let (low, high) = self.full_mul(other);
+        U256::reduce_wide(low, high, m)
+    }
+
+    pub fn pow_mod(self, exponent: U256, m: U256) -> U256 {
+        let mut result = U256::ONE % m;
+        let mut base = self % m;
+        for i in 0..exponent.bits() {
+            if exponent.bit(i) {
+                result = result.mul_mod(base, m);
+            }
+            base = base.mul_mod(base, m);
+        }
+        result
+    }
+
+    // Inverse by Fermat's little theorem, so m must be prime
+    pub fn inv_mod(self, m: U256) -> U256 {
+        self.pow_mod(m - U256::from_u64(2), m)
+    }
+
+    pub fn bit(&self, index: u32) -> bool {
+        index < 256 && (self.0[(index / 64) as usize] >> (index % 64)) & 1 == 1
+    }
+
+    // Number of significant bits, 0 for zero
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + (64 - self.0[i].leading_zeros());
+            }
+        }
+        0
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &U256) -> Ordering {
+        for i in (0..4).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &U256) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for U256 {
+    type Output = U256;
+
+    fn add(self, other: U256) -> U256 {
+        let (sum, overflow) = self.overflowing_add(other);
+        if overflow {
+            panic!("attempt to add with overflow");
+        }
+        sum
+    }
+}
+
+impl Sub for U256 {
+    type Output = U256;
+
+    fn sub(self, other: U256) -> U256 {
+        let (diff, overflow) = self.overflowing_sub(other);
+        if overflow {
+            panic!("attempt to subtract with overflow");
+        }
+        diff
+    }
+}
+
+impl Mul for U256 {
+    type Output = U256;
+
+    fn mul(self, other: U256) -> U256 {
+        self.checked_mul(other).expect("attempt to multiply with overflow")
+    }
+}
+
+impl Div for U256 {
+    type Output = U256;
+
+    fn div(self, other: U256) -> U256 {
+        self.div_rem(other).0
+    }
+}
+
+impl Rem for U256 {
+    type Output = U256;
+
+    fn rem(self, other: U256) -> U256 {
+        self.div_rem(other).1
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(n: u64) -> U256 {
+        U256::from_u64(n)
+    }
+}
+
+// Bits shifted past the top are discarded
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> U256 {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+        let limb_shift = (shift / 64) as usize;
+        let bit_shift = shift % 64;
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate().skip(limb_shift) {
+            *limb = self.0[i - limb_shift] << bit_shift;
+            if bit_shift > 0 && i > limb_shift {
+                *limb |= self.0[i - limb_shift - 1] >> (64 - bit_shift);
+            }
+        }
+        U256(limbs)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> U256 {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+        let limb_shift = (shift / 64) as usize;
+        let bit_shift = shift % 64;
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate().take(4 - limb_shift) {
+            *limb = self.0[i + limb_shift] >> bit_shift;
+            if bit_shift > 0 && i + limb_shift + 1 < 4 {
+                *limb |= self.0[i + limb_shift + 1] << (64 - bit_shift);
+            }
+        }
+        U256(limbs)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for U256 {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::LowerHex for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.to_be_bytes() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "U256(0x{:x})", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts() {
+        let n = U256::from_u64(0x1234) << 200;
+        assert_eq!(n >> 200, U256::from_u64(0x1234));
+        assert_eq!(U256::MAX >> 255, U256::ONE);
+        assert_eq!((U256::ONE << 64) >> 1, U256::from_u64(1 << 63));
+        assert_eq!(U256::ONE << 256, U256::ZERO);
+    }
+
+    #[test]
+    fn div_rem() {
+        let n = U256::from_u64(123456789) << 100;
+        let (quotient, remainder) = n.div_rem(U256::from_u64(1000));
+        assert_eq!(quotient * U256::from_u64(1000) + remainder, n);
+        assert!(remainder < U256::from_u64(1000));
+        assert_eq!(U256::MAX / U256::MAX, U256::ONE);
+        assert_eq!(U256::from_u64(7) % U256::from_u64(3), U256::ONE);
+    }
+}
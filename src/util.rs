@@ -0,0 +1,449 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::field::{FieldElement, FieldElementError};
+use crate::hashes::hash256;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+pub fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut prefix = [0u8; 1];
+    reader.read_exact(&mut prefix)?;
+    match prefix[0] {
+        0xfd => {
+            let mut buf = [0u8; 2];
+            reader.read_exact(&mut buf)?;
+            Ok(u16::from_le_bytes(buf) as u64)
+        }
+        0xfe => {
+            let mut buf = [0u8; 4];
+            reader.read_exact(&mut buf)?;
+            Ok(u32::from_le_bytes(buf) as u64)
+        }
+        0xff => {
+            let mut buf = [0u8; 8];
+            reader.read_exact(&mut buf)?;
+            Ok(u64::from_le_bytes(buf))
+        }
+        n => Ok(n as u64),
+    }
+}
+
+pub fn encode_varint(n: u64) -> Vec<u8> {
+    if n < 0xfd {
+        vec![n as u8]
+    } else if n <= 0xffff {
+        let mut result = vec![0xfd];
+        result.extend_from_slice(&(n as u16).to_le_bytes());
+        result
+    } else if n <= 0xffff_ffff {
+        let mut result = vec![0xfe];
+        result.extend_from_slice(&(n as u32).to_le_bytes());
+        result
+    } else {
+        let mut result = vec![0xff];
+        result.extend_from_slice(&n.to_le_bytes());
+        result
+    }
+}
+
+// Reads exactly `len` bytes without trusting `len` for the allocation,
+// so a corrupt length prefix fails with UnexpectedEof instead of aborting
+pub fn read_bytes<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(buf)
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Packs a list of 0/1 bits into bytes, least significant bit first
+pub fn bit_field_to_bytes(bit_field: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; bit_field.len().div_ceil(8)];
+    for (i, bit) in bit_field.iter().enumerate() {
+        if *bit != 0 {
+            result[i / 8] |= 1 << (i % 8);
+        }
+    }
+    result
+}
+
+// Unpacks bytes into a list of 0/1 bits, least significant bit first
+pub fn bytes_to_bit_field(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1)).collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HexError {
+    OddLength,
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexError::OddLength => write!(f, "Hex string has an odd number of digits"),
+            HexError::InvalidCharacter(c) => write!(f, "Invalid hex character {:?}", c),
+        }
+    }
+}
+
+pub fn decode_hex(s: &str) -> Result<Vec<u8>, HexError> {
+    let digits = s
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8).ok_or(HexError::InvalidCharacter(c)))
+        .collect::<Result<Vec<u8>, HexError>>()?;
+    if digits.len() % 2 != 0 {
+        return Err(HexError::OddLength);
+    }
+    Ok(digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Base58Error {
+    InvalidCharacter(char),
+    BadChecksum,
+}
+
+impl fmt::Display for Base58Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Base58Error::InvalidCharacter(c) => write!(f, "Invalid base58 character {:?}", c),
+            Base58Error::BadChecksum => write!(f, "Base58 checksum does not match"),
+        }
+    }
+}
+
+// Each leading zero byte becomes a leading '1'
+pub fn encode_base58(data: &[u8]) -> String {
+    let zeros = data.iter().take_while(|&&byte| byte == 0).count();
+    // Base-58 digits, least significant first
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in &data[zeros..] {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let mut result = "1".repeat(zeros);
+    result.extend(digits.iter().rev().map(|&digit| BASE58_ALPHABET[digit as usize] as char));
+    result
+}
+
+// Appends the first four bytes of hash256 before encoding
+pub fn encode_base58_checksum(data: &[u8]) -> String {
+    let mut payload = data.to_vec();
+    payload.extend_from_slice(&hash256(data)[..4]);
+    encode_base58(&payload)
+}
+
+pub fn h160_to_p2pkh_address(h160: &[u8; 20], testnet: bool) -> String {
+    let mut payload = vec![if testnet { 0x6f } else { 0x00 }];
+    payload.extend_from_slice(h160);
+    encode_base58_checksum(&payload)
+}
+
+pub fn h160_to_p2sh_address(h160: &[u8; 20], testnet: bool) -> String {
+    let mut payload = vec![if testnet { 0xc4 } else { 0x05 }];
+    payload.extend_from_slice(h160);
+    encode_base58_checksum(&payload)
+}
+
+pub fn decode_base58(s: &str) -> Result<Vec<u8>, Base58Error> {
+    let zeros = s.chars().take_while(|&c| c == '1').count();
+    // Bytes, least significant first
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.chars().skip(zeros) {
+        let value = BASE58_ALPHABET
+            .iter()
+            .position(|&symbol| symbol as char == c)
+            .ok_or(Base58Error::InvalidCharacter(c))?;
+        let mut carry = value as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let mut result = vec![0u8; zeros];
+    result.extend(bytes.iter().rev());
+    Ok(result)
+}
+
+// Checks and strips the four-byte checksum
+pub fn decode_base58_checksum(s: &str) -> Result<Vec<u8>, Base58Error> {
+    let raw = decode_base58(s)?;
+    if raw.len() < 4 {
+        return Err(Base58Error::BadChecksum);
+    }
+    let (payload, checksum) = raw.split_at(raw.len() - 4);
+    if hash256(payload)[..4] != *checksum {
+        return Err(Base58Error::BadChecksum);
+    }
+    Ok(payload.to_vec())
+}
+
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Base64Error {
+    InvalidCharacter(char),
+    InvalidLength,
+}
+
+impl fmt::Display for Base64Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Base64Error::InvalidCharacter(c) => write!(f, "Invalid base64 character {:?}", c),
+            Base64Error::InvalidLength => write!(f, "Base64 length is not a multiple of four"),
+        }
+    }
+}
+
+// Standard alphabet with '=' padding
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut result = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+pub fn decode_base64(s: &str) -> Result<Vec<u8>, Base64Error> {
+    let chars: Vec<char> = s.chars().collect();
+    if !chars.len().is_multiple_of(4) {
+        return Err(Base64Error::InvalidLength);
+    }
+    let mut result = Vec::new();
+    for (index, group) in chars.chunks(4).enumerate() {
+        let last = index == chars.len() / 4 - 1;
+        let padding = group.iter().rev().take_while(|&&c| c == '=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return Err(Base64Error::InvalidCharacter('='));
+        }
+        let mut value = 0u32;
+        for &c in &group[..4 - padding] {
+            let digit = BASE64_ALPHABET.iter().position(|&a| a as char == c).ok_or(Base64Error::InvalidCharacter(c))?;
+            value = value << 6 | digit as u32;
+        }
+        value <<= 6 * padding;
+        result.extend_from_slice(&value.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(result)
+}
+
+// Always non-negative, and gcd(0, 0) is 0. Unsigned because
+// gcd(i64::MIN, 0) is 2^63.
+pub fn gcd(a: i64, b: i64) -> u64 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+// (g, x, y) with a*x + b*y = g = gcd(a, b), so when g is 1, x is the
+// inverse of a modulo b. Worked in i128 so i64::MIN cannot overflow; the
+// coefficients never outgrow the inputs, so they fit back in i64.
+pub fn ext_gcd(a: i64, b: i64) -> (u64, i64, i64) {
+    let (mut old_r, mut r) = (a as i128, b as i128);
+    let (mut old_x, mut x) = (1i128, 0i128);
+    let (mut old_y, mut y) = (0i128, 1i128);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_x, x) = (x, old_x - q * x);
+        (old_y, y) = (y, old_y - q * y);
+    }
+    if old_r < 0 {
+        (old_r, old_x, old_y) = (-old_r, -old_x, -old_y);
+    }
+    (old_r as u64, old_x as i64, old_y as i64)
+}
+
+// The x below the product of the moduli that is congruent to every residue,
+// and that product. Each residue is folded in by solving
+// x + m*t = r (mod p) for t, which needs m invertible mod p.
+pub fn crt(residues: &[FieldElement]) -> Result<(i64, i64), FieldElementError> {
+    let mut x = 0i64;
+    let mut modulus = 1i64;
+    for residue in residues {
+        let prime = residue.prime();
+        let (g, inverse, _) = ext_gcd(modulus % prime, prime);
+        if g != 1 {
+            return Err(FieldElementError::NotCoprime(modulus, prime));
+        }
+        let t = ((residue.num() as i128 - x as i128) * inverse as i128).rem_euclid(prime as i128);
+        let next_modulus = modulus.checked_mul(prime).ok_or(FieldElementError::Overflow)?;
+        x = (x as i128 + modulus as i128 * t) as i64;
+        modulus = next_modulus;
+    }
+    Ok((x, modulus))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(decode_hex("00ff1a").unwrap(), vec![0x00, 0xff, 0x1a]);
+        assert_eq!(encode_hex(&[0x00, 0xff, 0x1a]), "00ff1a");
+        assert_eq!(decode_hex("0a1"), Err(HexError::OddLength));
+        assert_eq!(decode_hex("zz"), Err(HexError::InvalidCharacter('z')));
+    }
+
+    #[test]
+    fn base58_round_trip() {
+        assert_eq!(encode_base58(&[0, 0, 1, 2, 3]), "11Ldp");
+        assert_eq!(decode_base58("11Ldp").unwrap(), vec![0, 0, 1, 2, 3]);
+        let decoded = decode_base58_checksum("mzx5YhAH9kNHtcN481u6WkjeHjYtVeKVh2").unwrap();
+        assert_eq!(encode_hex(&decoded), "6fd52ad7ca9b3d096a38e752c2018e6fbc40cdf26f");
+        assert_eq!(encode_base58_checksum(&decoded), "mzx5YhAH9kNHtcN481u6WkjeHjYtVeKVh2");
+        assert_eq!(decode_base58_checksum("mzx5YhAH9kNHtcN481u6WkjeHjYtVeKVh3"), Err(Base58Error::BadChecksum));
+        assert_eq!(decode_base58("0OIl"), Err(Base58Error::InvalidCharacter('0')));
+    }
+
+    #[test]
+    fn base64_vectors() {
+        // RFC 4648 section 10
+        for (data, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (b"fooba", "Zm9vYmE="),
+            (b"foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode_base64(data), encoded);
+            assert_eq!(decode_base64(encoded).unwrap(), data);
+        }
+        for bad in ["Zg=", "Z===", "Zg==Zg==", "Zm9*"] {
+            assert!(decode_base64(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn gcd_and_ext_gcd() {
+        let cases = [
+            (12, 18, 6),
+            (17, 5, 1),
+            (0, 7, 7),
+            (7, 0, 7),
+            (0, 0, 0),
+            (-12, 18, 6),
+            (12, -18, 6),
+            (-4, -6, 2),
+            (240, 46, 2),
+            (1 << 40, 3 << 20, 1 << 20),
+        ];
+        for (a, b, g) in cases {
+            assert_eq!(gcd(a, b), g);
+            let (ext_g, x, y) = ext_gcd(a, b);
+            assert_eq!(ext_g, g);
+            assert_eq!(a * x + b * y, g as i64);
+        }
+        let (g, x, _) = ext_gcd(3, 223);
+        assert_eq!(g, 1);
+        assert_eq!((3 * x).rem_euclid(223), 1);
+    }
+
+    #[test]
+    fn gcd_of_i64_min() {
+        assert_eq!(gcd(i64::MIN, 0), 1 << 63);
+        assert_eq!(gcd(i64::MIN, i64::MIN), 1 << 63);
+        assert_eq!(gcd(i64::MIN, -1), 1);
+        assert_eq!(gcd(i64::MIN, 6), 2);
+        let cases = [(i64::MIN, 0), (0, i64::MIN), (i64::MIN, i64::MIN), (i64::MIN, -1), (1, i64::MIN), (i64::MIN, i64::MAX)];
+        for (a, b) in cases {
+            let (g, x, y) = ext_gcd(a, b);
+            assert_eq!(g, gcd(a, b));
+            assert_eq!(a as i128 * x as i128 + b as i128 * y as i128, g as i128);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn ext_gcd_identity(a in any::<i64>(), b in any::<i64>()) {
+            let (g, x, y) = ext_gcd(a, b);
+            prop_assert_eq!(g, gcd(a, b));
+            prop_assert_eq!(a as i128 * x as i128 + b as i128 * y as i128, g as i128);
+        }
+
+        #[test]
+        fn bit_field_round_trip(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let bit_field = bytes_to_bit_field(&bytes);
+            prop_assert_eq!(bit_field.len(), bytes.len() * 8);
+            prop_assert_eq!(bit_field_to_bytes(&bit_field), bytes);
+        }
+    }
+
+    #[test]
+    fn bit_field_vectors() {
+        // The bloom filter from chapter 12 after adding "Hello World" and "Goodbye!"
+        let mut bit_field = vec![0u8; 80];
+        for i in [6, 21, 22, 25, 27, 35, 56, 64, 67, 78] {
+            bit_field[i] = 1;
+        }
+        assert_eq!(encode_hex(&bit_field_to_bytes(&bit_field)), "4000600a080000010940");
+        assert_eq!(bytes_to_bit_field(&decode_hex("4000600a080000010940").unwrap()), bit_field);
+        // Least significant bit first, with a partial last byte zero-padded
+        assert_eq!(bit_field_to_bytes(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 1]), vec![0x01, 0x02]);
+        assert_eq!(bytes_to_bit_field(&[0x80]), vec![0, 0, 0, 0, 0, 0, 0, 1]);
+        assert!(bit_field_to_bytes(&[]).is_empty());
+    }
+
+    #[test]
+    fn crt_solutions() {
+        let residue = |num, prime| FieldElement::new(num, prime).unwrap();
+        for x in 0..105 {
+            assert_eq!(crt(&[residue(x % 3, 3), residue(x % 5, 5), residue(x % 7, 7)]).unwrap(), (x, 105));
+        }
+        assert_eq!(crt(&[]).unwrap(), (0, 1));
+        assert!(matches!(crt(&[residue(1, 4), residue(1, 6)]), Err(FieldElementError::NotCoprime(4, 6))));
+        let large = (1 << 61) - 1;
+        assert!(matches!(crt(&[residue(1, large), residue(1, 7), residue(0, 11)]), Err(FieldElementError::Overflow)));
+        // A modulus just below 2^63, where the steps need i128
+        let (x, modulus) = crt(&[residue(large - 2, large), residue(3, 4)]).unwrap();
+        assert_eq!(modulus, large * 4);
+        assert_eq!(x % large, large - 2);
+        assert_eq!(x % 4, 3);
+    }
+}